@@ -0,0 +1,610 @@
+// Runs one stake instruction against the p-stake ELF in mollusk, to reproduce a
+// transaction offline. Accounts are read from the JSON files written by
+// `solana account --output json <ADDRESS>` and sysvars default to the mollusk
+// ones, with the given fields overridden. The program must be built first, with
+// `make build` so that its logs are printed.
+//
+//   cargo run --features std --example simulate -- \
+//       (--instruction <INSTRUCTION> | --data <BASE64>) [--account <ACCOUNT>[:<FLAGS>]]... \
+//       [--clock <JSON>] [--rent <JSON>] [--epoch-rewards <JSON>] \
+//       [--stake-history <FILE>] [--program <ELF>]
+//
+// Instruction accounts are given in order. An account is a JSON file, a sysvar
+// (`clock`, `rent`, `stake-history`, `epoch-rewards` or `epoch-schedule`) or a
+// base58 pubkey for an empty system account, followed by `:s` if it signs, `:w`
+// if it is writable or `:sw`. The stake history file holds the data of the
+// sysvar account or its JSON account file, and the other sysvars take a JSON
+// object of the fields to override, e.g. `--clock '{"epoch": 700}'`.
+//
+// Instructions are written as:
+//
+//   initialize <STAKER> <WITHDRAWER> [<UNIX_TIMESTAMP> <EPOCH> <CUSTODIAN>]
+//   authorize <NEW_AUTHORITY> staker|withdrawer
+//   delegate
+//   split <LAMPORTS>
+//   withdraw <LAMPORTS>
+//   deactivate
+//   set-lockup [unix_timestamp=<UNIX_TIMESTAMP>] [epoch=<EPOCH>] [custodian=<CUSTODIAN>]
+//   merge
+//   authorize-with-seed <NEW_AUTHORITY> staker|withdrawer <SEED> <OWNER>
+//   initialize-checked
+//   authorize-checked staker|withdrawer
+//   authorize-checked-with-seed staker|withdrawer <SEED> <OWNER>
+//   set-lockup-checked [unix_timestamp=<UNIX_TIMESTAMP>] [epoch=<EPOCH>]
+//   get-minimum-delegation
+//   deactivate-delinquent
+//   move-stake <LAMPORTS>
+//   move-lamports <LAMPORTS>
+//
+// Instructions of the p-stake extensions are given as base64 data.
+
+use {
+    base64::{engine::general_purpose::STANDARD, Engine},
+    mollusk_svm::Mollusk,
+    p_stake::id as p_stake_id,
+    serde::{de::DeserializeOwned, Deserialize, Serialize},
+    solana_account::Account,
+    solana_sdk::{
+        instruction::{AccountMeta, Instruction, InstructionError},
+        program_error::ProgramError,
+        pubkey::Pubkey,
+    },
+    solana_stake_interface::{
+        error::StakeError,
+        instruction::{
+            AuthorizeCheckedWithSeedArgs, AuthorizeWithSeedArgs, LockupArgs, LockupCheckedArgs,
+            StakeInstruction,
+        },
+        state::{Authorized, Lockup, StakeAuthorize, StakeStateV2},
+    },
+    std::{env, fs, ops::Range, path::Path, process::ExitCode, str::FromStr},
+};
+
+const USAGE: &str = "usage: simulate (--instruction <INSTRUCTION> | --data <BASE64>) \
+                     [--account <ACCOUNT>[:<FLAGS>]]... [--clock <JSON>] [--rent <JSON>] \
+                     [--epoch-rewards <JSON>] [--stake-history <FILE>] [--program <ELF>]";
+
+// p_stake::state::WITHDRAW_DESTINATION_NOT_ALLOWED, only defined with the
+// withdraw-allowlist feature
+const WITHDRAW_DESTINATION_NOT_ALLOWED: u32 = 100;
+
+const STAKE_ERRORS: [StakeError; 17] = [
+    StakeError::NoCreditsToRedeem,
+    StakeError::LockupInForce,
+    StakeError::AlreadyDeactivated,
+    StakeError::TooSoonToRedelegate,
+    StakeError::InsufficientStake,
+    StakeError::MergeTransientStake,
+    StakeError::MergeMismatch,
+    StakeError::CustodianMissing,
+    StakeError::CustodianSignatureMissing,
+    StakeError::InsufficientReferenceVotes,
+    StakeError::VoteAddressMismatch,
+    StakeError::MinimumDelinquentEpochsForDeactivationNotMet,
+    StakeError::InsufficientDelegation,
+    StakeError::RedelegateTransientOrInactiveStake,
+    StakeError::RedelegateToSameVoteAccount,
+    StakeError::RedelegatedStakeMustFullyActivateBeforeDeactivationIsPermitted,
+    StakeError::EpochRewardsActive,
+];
+
+fn id() -> Pubkey {
+    p_stake_id().into()
+}
+
+struct Args {
+    data: Vec<u8>,
+    accounts: Vec<String>,
+    clock: Option<String>,
+    rent: Option<String>,
+    epoch_rewards: Option<String>,
+    stake_history: Option<String>,
+    program: String,
+}
+
+// the account files written by `solana account --output json`
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccountFile {
+    pubkey: String,
+    account: UiAccount,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UiAccount {
+    lamports: u64,
+    /// data and its encoding
+    data: (String, String),
+    owner: String,
+    executable: bool,
+    rent_epoch: u64,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut data = None;
+    let mut accounts = vec![];
+    let mut clock = None;
+    let mut rent = None;
+    let mut epoch_rewards = None;
+    let mut stake_history = None;
+    let mut program = "target/deploy/p_stake".to_string();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("missing value of {name}"));
+        match arg.as_str() {
+            "--instruction" if data.is_none() => {
+                let instruction = parse_instruction(&value(&arg)?)?;
+                data = Some(bincode::serialize(&instruction).unwrap());
+            }
+            "--data" if data.is_none() => {
+                data = Some(
+                    STANDARD
+                        .decode(value(&arg)?.trim())
+                        .map_err(|e| format!("--data: {e}"))?,
+                )
+            }
+            "--account" => accounts.push(value(&arg)?),
+            "--clock" => clock = Some(value(&arg)?),
+            "--rent" => rent = Some(value(&arg)?),
+            "--epoch-rewards" => epoch_rewards = Some(value(&arg)?),
+            "--stake-history" => stake_history = Some(value(&arg)?),
+            "--program" => program = value(&arg)?,
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
+    }
+
+    Ok(Args {
+        data: data.ok_or("missing --instruction or --data")?,
+        accounts,
+        clock,
+        rent,
+        epoch_rewards,
+        stake_history,
+        program,
+    })
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value.parse().map_err(|e| format!("{name}: {e}"))
+}
+
+fn stake_authorize(value: &str) -> Result<StakeAuthorize, String> {
+    match value {
+        "staker" => Ok(StakeAuthorize::Staker),
+        "withdrawer" => Ok(StakeAuthorize::Withdrawer),
+        _ => Err(format!("invalid authority type {value}")),
+    }
+}
+
+// fields of the set-lockup instructions, as `name=value` pairs
+fn lockup_fields(name: &str, fields: &[&str]) -> Result<LockupArgs, String> {
+    let (mut unix_timestamp, mut epoch, mut custodian) = (None, None, None);
+    for field in fields {
+        match field.split_once('=') {
+            Some(("unix_timestamp", value)) => unix_timestamp = Some(parse(name, value)?),
+            Some(("epoch", value)) => epoch = Some(parse(name, value)?),
+            Some(("custodian", value)) => custodian = Some(parse(name, value)?),
+            _ => return Err(format!("{name}: invalid field {field}")),
+        }
+    }
+    Ok(LockupArgs {
+        unix_timestamp,
+        epoch,
+        custodian,
+    })
+}
+
+fn parse_instruction(text: &str) -> Result<StakeInstruction, String> {
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    let Some((&name, args)) = words.split_first() else {
+        return Err("empty instruction".into());
+    };
+
+    let instruction = match (name, args) {
+        ("initialize", [staker, withdrawer, lockup @ ..]) => {
+            let lockup = match lockup {
+                [] => Lockup::default(),
+                [unix_timestamp, epoch, custodian] => Lockup {
+                    unix_timestamp: parse(name, unix_timestamp)?,
+                    epoch: parse(name, epoch)?,
+                    custodian: parse(name, custodian)?,
+                },
+                _ => return Err(format!("invalid instruction {text}")),
+            };
+            StakeInstruction::Initialize(
+                Authorized {
+                    staker: parse(name, staker)?,
+                    withdrawer: parse(name, withdrawer)?,
+                },
+                lockup,
+            )
+        }
+        ("authorize", [new_authority, authority_type]) => StakeInstruction::Authorize(
+            parse(name, new_authority)?,
+            stake_authorize(authority_type)?,
+        ),
+        ("delegate", []) => StakeInstruction::DelegateStake,
+        ("split", [lamports]) => StakeInstruction::Split(parse(name, lamports)?),
+        ("withdraw", [lamports]) => StakeInstruction::Withdraw(parse(name, lamports)?),
+        ("deactivate", []) => StakeInstruction::Deactivate,
+        ("set-lockup", fields) => StakeInstruction::SetLockup(lockup_fields(name, fields)?),
+        ("merge", []) => StakeInstruction::Merge,
+        ("authorize-with-seed", [new_authority, authority_type, seed, owner]) => {
+            StakeInstruction::AuthorizeWithSeed(AuthorizeWithSeedArgs {
+                new_authorized_pubkey: parse(name, new_authority)?,
+                stake_authorize: stake_authorize(authority_type)?,
+                authority_seed: seed.to_string(),
+                authority_owner: parse(name, owner)?,
+            })
+        }
+        ("initialize-checked", []) => StakeInstruction::InitializeChecked,
+        ("authorize-checked", [authority_type]) => {
+            StakeInstruction::AuthorizeChecked(stake_authorize(authority_type)?)
+        }
+        ("authorize-checked-with-seed", [authority_type, seed, owner]) => {
+            StakeInstruction::AuthorizeCheckedWithSeed(AuthorizeCheckedWithSeedArgs {
+                stake_authorize: stake_authorize(authority_type)?,
+                authority_seed: seed.to_string(),
+                authority_owner: parse(name, owner)?,
+            })
+        }
+        ("set-lockup-checked", fields) => {
+            let LockupArgs {
+                unix_timestamp,
+                epoch,
+                custodian,
+            } = lockup_fields(name, fields)?;
+            if custodian.is_some() {
+                return Err(format!(
+                    "{name}: the custodian is given as a signer account"
+                ));
+            }
+            StakeInstruction::SetLockupChecked(LockupCheckedArgs {
+                unix_timestamp,
+                epoch,
+            })
+        }
+        ("get-minimum-delegation", []) => StakeInstruction::GetMinimumDelegation,
+        ("deactivate-delinquent", []) => StakeInstruction::DeactivateDelinquent,
+        ("move-stake", [lamports]) => StakeInstruction::MoveStake(parse(name, lamports)?),
+        ("move-lamports", [lamports]) => StakeInstruction::MoveLamports(parse(name, lamports)?),
+        _ => return Err(format!("invalid instruction {text}")),
+    };
+    Ok(instruction)
+}
+
+// overrides the fields of a sysvar with those of a JSON object
+fn override_fields<T: Serialize + DeserializeOwned>(
+    name: &str,
+    sysvar: &T,
+    overrides: &str,
+) -> Result<T, String> {
+    let mut value = serde_json::to_value(sysvar).map_err(|e| format!("{name}: {e}"))?;
+    let overrides: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(overrides).map_err(|e| format!("{name}: {e}"))?;
+    for (field, field_value) in overrides {
+        match value.get_mut(&field) {
+            Some(value) => *value = field_value,
+            None => return Err(format!("{name}: unknown field {field}")),
+        }
+    }
+    serde_json::from_value(value).map_err(|e| format!("{name}: {e}"))
+}
+
+fn read_account_file(path: &str) -> Result<(Pubkey, Account), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+    let file: AccountFile = serde_json::from_str(&text).map_err(|e| format!("{path}: {e}"))?;
+    let (data, encoding) = &file.account.data;
+    if encoding != "base64" {
+        return Err(format!("{path}: unsupported data encoding {encoding}"));
+    }
+
+    Ok((
+        parse(path, &file.pubkey)?,
+        Account {
+            lamports: file.account.lamports,
+            data: STANDARD.decode(data).map_err(|e| format!("{path}: {e}"))?,
+            owner: parse(path, &file.account.owner)?,
+            executable: file.account.executable,
+            rent_epoch: file.account.rent_epoch,
+        },
+    ))
+}
+
+fn load_sysvars(mollusk: &mut Mollusk, args: &Args) -> Result<(), String> {
+    if let Some(clock) = &args.clock {
+        mollusk.sysvars.clock = override_fields("--clock", &mollusk.sysvars.clock, clock)?;
+    }
+    if let Some(rent) = &args.rent {
+        mollusk.sysvars.rent = override_fields("--rent", &mollusk.sysvars.rent, rent)?;
+    }
+    if let Some(epoch_rewards) = &args.epoch_rewards {
+        mollusk.sysvars.epoch_rewards = override_fields(
+            "--epoch-rewards",
+            &mollusk.sysvars.epoch_rewards,
+            epoch_rewards,
+        )?;
+    }
+    if let Some(path) = &args.stake_history {
+        // either the JSON account file or the raw account data
+        let data = match read_account_file(path) {
+            Ok((_, account)) => account.data,
+            Err(_) => fs::read(path).map_err(|e| format!("{path}: {e}"))?,
+        };
+        mollusk.sysvars.stake_history =
+            bincode::deserialize(&data).map_err(|e| format!("{path}: {e}"))?;
+    }
+    Ok(())
+}
+
+fn resolve_account(mollusk: &Mollusk, account: &str) -> Result<(Pubkey, Account), String> {
+    let sysvars = &mollusk.sysvars;
+    match account {
+        "clock" => Ok(sysvars.keyed_account_for_clock_sysvar()),
+        "rent" => Ok(sysvars.keyed_account_for_rent_sysvar()),
+        "stake-history" => Ok(sysvars.keyed_account_for_stake_history_sysvar()),
+        "epoch-rewards" => Ok(sysvars.keyed_account_for_epoch_rewards_sysvar()),
+        "epoch-schedule" => Ok(sysvars.keyed_account_for_epoch_schedule_sysvar()),
+        _ if Path::new(account).is_file() => read_account_file(account),
+        _ => Ok((
+            Pubkey::from_str(account)
+                .map_err(|_| format!("{account} is neither a file nor a pubkey"))?,
+            Account::default(),
+        )),
+    }
+}
+
+fn error_name(error: &InstructionError) -> String {
+    match error {
+        InstructionError::Custom(WITHDRAW_DESTINATION_NOT_ALLOWED) => {
+            format!("Custom({WITHDRAW_DESTINATION_NOT_ALLOWED}) WithdrawDestinationNotAllowed")
+        }
+        InstructionError::Custom(code) => match STAKE_ERRORS
+            .iter()
+            .find(|error| ProgramError::from((*error).clone()) == ProgramError::Custom(*code))
+        {
+            Some(stake_error) => {
+                format!("Custom({code}) StakeError::{stake_error:?}: {stake_error}")
+            }
+            None => format!("Custom({code})"),
+        },
+        error => format!("{error:?}"),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// ranges of differing bytes over the length both have
+fn changed_ranges(before: &[u8], after: &[u8]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = vec![];
+    for (index, (before, after)) in before.iter().zip(after).enumerate() {
+        if before == after {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(range) if range.end == index => range.end += 1,
+            _ => ranges.push(index..index + 1),
+        }
+    }
+    ranges
+}
+
+fn print_diff(pubkey: &Pubkey, before: &Account, after: &Account) {
+    if before == after {
+        return;
+    }
+    println!("  {pubkey}");
+    if before.lamports != after.lamports {
+        println!("    lamports: {} -> {}", before.lamports, after.lamports);
+    }
+    if before.owner != after.owner {
+        println!("    owner: {} -> {}", before.owner, after.owner);
+    }
+    if before.data == after.data {
+        return;
+    }
+    if before.data.len() != after.data.len() {
+        println!(
+            "    data length: {} -> {}",
+            before.data.len(),
+            after.data.len()
+        );
+    }
+
+    if after.owner == id() {
+        if let (Ok(before), Ok(after)) = (
+            bincode::deserialize::<StakeStateV2>(&before.data),
+            bincode::deserialize::<StakeStateV2>(&after.data),
+        ) {
+            println!("    state: {before:?}");
+            println!("        -> {after:?}");
+        }
+    }
+    for Range { start, end } in changed_ranges(&before.data, &after.data) {
+        println!(
+            "    data[{start}..{end}]: {} -> {}",
+            hex(&before.data[start..end]),
+            hex(&after.data[start..end])
+        );
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let mut mollusk = Mollusk::new(&id(), &args.program);
+    load_sysvars(&mut mollusk, &args)?;
+
+    let mut account_metas = vec![];
+    let mut accounts: Vec<(Pubkey, Account)> = vec![];
+    for arg in &args.accounts {
+        let (account, flags) = match arg.rsplit_once(':') {
+            Some((account, flags))
+                if !flags.is_empty() && flags.chars().all(|flag| flag == 's' || flag == 'w') =>
+            {
+                (account, flags)
+            }
+            _ => (arg.as_str(), ""),
+        };
+        let (pubkey, account) = resolve_account(&mollusk, account)?;
+
+        account_metas.push(AccountMeta {
+            pubkey,
+            is_signer: flags.contains('s'),
+            is_writable: flags.contains('w'),
+        });
+        // an account may be passed more than once
+        if accounts.iter().all(|(key, _)| *key != pubkey) {
+            accounts.push((pubkey, account));
+        }
+    }
+
+    let instruction = Instruction {
+        program_id: id(),
+        accounts: account_metas,
+        data: args.data,
+    };
+    let result = mollusk.process_instruction(&instruction, &accounts);
+
+    match &result.raw_result {
+        Ok(()) => println!("result: success"),
+        Err(error) => println!("result: {}", error_name(error)),
+    }
+    println!("compute units: {}", result.compute_units_consumed);
+    if !result.return_data.is_empty() {
+        println!("return data: {}", hex(&result.return_data));
+    }
+
+    // effects of failed instructions are discarded
+    if result.raw_result.is_ok() {
+        println!("accounts:");
+        for (pubkey, before) in &accounts {
+            if let Some((_, after)) = result
+                .resulting_accounts
+                .iter()
+                .find(|(key, _)| key == pubkey)
+            {
+                print_diff(pubkey, before, after);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let result = parse_args()
+        .map_err(|e| format!("{e}\n{USAGE}"))
+        .and_then(run);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "11111111111111111111111111111112";
+
+    #[test]
+    fn test_parse_instruction() {
+        let key = Pubkey::from_str(KEY).unwrap();
+
+        assert_eq!(
+            parse_instruction(&format!("initialize {KEY} {KEY}")),
+            Ok(StakeInstruction::Initialize(
+                Authorized {
+                    staker: key,
+                    withdrawer: key,
+                },
+                Lockup::default(),
+            ))
+        );
+        assert_eq!(
+            parse_instruction(&format!("initialize {KEY} {KEY} 1 2 {KEY}")),
+            Ok(StakeInstruction::Initialize(
+                Authorized {
+                    staker: key,
+                    withdrawer: key,
+                },
+                Lockup {
+                    unix_timestamp: 1,
+                    epoch: 2,
+                    custodian: key,
+                },
+            ))
+        );
+        assert_eq!(
+            parse_instruction(&format!("authorize {KEY} withdrawer")),
+            Ok(StakeInstruction::Authorize(key, StakeAuthorize::Withdrawer))
+        );
+        assert_eq!(
+            parse_instruction("  split   42 "),
+            Ok(StakeInstruction::Split(42))
+        );
+        assert_eq!(
+            parse_instruction("set-lockup epoch=5"),
+            Ok(StakeInstruction::SetLockup(LockupArgs {
+                unix_timestamp: None,
+                epoch: Some(5),
+                custodian: None,
+            }))
+        );
+        assert_eq!(
+            parse_instruction("set-lockup-checked unix_timestamp=-1 epoch=5"),
+            Ok(StakeInstruction::SetLockupChecked(LockupCheckedArgs {
+                unix_timestamp: Some(-1),
+                epoch: Some(5),
+            }))
+        );
+        assert_eq!(
+            parse_instruction(&format!("authorize-checked-with-seed staker seed {KEY}")),
+            Ok(StakeInstruction::AuthorizeCheckedWithSeed(
+                AuthorizeCheckedWithSeedArgs {
+                    stake_authorize: StakeAuthorize::Staker,
+                    authority_seed: "seed".into(),
+                    authority_owner: key,
+                }
+            ))
+        );
+        assert_eq!(
+            parse_instruction("move-lamports 7"),
+            Ok(StakeInstruction::MoveLamports(7))
+        );
+
+        assert!(parse_instruction("").is_err());
+        assert!(parse_instruction("split").is_err());
+        assert!(parse_instruction("split ten").is_err());
+        assert!(parse_instruction("deactivate now").is_err());
+        assert!(parse_instruction(&format!("initialize {KEY} {KEY} 1")).is_err());
+        assert!(parse_instruction(&format!("authorize {KEY} voter")).is_err());
+        assert!(parse_instruction("set-lockup era=5").is_err());
+        assert!(parse_instruction(&format!("set-lockup-checked custodian={KEY}")).is_err());
+        assert!(parse_instruction("redelegate").is_err());
+    }
+
+    #[test]
+    fn test_changed_ranges() {
+        assert!(changed_ranges(&[], &[]).is_empty());
+        assert!(changed_ranges(&[1, 2, 3], &[1, 2, 3]).is_empty());
+        assert_eq!(changed_ranges(&[1, 2, 3], &[0, 2, 3]), vec![0..1]);
+        assert_eq!(
+            changed_ranges(&[1, 2, 3, 4, 5], &[1, 0, 0, 4, 0]),
+            vec![1..3, 4..5]
+        );
+        assert_eq!(changed_ranges(&[1, 2, 3], &[0, 0, 0]), vec![0..3]);
+
+        // only the length both have is compared
+        assert_eq!(changed_ranges(&[1, 2], &[1, 0, 3, 4]), vec![1..2]);
+        assert_eq!(changed_ranges(&[1, 2, 3, 4], &[0, 2]), vec![0..1]);
+    }
+}
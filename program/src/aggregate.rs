@@ -0,0 +1,238 @@
+//! Cluster-wide totals of effective, activating and deactivating stake per vote
+//! account, computed off chain from snapshots of stake accounts with the same
+//! activation math the program uses.
+//!
+//! A snapshot is a record of the account pubkey, its lamports as a little-endian
+//! `u64` and the first 200 bytes of its data, i.e. the `StakeStateV2`. Dumps are
+//! concatenations of records, read from a stream or from every file of a
+//! directory.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Read},
+    path::Path,
+    vec::Vec,
+};
+
+use pinocchio::{program_error::ProgramError, pubkey::Pubkey, sysvars::clock::Epoch};
+
+use crate::state::{StakeHistoryEntry, StakeHistoryGetEntry, StakeStateV2};
+
+/// Serialized length of a snapshot
+pub const SNAPSHOT_LEN: usize = 32 + 8 + StakeStateV2::size_of();
+
+/// Serialized length of an epoch and its entry in the stake history sysvar
+const STAKE_HISTORY_ENTRY_LEN: usize = 32;
+
+/// A stake account at some point in time
+#[derive(Debug, Clone, PartialEq)]
+pub struct StakeAccountSnapshot {
+    pub pubkey: Pubkey,
+    pub lamports: u64,
+    pub state: StakeStateV2,
+}
+
+impl StakeAccountSnapshot {
+    /// Decodes one serialized snapshot
+    pub fn from_bytes(bytes: &[u8; SNAPSHOT_LEN]) -> Result<Self, ProgramError> {
+        Ok(Self {
+            pubkey: bytes[..32].try_into().unwrap(),
+            lamports: u64::from_le_bytes(bytes[32..40].try_into().unwrap()),
+            state: StakeStateV2::try_from_slice(&bytes[40..])?,
+        })
+    }
+}
+
+/// Stake delegated to a vote account in the aggregated epoch
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct VoterStake {
+    pub effective: u64,
+    pub activating: u64,
+    pub deactivating: u64,
+    /// number of stake accounts delegated to the vote account
+    pub accounts: u64,
+}
+
+/// Reads every snapshot of a stream
+pub fn read_snapshots(mut reader: impl Read) -> io::Result<Vec<StakeAccountSnapshot>> {
+    let mut snapshots = Vec::new();
+    let mut record = [0; SNAPSHOT_LEN];
+    loop {
+        // a clean end of stream falls on a record boundary
+        match reader.read(&mut record[..1])? {
+            0 => return Ok(snapshots),
+            _ => reader.read_exact(&mut record[1..])?,
+        }
+        let snapshot = StakeAccountSnapshot::from_bytes(&record)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid stake state"))?;
+        snapshots.push(snapshot);
+    }
+}
+
+/// Reads the snapshots of every file of a directory, in file name order
+pub fn read_snapshot_dir(path: &Path) -> io::Result<Vec<StakeAccountSnapshot>> {
+    let mut paths = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.sort();
+
+    let mut snapshots = Vec::new();
+    for path in paths.iter().filter(|path| path.is_file()) {
+        snapshots.extend(read_snapshots(io::BufReader::new(fs::File::open(path)?))?);
+    }
+    Ok(snapshots)
+}
+
+/// Decodes the data of the stake history sysvar account, newest epoch first
+pub fn parse_stake_history(data: &[u8]) -> Result<Vec<(Epoch, StakeHistoryEntry)>, ProgramError> {
+    let (len, entries) = data
+        .split_first_chunk::<8>()
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let len =
+        usize::try_from(u64::from_le_bytes(*len)).map_err(|_| ProgramError::InvalidAccountData)?;
+    let entries = entries
+        .get(..len.saturating_mul(STAKE_HISTORY_ENTRY_LEN))
+        .ok_or(ProgramError::InvalidAccountData)?;
+
+    Ok(entries
+        .chunks_exact(STAKE_HISTORY_ENTRY_LEN)
+        .map(|entry| {
+            let field = |index: usize| {
+                u64::from_le_bytes(entry[index * 8..(index + 1) * 8].try_into().unwrap())
+            };
+            (
+                field(0),
+                StakeHistoryEntry {
+                    effective: field(1),
+                    activating: field(2),
+                    deactivating: field(3),
+                },
+            )
+        })
+        .collect())
+}
+
+/// Sums the status of every delegation in `epoch` per vote account. Accounts
+/// that are not delegated are skipped.
+pub fn aggregate_by_voter<'a, T: StakeHistoryGetEntry + ?Sized>(
+    snapshots: impl IntoIterator<Item = &'a StakeAccountSnapshot>,
+    epoch: Epoch,
+    stake_history: &T,
+    new_rate_activation_epoch: Option<Epoch>,
+) -> BTreeMap<Pubkey, VoterStake> {
+    let mut totals = BTreeMap::<Pubkey, VoterStake>::new();
+    for snapshot in snapshots {
+        let StakeStateV2::Stake(_, stake, _) = &snapshot.state else {
+            continue;
+        };
+        let status = stake.delegation.stake_activating_and_deactivating(
+            epoch,
+            stake_history,
+            new_rate_activation_epoch,
+        );
+
+        let total = totals.entry(stake.delegation.voter_pubkey).or_default();
+        total.effective = total.effective.saturating_add(status.effective);
+        total.activating = total.activating.saturating_add(status.activating);
+        total.deactivating = total.deactivating.saturating_add(status.deactivating);
+        total.accounts = total.accounts.saturating_add(1);
+    }
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::{Authorized, Delegation, Lockup, Meta, Stake, StakeFlags};
+
+    use super::*;
+
+    fn snapshot(pubkey: Pubkey, state: StakeStateV2) -> Vec<u8> {
+        // zeroed, so that padding is initialized, and aligned like the state
+        let mut buffer = [0u32; StakeStateV2::size_of() / 4];
+        let data = unsafe {
+            *(buffer.as_mut_ptr() as *mut StakeStateV2) = state;
+            core::slice::from_raw_parts(buffer.as_ptr() as *const u8, StakeStateV2::size_of())
+        };
+
+        let mut bytes = Vec::with_capacity(SNAPSHOT_LEN);
+        bytes.extend_from_slice(&pubkey);
+        bytes.extend_from_slice(&1_000u64.to_le_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn delegated(voter: Pubkey, amount: u64, activation_epoch: u64) -> StakeStateV2 {
+        StakeStateV2::Stake(
+            Meta {
+                rent_exempt_reserve: 0.into(),
+                authorized: Authorized::default(),
+                lockup: Lockup::default(),
+            },
+            Stake {
+                delegation: Delegation::new(&voter, amount.into(), activation_epoch.into()),
+                credits_observed: 0.into(),
+            },
+            StakeFlags::empty(),
+        )
+    }
+
+    #[test]
+    fn test_aggregate_by_voter() {
+        let mut dump = Vec::new();
+        dump.extend(snapshot([1; 32], delegated([7; 32], 100, 0)));
+        dump.extend(snapshot([2; 32], delegated([7; 32], 50, 5)));
+        dump.extend(snapshot([3; 32], delegated([8; 32], 10, 0)));
+        dump.extend(snapshot([4; 32], StakeStateV2::Uninitialized));
+
+        let snapshots = read_snapshots(dump.as_slice()).unwrap();
+        assert_eq!(snapshots.len(), 4);
+        assert_eq!(snapshots[3].pubkey, [4; 32]);
+        assert_eq!(snapshots[3].lamports, 1_000);
+
+        // the stake delegated in epoch 5 is activating, the rest is active
+        // without history
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let totals = aggregate_by_voter(&snapshots, 5, history, Some(0));
+        assert_eq!(
+            totals.get(&[7; 32]),
+            Some(&VoterStake {
+                effective: 100,
+                activating: 50,
+                deactivating: 0,
+                accounts: 2,
+            })
+        );
+        assert_eq!(totals.get(&[8; 32]).map(|total| total.effective), Some(10));
+        assert_eq!(totals.len(), 2);
+
+        // truncated records are rejected
+        assert!(read_snapshots(&dump[..SNAPSHOT_LEN + 1]).is_err());
+    }
+
+    #[test]
+    fn test_parse_stake_history() {
+        let mut data = 2u64.to_le_bytes().to_vec();
+        for (epoch, effective) in [(9u64, 300u64), (8, 200)] {
+            for value in [epoch, effective, 1, 2] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        let history = parse_stake_history(&data).unwrap();
+        assert_eq!(
+            history[1],
+            (
+                8,
+                StakeHistoryEntry {
+                    effective: 200,
+                    activating: 1,
+                    deactivating: 2,
+                }
+            )
+        );
+        assert_eq!(
+            parse_stake_history(&data[..40]),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+}
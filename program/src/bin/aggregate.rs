@@ -0,0 +1,114 @@
+//! Prints the effective, activating and deactivating stake of every vote account
+//! in an epoch, from a dump of stake account snapshots.
+//!
+//! ```text
+//! p-stake-aggregate --epoch <EPOCH> --stake-history <FILE>
+//!     [--new-rate-activation-epoch <EPOCH>] [<DIR> | <FILE> | -]
+//! ```
+//!
+//! The stake history file holds the data of the stake history sysvar account.
+//! Snapshots are read from standard input when no path or `-` is given.
+
+use std::{env, fs, io, path::Path, process::ExitCode};
+
+use p_stake::aggregate::{
+    aggregate_by_voter, parse_stake_history, read_snapshot_dir, read_snapshots,
+};
+
+const USAGE: &str = "usage: p-stake-aggregate --epoch <EPOCH> --stake-history <FILE> \
+                     [--new-rate-activation-epoch <EPOCH>] [<DIR> | <FILE> | -]";
+
+struct Args {
+    epoch: u64,
+    stake_history: String,
+    new_rate_activation_epoch: Option<u64>,
+    input: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut epoch = None;
+    let mut stake_history = None;
+    // the program assumes the 9% rate has always applied
+    let mut new_rate_activation_epoch = Some(0);
+    let mut input = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("missing value of {name}"));
+        match arg.as_str() {
+            "--epoch" => {
+                epoch = Some(
+                    value("--epoch")?
+                        .parse()
+                        .map_err(|e| format!("--epoch: {e}"))?,
+                )
+            }
+            "--stake-history" => stake_history = Some(value("--stake-history")?),
+            "--new-rate-activation-epoch" => {
+                new_rate_activation_epoch = Some(
+                    value("--new-rate-activation-epoch")?
+                        .parse()
+                        .map_err(|e| format!("--new-rate-activation-epoch: {e}"))?,
+                )
+            }
+            _ if input.is_none() && (arg == "-" || !arg.starts_with('-')) => input = Some(arg),
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
+    }
+
+    Ok(Args {
+        epoch: epoch.ok_or("missing --epoch")?,
+        stake_history: stake_history.ok_or("missing --stake-history")?,
+        new_rate_activation_epoch,
+        input: input.filter(|input| input != "-"),
+    })
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let stake_history_data =
+        fs::read(&args.stake_history).map_err(|e| format!("{}: {e}", args.stake_history))?;
+    let stake_history = parse_stake_history(&stake_history_data)
+        .map_err(|_| format!("{}: invalid stake history", args.stake_history))?;
+
+    let snapshots = match &args.input {
+        Some(path) if Path::new(path).is_dir() => read_snapshot_dir(Path::new(path)),
+        Some(path) => {
+            fs::File::open(path).and_then(|file| read_snapshots(io::BufReader::new(file)))
+        }
+        None => read_snapshots(io::stdin().lock()),
+    }
+    .map_err(|e| format!("{}: {e}", args.input.as_deref().unwrap_or("stdin")))?;
+
+    let totals = aggregate_by_voter(
+        &snapshots,
+        args.epoch,
+        stake_history.as_slice(),
+        args.new_rate_activation_epoch,
+    );
+
+    println!("vote_account,effective,activating,deactivating,stake_accounts");
+    for (voter_pubkey, total) in totals {
+        println!(
+            "{},{},{},{},{}",
+            bs58::encode(voter_pubkey).into_string(),
+            total.effective,
+            total.activating,
+            total.deactivating,
+            total.accounts
+        );
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let result = parse_args()
+        .map_err(|e| format!("{e}\n{USAGE}"))
+        .and_then(run);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
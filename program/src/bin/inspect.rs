@@ -0,0 +1,572 @@
+//! Decodes a stake account and prints every field of its state and extensions.
+//! Given a clock, it also prints the activation status, the lockup time
+//! remaining and, given the account balance, the amounts that can be withdrawn
+//! and split.
+//!
+//! ```text
+//! p-stake-inspect [--base64 <DATA> | --hex <DATA> | <FILE> | -]
+//!     [--epoch <EPOCH> [--unix-timestamp <TIMESTAMP>] [--stake-history <FILE>]]
+//!     [--lamports <LAMPORTS> [--destination-lamports <LAMPORTS>]] [--json]
+//! ```
+//!
+//! The data is read from standard input when no input is given. The unix
+//! timestamp defaults to the current time and the stake history file holds the
+//! data of the stake history sysvar account. Splits are estimated into a
+//! destination of the size of the account, so that it can hold the extensions,
+//! holding its rent exempt reserve unless `--destination-lamports` is given.
+//!
+//! Extensions are only decoded when the matching features are enabled, the
+//! others are printed as hex data.
+
+use std::{
+    env, fs,
+    io::{self, Read},
+    process::ExitCode,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use p_stake::{
+    aggregate::parse_stake_history,
+    query::{max_split, max_withdrawable},
+    state::{Delegation, Meta, StakeFlags, StakeHistoryEntry, StakeStateV2},
+};
+use pinocchio::{
+    pubkey::Pubkey,
+    sysvars::{clock::Clock, rent::Rent},
+};
+
+#[cfg(any(
+    feature = "deactivation-schedule",
+    feature = "delayed-withdrawer-transfer",
+    feature = "metadata",
+    feature = "recovery-authority",
+    feature = "role-authorities",
+    feature = "vesting",
+    feature = "withdraw-allowlist"
+))]
+use p_stake::state::get_extension;
+#[cfg(feature = "deactivation-schedule")]
+use p_stake::state::DeactivationSchedule;
+#[cfg(feature = "delayed-withdrawer-transfer")]
+use p_stake::state::DelayedWithdrawerTransfer;
+#[cfg(feature = "metadata")]
+use p_stake::state::Metadata;
+#[cfg(feature = "recovery-authority")]
+use p_stake::state::RecoveryAuthority;
+#[cfg(feature = "role-authorities")]
+use p_stake::state::RoleAuthorities;
+#[cfg(feature = "vesting")]
+use p_stake::state::VestingSchedule;
+#[cfg(feature = "extensions")]
+use p_stake::state::{get_extensions, ExtensionType};
+#[cfg(feature = "withdraw-allowlist")]
+use p_stake::state::{WithdrawAllowlist, MAX_ALLOWED_DESTINATIONS};
+#[cfg(feature = "extensions")]
+use pinocchio::program_error::ProgramError;
+use serde_json::{json, Value};
+
+const USAGE: &str = "usage: p-stake-inspect [--base64 <DATA> | --hex <DATA> | <FILE> | -] \
+                     [--epoch <EPOCH> [--unix-timestamp <TIMESTAMP>] [--stake-history <FILE>]] \
+                     [--lamports <LAMPORTS> [--destination-lamports <LAMPORTS>]] [--json]";
+
+// the program assumes the 9% rate has always applied
+const NEW_RATE_ACTIVATION_EPOCH: Option<u64> = Some(0);
+
+// mainnet-beta rent, which splits are estimated with
+const RENT: Rent = Rent {
+    lamports_per_byte_year: 3_480,
+    exemption_threshold: 2.0,
+    burn_percent: 50,
+};
+
+enum Input {
+    Base64(String),
+    Hex(String),
+    File(String),
+    Stdin,
+}
+
+struct Args {
+    input: Input,
+    epoch: Option<u64>,
+    unix_timestamp: Option<i64>,
+    stake_history: Option<String>,
+    lamports: Option<u64>,
+    destination_lamports: Option<u64>,
+    json: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut input = None;
+    let mut epoch = None;
+    let mut unix_timestamp = None;
+    let mut stake_history = None;
+    let mut lamports = None;
+    let mut destination_lamports = None;
+    let mut json = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("missing value of {name}"));
+        match arg.as_str() {
+            "--base64" if input.is_none() => input = Some(Input::Base64(value("--base64")?)),
+            "--hex" if input.is_none() => input = Some(Input::Hex(value("--hex")?)),
+            "--epoch" => epoch = Some(parse(&arg, value(&arg)?)?),
+            "--unix-timestamp" => unix_timestamp = Some(parse(&arg, value(&arg)?)?),
+            "--stake-history" => stake_history = Some(value(&arg)?),
+            "--lamports" => lamports = Some(parse(&arg, value(&arg)?)?),
+            "--destination-lamports" => destination_lamports = Some(parse(&arg, value(&arg)?)?),
+            "--json" => json = true,
+            "-" if input.is_none() => input = Some(Input::Stdin),
+            _ if input.is_none() && !arg.starts_with('-') => input = Some(Input::File(arg)),
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
+    }
+
+    if epoch.is_none() && (unix_timestamp.is_some() || stake_history.is_some()) {
+        return Err("--unix-timestamp and --stake-history require --epoch".into());
+    }
+    if lamports.is_none() && destination_lamports.is_some() {
+        return Err("--destination-lamports requires --lamports".into());
+    }
+
+    Ok(Args {
+        input: input.unwrap_or(Input::Stdin),
+        epoch,
+        unix_timestamp,
+        stake_history,
+        lamports,
+        destination_lamports,
+        json,
+    })
+}
+
+fn parse<T: std::str::FromStr>(name: &str, value: String) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value.parse().map_err(|e| format!("{name}: {e}"))
+}
+
+fn decode_hex(data: &str) -> Result<Vec<u8>, String> {
+    let data = data.trim();
+    let data = data.strip_prefix("0x").unwrap_or(data);
+    if !data.is_ascii() || !data.len().is_multiple_of(2) {
+        return Err("invalid hex data".into());
+    }
+    (0..data.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&data[index..index + 2], 16))
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("invalid hex data: {e}"))
+}
+
+fn read_input(input: &Input) -> Result<Vec<u8>, String> {
+    match input {
+        Input::Base64(data) => STANDARD
+            .decode(data.trim())
+            .map_err(|e| format!("invalid base64 data: {e}")),
+        Input::Hex(data) => decode_hex(data),
+        Input::File(path) => fs::read(path).map_err(|e| format!("{path}: {e}")),
+        Input::Stdin => {
+            let mut data = Vec::new();
+            io::stdin()
+                .lock()
+                .read_to_end(&mut data)
+                .map_err(|e| format!("stdin: {e}"))?;
+            Ok(data)
+        }
+    }
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn pubkey(pubkey: &Pubkey) -> Value {
+    bs58::encode(pubkey).into_string().into()
+}
+
+fn meta_json(meta: &Meta) -> Value {
+    json!({
+        "rent_exempt_reserve": u64::from(meta.rent_exempt_reserve),
+        "authorized": {
+            "staker": pubkey(&meta.authorized.staker),
+            "withdrawer": pubkey(&meta.authorized.withdrawer),
+        },
+        "lockup": {
+            "unix_timestamp": i64::from(meta.lockup.unix_timestamp),
+            "epoch": u64::from(meta.lockup.epoch),
+            "custodian": pubkey(&meta.lockup.custodian),
+        },
+    })
+}
+
+fn delegation_json(delegation: &Delegation) -> Value {
+    #[allow(deprecated)]
+    let warmup_cooldown_rate = f64::from(delegation.warmup_cooldown_rate);
+    json!({
+        "voter_pubkey": pubkey(&delegation.voter_pubkey),
+        "stake": u64::from(delegation.stake),
+        "activation_epoch": u64::from(delegation.activation_epoch),
+        "deactivation_epoch": u64::from(delegation.deactivation_epoch),
+        "warmup_cooldown_rate": warmup_cooldown_rate,
+    })
+}
+
+fn flags_json(flags: &StakeFlags) -> Value {
+    json!({
+        "bits": flags.bits(),
+        // the only flag defined by the stake program
+        "must_fully_activate_before_deactivation_is_permitted": flags.bits() & 1 != 0,
+    })
+}
+
+fn status_json(status: &StakeHistoryEntry) -> Value {
+    json!({
+        "effective": status.effective,
+        "activating": status.activating,
+        "deactivating": status.deactivating,
+    })
+}
+
+/// Fields of the state, as stored in the account
+fn state_json(state: &StakeStateV2) -> Value {
+    match state {
+        StakeStateV2::Uninitialized => json!({ "state": "uninitialized" }),
+        StakeStateV2::Initialized(meta) => json!({
+            "state": "initialized",
+            "meta": meta_json(meta),
+        }),
+        StakeStateV2::Stake(meta, stake, flags) => json!({
+            "state": "stake",
+            "meta": meta_json(meta),
+            "stake": {
+                "delegation": delegation_json(&stake.delegation),
+                "credits_observed": u64::from(stake.credits_observed),
+            },
+            "flags": flags_json(flags),
+        }),
+        StakeStateV2::RewardsPool => json!({ "state": "rewards_pool" }),
+    }
+}
+
+/// Fields of the extensions in the extension area, keyed by extension name
+#[cfg(feature = "extensions")]
+fn extensions_json(data: &[u8]) -> Result<Value, String> {
+    let mut extensions = serde_json::Map::new();
+    for extension in get_extensions(data) {
+        let (extension_type, value) = extension.map_err(|_| "invalid extension data")?;
+        let (name, fields) = extension_json(data, extension_type)
+            .map_err(|_| format!("invalid extension of type {extension_type}"))?
+            .unwrap_or_else(|| {
+                (
+                    format!("type_{extension_type}"),
+                    json!({ "data": encode_hex(value) }),
+                )
+            });
+        extensions.insert(name, fields);
+    }
+    Ok(extensions.into())
+}
+
+/// Name and fields of an extension, if its type is known and its feature enabled
+#[cfg(feature = "extensions")]
+fn extension_json(
+    _data: &[u8],
+    extension_type: u16,
+) -> Result<Option<(String, Value)>, ProgramError> {
+    let Ok(extension_type) = ExtensionType::try_from(extension_type) else {
+        return Ok(None);
+    };
+
+    let extension: Option<(&str, Value)> = match extension_type {
+        #[cfg(feature = "vesting")]
+        ExtensionType::VestingSchedule => {
+            get_extension::<VestingSchedule>(_data)?.map(|vesting_schedule| {
+                (
+                    "vesting_schedule",
+                    json!({
+                        "amount": u64::from(vesting_schedule.amount),
+                        "start_timestamp": i64::from(vesting_schedule.start_timestamp),
+                        "end_timestamp": i64::from(vesting_schedule.end_timestamp),
+                        "period": i64::from(vesting_schedule.period),
+                    }),
+                )
+            })
+        }
+        #[cfg(feature = "metadata")]
+        ExtensionType::Metadata => get_extension::<Metadata>(_data)?.map(|metadata| {
+            let entries = metadata
+                .entries()
+                .filter_map(Result::ok)
+                .map(|(key, value)| {
+                    (
+                        String::from_utf8_lossy(key).into_owned(),
+                        Value::from(String::from_utf8_lossy(value)),
+                    )
+                })
+                .collect::<serde_json::Map<_, _>>();
+            (
+                "metadata",
+                json!({
+                    "name": String::from_utf8_lossy(metadata.name()),
+                    "entries": entries,
+                }),
+            )
+        }),
+        #[cfg(feature = "deactivation-schedule")]
+        ExtensionType::DeactivationSchedule => {
+            get_extension::<DeactivationSchedule>(_data)?.map(|deactivation_schedule| {
+                (
+                    "deactivation_schedule",
+                    json!({ "epoch": u64::from(deactivation_schedule.epoch) }),
+                )
+            })
+        }
+        #[cfg(feature = "delayed-withdrawer-transfer")]
+        ExtensionType::DelayedWithdrawerTransfer => {
+            get_extension::<DelayedWithdrawerTransfer>(_data)?.map(|transfer| {
+                let pending = match transfer.pending() {
+                    Some((withdrawer, epoch)) => json!({
+                        "withdrawer": pubkey(withdrawer),
+                        "effective_epoch": epoch,
+                    }),
+                    None => Value::Null,
+                };
+                (
+                    "delayed_withdrawer_transfer",
+                    json!({
+                        "delay_epochs": u64::from(transfer.delay_epochs),
+                        "pending": pending,
+                    }),
+                )
+            })
+        }
+        #[cfg(feature = "withdraw-allowlist")]
+        ExtensionType::WithdrawAllowlist => {
+            get_extension::<WithdrawAllowlist>(_data)?.map(|allowlist| {
+                let destinations = |destinations: &[Pubkey], len: u8| {
+                    destinations[..(len as usize).min(MAX_ALLOWED_DESTINATIONS)]
+                        .iter()
+                        .map(pubkey)
+                        .collect::<Vec<_>>()
+                };
+                let effective_epoch = u64::from(allowlist.pending_effective_epoch);
+                let pending = if effective_epoch == u64::MAX {
+                    Value::Null
+                } else {
+                    json!({
+                        "timelock_epochs": u64::from(allowlist.pending_timelock_epochs),
+                        "destinations": destinations(
+                            &allowlist.pending_destinations,
+                            allowlist.pending_destinations_len,
+                        ),
+                        "effective_epoch": effective_epoch,
+                    })
+                };
+                (
+                    "withdraw_allowlist",
+                    json!({
+                        "timelock_epochs": u64::from(allowlist.timelock_epochs),
+                        "destinations": destinations(
+                            &allowlist.destinations,
+                            allowlist.destinations_len,
+                        ),
+                        "pending": pending,
+                    }),
+                )
+            })
+        }
+        #[cfg(feature = "role-authorities")]
+        ExtensionType::RoleAuthorities => {
+            get_extension::<RoleAuthorities>(_data)?.map(|role_authorities| {
+                (
+                    "role_authorities",
+                    json!({
+                        "deactivator": pubkey(&role_authorities.deactivator),
+                        "rebalancer": pubkey(&role_authorities.rebalancer),
+                    }),
+                )
+            })
+        }
+        #[cfg(feature = "recovery-authority")]
+        ExtensionType::RecoveryAuthority => {
+            get_extension::<RecoveryAuthority>(_data)?.map(|recovery_authority| {
+                (
+                    "recovery_authority",
+                    json!({
+                        "authority": pubkey(&recovery_authority.authority),
+                        "inactivity_epochs": u64::from(recovery_authority.inactivity_epochs),
+                        "last_activity_epoch": u64::from(recovery_authority.last_activity_epoch),
+                    }),
+                )
+            })
+        }
+        _ => None,
+    };
+
+    Ok(extension.map(|(name, fields)| (name.to_string(), fields)))
+}
+
+fn inspect(args: &Args, state: &StakeStateV2, data: &[u8]) -> Result<Value, String> {
+    let mut report = state_json(state);
+    let fields = report.as_object_mut().unwrap();
+
+    if data.len() > StakeStateV2::size_of() {
+        #[cfg(feature = "extensions")]
+        fields.insert(
+            "extensions".into(),
+            extensions_json(&data[StakeStateV2::size_of()..])?,
+        );
+        #[cfg(not(feature = "extensions"))]
+        fields.insert(
+            "extensions".into(),
+            json!({ "data": encode_hex(&data[StakeStateV2::size_of()..]) }),
+        );
+    }
+
+    let Some(epoch) = args.epoch else {
+        return Ok(report);
+    };
+
+    let unix_timestamp = match args.unix_timestamp {
+        Some(unix_timestamp) => unix_timestamp,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| format!("system time: {e}"))?
+            .as_secs() as i64,
+    };
+    // only the epoch and the timestamp are read by the queries
+    let clock = Clock {
+        slot: 0,
+        epoch_start_timestamp: unix_timestamp,
+        epoch,
+        leader_schedule_epoch: epoch.saturating_add(1),
+        unix_timestamp,
+    };
+    fields.insert(
+        "clock".into(),
+        json!({ "epoch": epoch, "unix_timestamp": unix_timestamp }),
+    );
+
+    let stake_history = match &args.stake_history {
+        Some(path) => {
+            let data = fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+            parse_stake_history(&data).map_err(|_| format!("{path}: invalid stake history"))?
+        }
+        None => Vec::new(),
+    };
+    let stake_history = stake_history.as_slice();
+
+    if let StakeStateV2::Stake(_, stake, _) = state {
+        let status = stake.delegation.stake_activating_and_deactivating(
+            epoch,
+            stake_history,
+            NEW_RATE_ACTIVATION_EPOCH,
+        );
+        fields.insert("status".into(), status_json(&status));
+    }
+
+    if let StakeStateV2::Initialized(meta) | StakeStateV2::Stake(meta, _, _) = state {
+        let lockup = &meta.lockup;
+        fields.insert(
+            "lockup_remaining".into(),
+            json!({
+                "in_force": lockup.is_in_force(&clock, None),
+                "epochs": u64::from(lockup.epoch).saturating_sub(epoch),
+                "seconds": i64::from(lockup.unix_timestamp).saturating_sub(unix_timestamp).max(0),
+            }),
+        );
+    }
+
+    if let Some(lamports) = args.lamports {
+        let extensions = data.get(StakeStateV2::size_of()..).unwrap_or_default();
+        let destination_data_len = data.len();
+        let destination_lamports = args
+            .destination_lamports
+            .unwrap_or_else(|| RENT.minimum_balance(destination_data_len));
+        let withdrawable = max_withdrawable(
+            state,
+            extensions,
+            lamports,
+            None,
+            &clock,
+            stake_history,
+            None,
+        )
+        .map_err(|e| format!("withdrawable: {e:?}"))?;
+        let splittable = max_split(
+            state,
+            extensions,
+            lamports,
+            destination_lamports,
+            destination_data_len,
+            &clock,
+            stake_history,
+            &RENT,
+        )
+        .map_err(|e| format!("splittable: {e:?}"))?;
+
+        fields.insert("lamports".into(), lamports.into());
+        fields.insert("withdrawable".into(), withdrawable.into());
+        fields.insert(
+            "splittable".into(),
+            json!({
+                "destination_lamports": destination_lamports,
+                "lamports": splittable,
+            }),
+        );
+    }
+
+    Ok(report)
+}
+
+fn print_text(value: &Value, indent: usize) {
+    let Value::Object(fields) = value else {
+        return;
+    };
+    for (name, field) in fields {
+        match field {
+            Value::Object(_) => {
+                println!("{:indent$}{name}:", "");
+                print_text(field, indent + 2);
+            }
+            Value::String(field) => println!("{:indent$}{name}: {field}", ""),
+            _ => println!("{:indent$}{name}: {field}", ""),
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let data = read_input(&args.input)?;
+    let state = StakeStateV2::try_from_slice(&data).map_err(|_| {
+        format!(
+            "invalid stake account data of {} bytes, expected at least {}",
+            data.len(),
+            StakeStateV2::size_of()
+        )
+    })?;
+
+    let report = inspect(&args, &state, &data)?;
+    if args.json {
+        println!("{report:#}");
+    } else {
+        print_text(&report, 0);
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let result = parse_args()
+        .map_err(|e| format!("{e}\n{USAGE}"))
+        .and_then(run);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -165,6 +165,142 @@ fn process_instruction(
 
             instruction::process_move_lamports(accounts, instruction_data)
         }
+        // 18 - MergeMany
+        #[cfg(feature = "merge-many")]
+        18 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: MergeMany");
+
+            instruction::process_merge_many(accounts, instruction_data)
+        }
+        // 19 - InitializeAndDelegate
+        #[cfg(feature = "initialize-and-delegate")]
+        19 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: InitializeAndDelegate");
+
+            instruction::process_initialize_and_delegate(accounts, instruction_data)
+        }
+        // 20 - CreateAccount
+        #[cfg(feature = "create-account")]
+        20 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: CreateAccount");
+
+            instruction::process_create_account(accounts, instruction_data)
+        }
+        // 21 - AuthorizeWithProgramAddress
+        #[cfg(feature = "authorize-with-program-address")]
+        21 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: AuthorizeWithProgramAddress");
+
+            instruction::process_authorize_with_program_address(accounts, instruction_data)
+        }
+        // 22 - SetLockupWithSeed
+        #[cfg(feature = "set-lockup-with-seed")]
+        22 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: SetLockupWithSeed");
+
+            instruction::process_set_lockup_with_seed(accounts, instruction_data)
+        }
+        // 23 - SetVestingSchedule
+        #[cfg(feature = "vesting")]
+        23 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: SetVestingSchedule");
+
+            instruction::process_set_vesting_schedule(accounts, instruction_data)
+        }
+        // 24 - SetMetadata
+        #[cfg(feature = "metadata")]
+        24 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: SetMetadata");
+
+            instruction::process_set_metadata(accounts, instruction_data)
+        }
+        // 25 - SetDeactivationSchedule
+        #[cfg(feature = "deactivation-schedule")]
+        25 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: SetDeactivationSchedule");
+
+            instruction::process_set_deactivation_schedule(accounts, instruction_data)
+        }
+        // 26 - CrankScheduledDeactivation
+        #[cfg(feature = "deactivation-schedule")]
+        26 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: CrankScheduledDeactivation");
+
+            instruction::process_crank_scheduled_deactivation(accounts, instruction_data)
+        }
+        // 27 - EnableDelayedWithdrawerTransfer
+        #[cfg(feature = "delayed-withdrawer-transfer")]
+        27 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: EnableDelayedWithdrawerTransfer");
+
+            instruction::process_enable_delayed_withdrawer_transfer(accounts, instruction_data)
+        }
+        // 28 - ProposeWithdrawer
+        #[cfg(feature = "delayed-withdrawer-transfer")]
+        28 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: ProposeWithdrawer");
+
+            instruction::process_propose_withdrawer(accounts, instruction_data)
+        }
+        // 29 - AcceptWithdrawer
+        #[cfg(feature = "delayed-withdrawer-transfer")]
+        29 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: AcceptWithdrawer");
+
+            instruction::process_accept_withdrawer(accounts, instruction_data)
+        }
+        // 30 - CancelWithdrawerProposal
+        #[cfg(feature = "delayed-withdrawer-transfer")]
+        30 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: CancelWithdrawerProposal");
+
+            instruction::process_cancel_withdrawer_proposal(accounts, instruction_data)
+        }
+        // 31 - SetWithdrawAllowlist
+        #[cfg(feature = "withdraw-allowlist")]
+        31 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: SetWithdrawAllowlist");
+
+            instruction::process_set_withdraw_allowlist(accounts, instruction_data)
+        }
+        // 32 - SetRoleAuthority
+        #[cfg(feature = "role-authorities")]
+        32 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: SetRoleAuthority");
+
+            instruction::process_set_role_authority(accounts, instruction_data)
+        }
+        // 33 - SetRecoveryAuthority
+        #[cfg(feature = "recovery-authority")]
+        33 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: SetRecoveryAuthority");
+
+            instruction::process_set_recovery_authority(accounts, instruction_data)
+        }
+        // 34 - RecoverWithdrawer
+        #[cfg(feature = "recovery-authority")]
+        34 => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Instruction: RecoverWithdrawer");
+
+            instruction::process_recover_withdrawer(accounts, instruction_data)
+        }
         _ => Err(ProgramError::InvalidInstructionData),
     }
 }
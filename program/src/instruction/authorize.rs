@@ -3,11 +3,13 @@ use pinocchio::{
     ProgramResult,
 };
 
+#[cfg(feature = "authorize-with-program-address")]
+use pinocchio::pubkey::{create_program_address, MAX_SEEDS};
+
 use crate::{
     pinocchio_add::{clock, pubkey::create_with_seed},
-    state::{
-        get_stake_state, get_stake_state_mut, AuthorizeSignerArgs, StakeAuthorize, StakeStateV2,
-    },
+    state::{get_stake_account_data_mut, StakeAuthorize, StakeStateV2},
+    transition::{self, account_signers},
 };
 
 #[repr(C)]
@@ -126,6 +128,77 @@ impl AuthorizeCheckedWithSeedArgs<'_> {
     }
 }
 
+#[cfg(feature = "authorize-with-program-address")]
+#[derive(Debug)]
+pub struct AuthorizeWithProgramAddressArgs<'a> {
+    pub new_authority: &'a Pubkey,
+    pub authority_type: StakeAuthorize,
+    /// Seeds followed by the bump seed, only the first `seeds_len` are used
+    pub seeds: [&'a [u8]; MAX_SEEDS],
+    pub seeds_len: usize,
+    pub authority_owner: &'a Pubkey,
+}
+
+#[cfg(feature = "authorize-with-program-address")]
+impl AuthorizeWithProgramAddressArgs<'_> {
+    #[inline(always)]
+    fn from_data(data: &[u8]) -> Result<AuthorizeWithProgramAddressArgs<'_>, ProgramError> {
+        if data.len() < 32 + 4 + 8 + 1 + 32 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        // check authority type variants
+        if data[32] > 1 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        if data[33..36] != [0u8; 3] {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        let seeds_count = u64::from_le_bytes(data[32 + 4..32 + 4 + 8].try_into().unwrap());
+
+        // the bump seed takes the last slot
+        if seeds_count >= MAX_SEEDS as u64 {
+            return Err(ProgramError::MaxSeedLengthExceeded);
+        }
+
+        let mut seeds: [&[u8]; MAX_SEEDS] = [&[]; MAX_SEEDS];
+        let mut offset = 32 + 4 + 8;
+        for seed in seeds.iter_mut().take(seeds_count as usize) {
+            if data.len() < offset + 8 {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            let seed_size = u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+            offset += 8;
+
+            let seed_end = offset
+                .checked_add(seed_size as usize)
+                .ok_or(ProgramError::InvalidInstructionData)?;
+            if data.len() < seed_end {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            *seed = &data[offset..seed_end];
+            offset = seed_end;
+        }
+
+        if data.len() < offset + 1 + 32 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        seeds[seeds_count as usize] = &data[offset..offset + 1];
+        offset += 1;
+
+        let args = AuthorizeWithProgramAddressArgs {
+            new_authority: unsafe { &*(data.as_ptr() as *const Pubkey) },
+            authority_type: unsafe { *(data.as_ptr().add(32) as *const StakeAuthorize) },
+            seeds,
+            seeds_len: seeds_count as usize + 1,
+            authority_owner: unsafe { &*(data.as_ptr().add(offset) as *const Pubkey) },
+        };
+
+        Ok(args)
+    }
+}
+
 pub fn process_authorize(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
     let authorize_args = AuthorizeArgs::from_data(data)?;
 
@@ -148,11 +221,9 @@ pub fn process_authorize(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult
         None
     };
 
-    let signers_args = get_authorize_signer_args(stake_account_info, accounts)?;
-
     do_authorize(
         stake_account_info,
-        signers_args,
+        account_signers(accounts),
         &authorize_args.new_authority,
         authorize_args.authority_type,
         custodian,
@@ -196,16 +267,9 @@ pub fn process_authorize_with_seed(accounts: &[AccountInfo], data: &[u8]) -> Pro
         None
     };
 
-    let signers_args = get_authorize_signer_args_with_seed(
-        stake_account_info,
-        &authority,
-        &custodian,
-        &stake_or_withdraw_auth,
-    )?;
-
     do_authorize(
         stake_account_info,
-        signers_args,
+        seed_signers(authority, custodian, &stake_or_withdraw_auth),
         authorize_args.new_authority,
         authorize_args.authority_type,
         custodian,
@@ -215,6 +279,60 @@ pub fn process_authorize_with_seed(accounts: &[AccountInfo], data: &[u8]) -> Pro
     Ok(())
 }
 
+#[cfg(feature = "authorize-with-program-address")]
+pub fn process_authorize_with_program_address(
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let authorize_args = AuthorizeWithProgramAddressArgs::from_data(data)?;
+
+    let [stake_account_info, stake_or_withdraw_authority_info, clock_info, remaining @ ..] =
+        accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let clock = &clock::from_account_info(clock_info)?;
+
+    let custodian = if !remaining.is_empty() {
+        let lockup_authority_info = unsafe { remaining.get_unchecked(0) };
+        if lockup_authority_info.is_signer() {
+            Some(lockup_authority_info.key())
+        } else {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+    } else {
+        None
+    };
+
+    // the authority is a program derived address signing through invoke_signed,
+    // the seeds prove which program it belongs to
+    let stake_or_withdraw_auth = if stake_or_withdraw_authority_info.is_signer() {
+        let program_address = create_program_address(
+            &authorize_args.seeds[..authorize_args.seeds_len],
+            authorize_args.authority_owner,
+        )?;
+        if program_address != *stake_or_withdraw_authority_info.key() {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        Some(program_address)
+    } else {
+        None
+    };
+
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::authorize_with_program_address(
+        stake_account,
+        extensions,
+        authorize_args.new_authority,
+        authorize_args.authority_type,
+        stake_or_withdraw_auth.as_ref(),
+        custodian,
+        clock,
+    )
+}
+
 pub fn process_authorize_checked(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
     if data.len() < 4 {
         return Err(ProgramError::InvalidInstructionData);
@@ -253,11 +371,9 @@ pub fn process_authorize_checked(accounts: &[AccountInfo], data: &[u8]) -> Progr
         None
     };
 
-    let signers_args = get_authorize_signer_args(stake_account_info, accounts)?;
-
     do_authorize(
         stake_account_info,
-        signers_args,
+        account_signers(accounts),
         new_stake_or_withdraw_authority_info.key(),
         authority_type,
         custodian,
@@ -305,16 +421,9 @@ pub fn process_authorize_checked_with_seed(accounts: &[AccountInfo], data: &[u8]
         None
     };
 
-    let signers_args = get_authorize_signer_args_with_seed(
-        stake_account_info,
-        &authority,
-        &custodian,
-        &stake_or_withdraw_auth,
-    )?;
-
     do_authorize(
         stake_account_info,
-        signers_args,
+        seed_signers(authority, custodian, &stake_or_withdraw_auth),
         new_stake_or_withdraw_authority_info.key(),
         authorize_args.authority_type,
         custodian,
@@ -326,110 +435,37 @@ pub fn process_authorize_checked_with_seed(accounts: &[AccountInfo], data: &[u8]
 
 fn do_authorize(
     stake_account_info: &AccountInfo,
-    signers_args: AuthorizeSignerArgs,
+    is_signer: impl Fn(&Pubkey) -> bool,
     new_authority: &Pubkey,
     authority_type: StakeAuthorize,
     custodian: Option<&Pubkey>,
     clock: &Clock,
 ) -> ProgramResult {
-    let mut stake_account = get_stake_state_mut(stake_account_info)?;
-    match &mut *stake_account {
-        StakeStateV2::Initialized(meta) => meta.authorized.authorize(
-            signers_args,
-            new_authority,
-            authority_type,
-            (&meta.lockup, clock, custodian),
-        ),
-        StakeStateV2::Stake(meta, _stake, _stake_flags) => meta.authorized.authorize(
-            signers_args,
-            new_authority,
-            authority_type,
-            (&meta.lockup, clock, custodian),
-        ),
-        _ => Err(ProgramError::InvalidAccountData), // unreachable, but unreachable leads to more CUs
-    }
-}
-
-fn get_authorize_signer_args(
-    stake_account_info: &AccountInfo,
-    accounts: &[AccountInfo],
-) -> Result<AuthorizeSignerArgs, ProgramError> {
-    let stake_account = get_stake_state(stake_account_info)?;
-
-    let mut has_staker_signer = false;
-    let mut has_withdrawer_signer = false;
-
-    match *stake_account {
-        StakeStateV2::Initialized(ref meta) | StakeStateV2::Stake(ref meta, _, _) => {
-            for account in accounts {
-                if account.is_signer() {
-                    if meta.authorized.staker == *account.key() {
-                        has_staker_signer = true;
-                    }
-                    if meta.authorized.withdrawer == *account.key() {
-                        has_withdrawer_signer = true;
-                    }
-                }
-            }
-        }
-        _ => {
-            return Err(ProgramError::InvalidAccountData);
-        }
-    }
-    Ok(AuthorizeSignerArgs {
-        has_staker_signer,
-        has_withdrawer_signer,
-    })
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::authorize(
+        stake_account,
+        extensions,
+        new_authority,
+        authority_type,
+        is_signer,
+        custodian,
+        clock,
+    )
 }
 
-fn get_authorize_signer_args_with_seed(
-    stake_account_info: &AccountInfo,
-    authority: &Option<&Pubkey>,
-    custodian: &Option<&Pubkey>,
-    stake_or_withdraw_auth: &Option<Pubkey>,
-) -> Result<AuthorizeSignerArgs, ProgramError> {
-    let stake_account = get_stake_state(stake_account_info)?;
-
-    let mut has_staker_signer = false;
-    let mut has_withdrawer_signer = false;
-
-    match *stake_account {
-        StakeStateV2::Initialized(ref meta) | StakeStateV2::Stake(ref meta, _, _) => {
-            if let Some(authority) = *authority {
-                if meta.authorized.staker == *authority {
-                    has_staker_signer = true;
-                }
-                if meta.authorized.withdrawer == *authority {
-                    has_withdrawer_signer = true;
-                }
-            };
-
-            if let Some(custodian) = *custodian {
-                if meta.authorized.staker == *custodian {
-                    has_staker_signer = true;
-                }
-                if meta.authorized.withdrawer == *custodian {
-                    has_withdrawer_signer = true;
-                }
-            };
-
-            if let Some(stake_or_withdraw_auth) = stake_or_withdraw_auth {
-                if meta.authorized.staker == *stake_or_withdraw_auth {
-                    has_staker_signer = true;
-                }
-                if meta.authorized.withdrawer == *stake_or_withdraw_auth {
-                    has_withdrawer_signer = true;
-                }
-            };
-        }
-        _ => {
-            return Err(ProgramError::InvalidAccountData);
-        }
+/// Signers of the seed variants: only the new authority, the custodian and the
+/// derived authority count, not the base account that signed for the latter
+fn seed_signers<'a>(
+    authority: Option<&'a Pubkey>,
+    custodian: Option<&'a Pubkey>,
+    stake_or_withdraw_auth: &'a Option<Pubkey>,
+) -> impl Fn(&Pubkey) -> bool + 'a {
+    move |pubkey| {
+        authority == Some(pubkey)
+            || custodian == Some(pubkey)
+            || stake_or_withdraw_auth.as_ref() == Some(pubkey)
     }
-    Ok(AuthorizeSignerArgs {
-        has_staker_signer,
-        has_withdrawer_signer,
-    })
 }
 
 #[cfg(test)]
@@ -450,4 +486,43 @@ mod tests {
         println!("{:?}", args);
         // assert_eq!(args.authority_type, StakeAuthorize::Withdrawer);
     }
+
+    #[cfg(feature = "authorize-with-program-address")]
+    #[test]
+    fn test_authorize_with_program_address_args_bounds() {
+        let program_address_data = |seed_size: u64| {
+            let mut data = [[7; 32].as_slice(), &[0, 0, 0, 0]].concat();
+            data.extend_from_slice(&2u64.to_le_bytes());
+            data.extend_from_slice(&1u64.to_le_bytes());
+            data.push(1);
+            data.extend_from_slice(&seed_size.to_le_bytes());
+            data.extend_from_slice(b"seed");
+            // bump seed, then the owner
+            data.push(255);
+            data.extend_from_slice(&[8; 32]);
+            data
+        };
+
+        let data = program_address_data(4);
+        let args = AuthorizeWithProgramAddressArgs::from_data(&data).unwrap();
+        assert_eq!(args.new_authority, &[7; 32]);
+        assert_eq!(args.authority_type, StakeAuthorize::Staker);
+        assert_eq!(&args.seeds[..args.seeds_len], [&[1][..], b"seed", &[255]]);
+        assert_eq!(args.authority_owner, &[8; 32]);
+
+        for seed_size in [5, u64::MAX - 8, u64::MAX] {
+            assert_eq!(
+                AuthorizeWithProgramAddressArgs::from_data(&program_address_data(seed_size)).err(),
+                Some(ProgramError::InvalidInstructionData)
+            );
+        }
+
+        // the bump seed needs a slot
+        let mut data = program_address_data(4);
+        data[36..44].copy_from_slice(&(MAX_SEEDS as u64).to_le_bytes());
+        assert_eq!(
+            AuthorizeWithProgramAddressArgs::from_data(&data).err(),
+            Some(ProgramError::MaxSeedLengthExceeded)
+        );
+    }
 }
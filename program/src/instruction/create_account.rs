@@ -0,0 +1,213 @@
+use pinocchio::{
+    account_info::AccountInfo,
+    program_error::ProgramError,
+    sysvars::{rent::Rent, Sysvar},
+    ProgramResult,
+};
+
+use crate::{
+    instruction::InitializeArgs,
+    pinocchio_add::{
+        pubkey::create_with_seed,
+        system::{create_account, create_account_with_seed, SYSTEM_PROGRAM_ID},
+    },
+    state::{get_stake_state_mut, StakeStateV2},
+    transition,
+};
+
+#[derive(Debug, PartialEq)]
+struct CreateAccountArgs<'a> {
+    lamports: u64,
+    initialize_args: &'a InitializeArgs,
+    /// seed of an address derived with `create_with_seed`
+    seed: Option<&'a [u8]>,
+}
+
+impl CreateAccountArgs<'_> {
+    #[inline(always)]
+    fn from_data(data: &[u8]) -> Result<CreateAccountArgs<'_>, ProgramError> {
+        // lamports | authorized | lockup | optional seed length and seed
+        if data.len() < 8 + core::mem::size_of::<InitializeArgs>() {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let lamports = u64::from_le_bytes(data[0..8].try_into().unwrap());
+        let (initialize_data, seed_data) =
+            data[8..].split_at(core::mem::size_of::<InitializeArgs>());
+        let initialize_args = InitializeArgs::from_data(initialize_data)?;
+
+        let seed = if seed_data.is_empty() {
+            None
+        } else {
+            if seed_data.len() < 8 {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            let seed_size = u64::from_le_bytes(seed_data[0..8].try_into().unwrap());
+            let seed_end = 8usize
+                .checked_add(seed_size as usize)
+                .ok_or(ProgramError::InvalidInstructionData)?;
+            if seed_data.len() != seed_end {
+                return Err(ProgramError::InvalidInstructionData);
+            }
+            Some(&seed_data[8..])
+        };
+
+        Ok(CreateAccountArgs {
+            lamports,
+            initialize_args,
+            seed,
+        })
+    }
+}
+
+pub fn process_create_account(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let CreateAccountArgs {
+        lamports,
+        initialize_args,
+        seed,
+    } = CreateAccountArgs::from_data(data)?;
+
+    let [payer_info, stake_account_info, system_program_info, remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if *system_program_info.key() != SYSTEM_PROGRAM_ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let rent = &Rent::get()?;
+    let space = StakeStateV2::size_of() as u64;
+
+    // an underfunded account is rejected before it is created
+    let stake_state = transition::create_account(
+        lamports,
+        initialize_args.authorized,
+        initialize_args.lockup,
+        rent,
+    )?;
+
+    if let Some(seed) = seed {
+        let [base_info, _remaining @ ..] = remaining else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        if !base_info.is_signer() {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if create_with_seed(base_info.key(), seed, &crate::ID)? != *stake_account_info.key() {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        create_account_with_seed(
+            payer_info,
+            stake_account_info,
+            base_info,
+            seed,
+            lamports,
+            space,
+            &crate::ID,
+        )?;
+    } else {
+        create_account(payer_info, stake_account_info, lamports, space, &crate::ID)?;
+    }
+
+    *get_stake_state_mut(stake_account_info)? = stake_state;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        state::{Authorized, Lockup},
+        test_utils::*,
+    };
+
+    use super::*;
+
+    fn create_account_data(lamports: u64, seed: Option<(u64, &[u8])>) -> Vec<u8> {
+        let mut data = lamports.to_le_bytes().to_vec();
+        data.extend_from_slice(&[1; 32]);
+        data.extend_from_slice(&[2; 32]);
+        data.extend_from_slice(&[0; core::mem::size_of::<Lockup>()]);
+        if let Some((seed_size, seed)) = seed {
+            data.extend_from_slice(&seed_size.to_le_bytes());
+            data.extend_from_slice(seed);
+        }
+        data
+    }
+
+    #[test]
+    fn test_create_account_args() {
+        let initialize_args = InitializeArgs {
+            authorized: Authorized {
+                staker: [1; 32],
+                withdrawer: [2; 32],
+            },
+            lockup: Lockup::default(),
+        };
+
+        assert_eq!(
+            CreateAccountArgs::from_data(&create_account_data(5, None)),
+            Ok(CreateAccountArgs {
+                lamports: 5,
+                initialize_args: &initialize_args,
+                seed: None,
+            })
+        );
+        assert_eq!(
+            CreateAccountArgs::from_data(&create_account_data(5, Some((4, b"seed")))),
+            Ok(CreateAccountArgs {
+                lamports: 5,
+                initialize_args: &initialize_args,
+                seed: Some(b"seed"),
+            })
+        );
+        assert_eq!(
+            CreateAccountArgs::from_data(&create_account_data(5, Some((0, b"")))),
+            Ok(CreateAccountArgs {
+                lamports: 5,
+                initialize_args: &initialize_args,
+                seed: Some(b""),
+            })
+        );
+
+        // truncated data, seed sizes not matching the seed or overflowing
+        let mut truncated = create_account_data(5, None);
+        truncated.pop();
+        assert_eq!(
+            CreateAccountArgs::from_data(&truncated),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        let mut truncated_seed_size = create_account_data(5, None);
+        truncated_seed_size.extend_from_slice(&[4, 0, 0]);
+        assert_eq!(
+            CreateAccountArgs::from_data(&truncated_seed_size),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        for seed_size in [3, 5, u64::MAX - 7, u64::MAX] {
+            assert_eq!(
+                CreateAccountArgs::from_data(&create_account_data(5, Some((seed_size, b"seed")))),
+                Err(ProgramError::InvalidInstructionData)
+            );
+        }
+    }
+
+    #[test]
+    fn test_create_account() {
+        let rent = rent();
+        let authorized = Authorized {
+            staker: STAKER,
+            withdrawer: WITHDRAWER,
+        };
+
+        assert_eq!(
+            transition::create_account(RESERVE, authorized, Lockup::default(), &rent),
+            Ok(initialized())
+        );
+        assert_eq!(
+            transition::create_account(RESERVE - 1, authorized, Lockup::default(), &rent),
+            Err(ProgramError::InsufficientFunds)
+        );
+    }
+}
@@ -2,7 +2,8 @@ use pinocchio::{account_info::AccountInfo, program_error::ProgramError, ProgramR
 
 use crate::{
     pinocchio_add::clock,
-    state::{get_stake_state_mut, StakeStateV2},
+    state::{get_stake_account_data_mut, StakeStateV2},
+    transition::{self, account_signers},
 };
 
 pub fn process_deactivate(accounts: &[AccountInfo], _data: &[u8]) -> ProgramResult {
@@ -12,23 +13,7 @@ pub fn process_deactivate(accounts: &[AccountInfo], _data: &[u8]) -> ProgramResu
 
     let clock = &clock::from_account_info(clock_info)?;
 
-    let mut stake_account = get_stake_state_mut(stake_account_info)?;
-    match &mut *stake_account {
-        StakeStateV2::Stake(meta, stake, _stake_flags) => {
-            let mut has_signer = false;
-            for account in accounts {
-                if account.is_signer() && meta.authorized.staker == *account.key() {
-                    has_signer = true;
-                }
-            }
-            if !has_signer {
-                return Err(ProgramError::MissingRequiredSignature);
-            }
-
-            stake.deactivate(clock.epoch.into())?;
-        }
-        _ => return Err(ProgramError::InvalidAccountData),
-    }
-
-    Ok(())
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::deactivate(stake_account, extensions, account_signers(accounts), clock)
 }
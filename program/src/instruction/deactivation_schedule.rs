@@ -0,0 +1,137 @@
+use pinocchio::{
+    account_info::AccountInfo,
+    program_error::ProgramError,
+    sysvars::{clock::Clock, Sysvar},
+    ProgramResult,
+};
+
+use crate::{
+    pinocchio_add::clock,
+    state::{get_stake_account_data_mut, StakeStateV2},
+    transition::{self, account_signers},
+};
+
+/// Deactivation epoch that removes the schedule of an account
+pub const NO_DEACTIVATION_SCHEDULE: u64 = u64::MAX;
+
+pub fn process_set_deactivation_schedule(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() != 8 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let epoch = u64::from_le_bytes(data.try_into().unwrap());
+
+    let [stake_account_info, stake_authority_info, _remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let clock = Clock::get()?;
+
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::set_deactivation_schedule(
+        stake_account,
+        extensions,
+        epoch,
+        account_signers(core::slice::from_ref(stake_authority_info)),
+        &clock,
+    )
+}
+
+pub fn process_crank_scheduled_deactivation(
+    accounts: &[AccountInfo],
+    _data: &[u8],
+) -> ProgramResult {
+    let [stake_account_info, clock_info, _remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let clock = &clock::from_account_info(clock_info)?;
+
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::crank_scheduled_deactivation(stake_account, extensions, clock)
+}
+
+#[cfg(test)]
+mod tests {
+    use pinocchio::program_error::ProgramError;
+
+    use crate::{
+        state::{get_extension, DeactivationSchedule, StakeStateV2},
+        test_utils::*,
+        transition::*,
+    };
+
+    use super::NO_DEACTIVATION_SCHEDULE;
+
+    #[test]
+    fn test_deactivation_schedule() {
+        let space = crate::state::extension_space::<DeactivationSchedule>();
+        let deactivation_epoch = |stake_state: &StakeStateV2| match stake_state {
+            StakeStateV2::Stake(_, stake, _) => u64::from(stake.delegation.deactivation_epoch),
+            _ => panic!("stake account is not delegated"),
+        };
+        let schedule = |stake_state: &StakeStateV2, extensions: &mut [u8], epoch| {
+            set_deactivation_schedule(stake_state, extensions, epoch, signed_by(STAKER), &clock(1))
+        };
+
+        // only the staker schedules a deactivation
+        let mut stake_state = delegated(2 * RESERVE, 0);
+        let mut extensions = vec![0; space];
+        assert_eq!(
+            set_deactivation_schedule(
+                &stake_state,
+                &mut extensions,
+                5,
+                signed_by(WITHDRAWER),
+                &clock(1),
+            ),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        schedule(&stake_state, &mut extensions, 5).unwrap();
+        assert_eq!(
+            get_extension::<DeactivationSchedule>(&extensions)
+                .unwrap()
+                .map(|schedule| u64::from(schedule.epoch)),
+            Some(5)
+        );
+
+        // the crank deactivates once the epoch is reached, then the schedule is gone
+        assert_eq!(
+            crank_scheduled_deactivation(&mut stake_state, &mut extensions, &clock(4)),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(deactivation_epoch(&stake_state), u64::MAX);
+        crank_scheduled_deactivation(&mut stake_state, &mut extensions, &clock(6)).unwrap();
+        assert_eq!(deactivation_epoch(&stake_state), 6);
+        assert_eq!(extensions, vec![0; space]);
+        assert_eq!(
+            crank_scheduled_deactivation(&mut stake_state, &mut extensions, &clock(7)),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        // a cancelled schedule is never due
+        let mut stake_state = delegated(2 * RESERVE, 0);
+        schedule(&stake_state, &mut extensions, 5).unwrap();
+        schedule(&stake_state, &mut extensions, NO_DEACTIVATION_SCHEDULE).unwrap();
+        assert_eq!(extensions, vec![0; space]);
+        assert_eq!(
+            crank_scheduled_deactivation(&mut stake_state, &mut extensions, &clock(5)),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(deactivation_epoch(&stake_state), u64::MAX);
+
+        // stake deactivated in the meantime keeps its deactivation epoch
+        schedule(&stake_state, &mut extensions, 5).unwrap();
+        deactivate(
+            &mut stake_state,
+            &mut extensions,
+            signed_by(STAKER),
+            &clock(3),
+        )
+        .unwrap();
+        crank_scheduled_deactivation(&mut stake_state, &mut extensions, &clock(5)).unwrap();
+        assert_eq!(deactivation_epoch(&stake_state), 3);
+        assert_eq!(extensions, vec![0; space]);
+    }
+}
@@ -0,0 +1,380 @@
+use pinocchio::{
+    account_info::AccountInfo,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvars::{clock::Clock, Sysvar},
+    ProgramResult,
+};
+
+use crate::{
+    pinocchio_add::clock,
+    state::{get_stake_account_data_mut, StakeStateV2},
+    transition::{self, account_signers},
+};
+
+pub fn process_enable_delayed_withdrawer_transfer(
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    if data.len() != 8 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let delay_epochs = u64::from_le_bytes(data.try_into().unwrap());
+
+    let [stake_account_info, withdraw_authority_info, _remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let clock = Clock::get()?;
+
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::enable_delayed_withdrawer_transfer(
+        stake_account,
+        extensions,
+        delay_epochs,
+        account_signers(core::slice::from_ref(withdraw_authority_info)),
+        &clock,
+    )
+}
+
+pub fn process_propose_withdrawer(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() != 32 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let new_withdrawer = unsafe { &*(data.as_ptr() as *const Pubkey) };
+
+    let [stake_account_info, clock_info, withdraw_authority_info, _remaining @ ..] = accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let clock = clock::from_account_info(clock_info)?;
+
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::propose_withdrawer(
+        stake_account,
+        extensions,
+        new_withdrawer,
+        account_signers(core::slice::from_ref(withdraw_authority_info)),
+        &clock,
+    )
+}
+
+pub fn process_cancel_withdrawer_proposal(accounts: &[AccountInfo], _data: &[u8]) -> ProgramResult {
+    let [stake_account_info, withdraw_authority_info, _remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let clock = Clock::get()?;
+
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::cancel_withdrawer_proposal(
+        stake_account,
+        extensions,
+        account_signers(core::slice::from_ref(withdraw_authority_info)),
+        &clock,
+    )
+}
+
+pub fn process_accept_withdrawer(accounts: &[AccountInfo], _data: &[u8]) -> ProgramResult {
+    let [stake_account_info, clock_info, new_withdraw_authority_info, remaining @ ..] = accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let clock = &clock::from_account_info(clock_info)?;
+
+    let custodian = if !remaining.is_empty() {
+        let lockup_authority_info = unsafe { remaining.get_unchecked(0) };
+        if lockup_authority_info.is_signer() {
+            Some(lockup_authority_info.key())
+        } else {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+    } else {
+        None
+    };
+
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::accept_withdrawer(
+        stake_account,
+        extensions,
+        new_withdraw_authority_info.key(),
+        account_signers(core::slice::from_ref(new_withdraw_authority_info)),
+        custodian,
+        clock,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pinocchio::program_error::ProgramError;
+
+    use crate::{
+        state::{DelayedWithdrawerTransfer, StakeAuthorize, StakeHistoryEntry, StakeStateV2},
+        test_utils::*,
+        transition::*,
+    };
+
+    #[test]
+    fn test_delayed_withdrawer_transfer() {
+        let new_withdrawer = [5; 32];
+        let mut extensions = [0; crate::state::extension_space::<DelayedWithdrawerTransfer>()];
+        let mut stake_state = initialized();
+        enable_delayed_withdrawer_transfer(
+            &stake_state,
+            &mut extensions,
+            2,
+            signed_by(WITHDRAWER),
+            &clock(0),
+        )
+        .unwrap();
+        assert_eq!(
+            enable_delayed_withdrawer_transfer(
+                &stake_state,
+                &mut extensions,
+                1,
+                signed_by(WITHDRAWER),
+                &clock(0),
+            ),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        // the withdrawer can no longer be replaced at once, the staker still can
+        assert_eq!(
+            authorize(
+                &mut stake_state,
+                &mut extensions,
+                &new_withdrawer,
+                StakeAuthorize::Withdrawer,
+                signed_by(WITHDRAWER),
+                None,
+                &clock(0),
+            ),
+            Err(ProgramError::InvalidArgument)
+        );
+        authorize(
+            &mut stake_state.clone(),
+            &mut extensions.clone(),
+            &new_withdrawer,
+            StakeAuthorize::Staker,
+            signed_by(WITHDRAWER),
+            None,
+            &clock(0),
+        )
+        .unwrap();
+
+        assert_eq!(
+            propose_withdrawer(
+                &stake_state,
+                &mut extensions,
+                &new_withdrawer,
+                signed_by(STAKER),
+                &clock(1),
+            ),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        propose_withdrawer(
+            &stake_state,
+            &mut extensions,
+            &new_withdrawer,
+            signed_by(WITHDRAWER),
+            &clock(1),
+        )
+        .unwrap();
+
+        // too early, by someone else, then canceled
+        assert_eq!(
+            accept_withdrawer(
+                &mut stake_state,
+                &mut extensions,
+                &new_withdrawer,
+                signed_by(new_withdrawer),
+                None,
+                &clock(2),
+            ),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            accept_withdrawer(
+                &mut stake_state,
+                &mut extensions,
+                &[6; 32],
+                signed_by([6; 32]),
+                None,
+                &clock(3),
+            ),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            accept_withdrawer(
+                &mut stake_state,
+                &mut extensions,
+                &new_withdrawer,
+                signed_by(WITHDRAWER),
+                None,
+                &clock(3),
+            ),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        let mut canceled_extensions = extensions;
+        cancel_withdrawer_proposal(
+            &stake_state,
+            &mut canceled_extensions,
+            signed_by(WITHDRAWER),
+            &clock(2),
+        )
+        .unwrap();
+        assert_eq!(
+            accept_withdrawer(
+                &mut stake_state,
+                &mut canceled_extensions,
+                &new_withdrawer,
+                signed_by(new_withdrawer),
+                None,
+                &clock(3),
+            ),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        // once the delay has passed
+        accept_withdrawer(
+            &mut stake_state,
+            &mut extensions,
+            &new_withdrawer,
+            signed_by(new_withdrawer),
+            None,
+            &clock(3),
+        )
+        .unwrap();
+        let StakeStateV2::Initialized(meta) = &stake_state else {
+            panic!("not initialized");
+        };
+        assert_eq!(meta.authorized.withdrawer, new_withdrawer);
+        let transfer = crate::state::get_extension::<DelayedWithdrawerTransfer>(&extensions)
+            .unwrap()
+            .unwrap();
+        assert_eq!(transfer.pending(), None);
+    }
+
+    #[test]
+    fn test_delayed_withdrawer_transfer_split_merge_and_move() {
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let rent = rent();
+        let space = crate::state::extension_space::<DelayedWithdrawerTransfer>();
+        let with_delay =
+            |delay_epochs| with_extension(DelayedWithdrawerTransfer::new(delay_epochs));
+        let delay_epochs = |extensions: &[u8]| {
+            crate::state::get_extension::<DelayedWithdrawerTransfer>(extensions)
+                .unwrap()
+                .map(|transfer| u64::from(transfer.delay_epochs))
+        };
+
+        // the split destination opts in, without the pending proposal
+        let mut source_extensions = with_delay(3);
+        crate::state::get_extension_mut::<DelayedWithdrawerTransfer>(&mut source_extensions)
+            .unwrap()
+            .unwrap()
+            .propose(&[5; 32], 0);
+        let mut destination_extensions = vec![0; space];
+        split(
+            &mut initialized(),
+            &mut source_extensions.clone(),
+            &[9; 32],
+            4 * RESERVE,
+            &mut StakeStateV2::Uninitialized,
+            &mut destination_extensions,
+            0,
+            2 * RESERVE,
+            signed_by(STAKER),
+            &clock(1),
+            history,
+            &rent,
+        )
+        .unwrap();
+        assert_eq!(destination_extensions, with_delay(3));
+
+        // a destination without room for the opt-in cannot be split into
+        assert_eq!(
+            split(
+                &mut initialized(),
+                &mut source_extensions,
+                &[9; 32],
+                4 * RESERVE,
+                &mut StakeStateV2::Uninitialized,
+                &mut [],
+                0,
+                2 * RESERVE,
+                signed_by(STAKER),
+                &clock(1),
+                history,
+                &rent,
+            ),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+
+        // the merge destination keeps the longer delay
+        let mut destination_extensions = with_delay(1);
+        merge(
+            &mut initialized(),
+            &mut destination_extensions,
+            2 * RESERVE,
+            &mut initialized(),
+            &mut with_delay(3),
+            2 * RESERVE,
+            signed_by(STAKER),
+            &clock(1),
+            history,
+        )
+        .unwrap();
+        assert_eq!(delay_epochs(&destination_extensions), Some(3));
+        assert_eq!(
+            merge(
+                &mut initialized(),
+                &mut [],
+                2 * RESERVE,
+                &mut initialized(),
+                &mut with_delay(3),
+                2 * RESERVE,
+                signed_by(STAKER),
+                &clock(1),
+                history,
+            ),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+
+        // lamports can only move to an account delaying as long
+        assert_eq!(
+            move_lamports(
+                &initialized(),
+                &mut with_delay(3),
+                2 * RESERVE,
+                &initialized(),
+                &mut with_delay(1),
+                RESERVE,
+                1,
+                &STAKER,
+                &clock(1),
+                history,
+            ),
+            Err(ProgramError::Custom(6))
+        );
+        move_lamports(
+            &initialized(),
+            &mut with_delay(1),
+            2 * RESERVE,
+            &initialized(),
+            &mut with_delay(3),
+            RESERVE,
+            1,
+            &STAKER,
+            &clock(1),
+            history,
+        )
+        .unwrap();
+    }
+}
@@ -2,17 +2,20 @@ use pinocchio::{
     account_info::AccountInfo, program_error::ProgramError, sysvars::rent::Rent, ProgramResult,
 };
 
-use crate::state::{get_stake_state_mut, Authorized, Lockup, Meta, StakeStateV2};
+use crate::{
+    state::{get_stake_account_data_mut, Authorized, Lockup, StakeStateV2},
+    transition,
+};
 
 #[repr(C)]
 #[derive(Clone, Debug, PartialEq)]
-struct InitializeArgs {
-    authorized: Authorized,
-    lockup: Lockup,
+pub(crate) struct InitializeArgs {
+    pub(crate) authorized: Authorized,
+    pub(crate) lockup: Lockup,
 }
 
 impl InitializeArgs {
-    fn from_data(data: &[u8]) -> Result<&InitializeArgs, ProgramError> {
+    pub(crate) fn from_data(data: &[u8]) -> Result<&InitializeArgs, ProgramError> {
         if data.len() != core::mem::size_of::<InitializeArgs>() {
             return Err(ProgramError::InvalidInstructionData);
         }
@@ -62,32 +65,38 @@ pub fn process_initialize_checked(accounts: &[AccountInfo], _data: &[u8]) -> Pro
     Ok(())
 }
 
-fn do_initialize(
+pub(crate) fn do_initialize(
     stake_account_info: &AccountInfo,
     authorized: Authorized,
     lockup: Lockup,
     rent: &Rent,
 ) -> ProgramResult {
+    check_stake_account_size(stake_account_info)?;
+
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::initialize(
+        stake_account,
+        extensions,
+        stake_account_info.lamports(),
+        authorized,
+        lockup,
+        rent,
+    )
+}
+
+/// Checks that an account to be initialized can hold the stake state
+#[inline(always)]
+pub(crate) fn check_stake_account_size(stake_account_info: &AccountInfo) -> ProgramResult {
+    #[cfg(not(feature = "extensions"))]
     if stake_account_info.data_len() != StakeStateV2::size_of() {
         return Err(ProgramError::InvalidAccountData);
     }
-    let mut stake_account: pinocchio::account_info::RefMut<'_, StakeStateV2> =
-        get_stake_state_mut(stake_account_info)?;
-
-    match &mut *stake_account {
-        StakeStateV2::Uninitialized => {
-            let rent_exempt_reserve = rent.minimum_balance(stake_account_info.data_len());
-            if stake_account_info.lamports() >= rent_exempt_reserve {
-                *stake_account = StakeStateV2::Initialized(Meta {
-                    rent_exempt_reserve: rent_exempt_reserve.into(),
-                    authorized,
-                    lockup,
-                });
-                Ok(())
-            } else {
-                Err(ProgramError::InsufficientFunds)
-            }
-        }
-        _ => Err(ProgramError::InvalidAccountData),
+    // larger accounts keep their extensions after the stake state
+    #[cfg(feature = "extensions")]
+    if stake_account_info.data_len() < StakeStateV2::size_of() {
+        return Err(ProgramError::InvalidAccountData);
     }
+
+    Ok(())
 }
@@ -0,0 +1,47 @@
+use pinocchio::{
+    account_info::AccountInfo,
+    program_error::ProgramError,
+    sysvars::{rent::Rent, Sysvar},
+    ProgramResult,
+};
+
+use crate::{
+    instruction::{check_stake_account_size, InitializeArgs},
+    pinocchio_add::clock,
+    state::{get_credits, get_stake_account_data_mut, StakeHistorySysvar, StakeStateV2},
+    transition::{self, account_signers},
+};
+
+pub fn process_initialize_and_delegate(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let initialize_args = InitializeArgs::from_data(data)?;
+
+    let [stake_account_info, vote_account_info, clock_info, _stake_history_info, stake_authority_info, _remaining @ ..] =
+        accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let clock = &clock::from_account_info(clock_info)?;
+    let rent = &Rent::get()?;
+
+    let credits = get_credits(vote_account_info)?;
+
+    check_stake_account_size(stake_account_info)?;
+
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::initialize_and_delegate(
+        stake_account,
+        extensions,
+        stake_account_info.lamports(),
+        initialize_args.authorized,
+        initialize_args.lockup,
+        vote_account_info.key(),
+        credits,
+        // the new staker must sign, as it would have to sign a separate DelegateStake
+        account_signers(core::slice::from_ref(stake_authority_info)),
+        clock,
+        &StakeHistorySysvar(clock.epoch),
+        rent,
+    )
+}
@@ -3,7 +3,8 @@ use pinocchio::{account_info::AccountInfo, program_error::ProgramError, ProgramR
 use crate::{
     instruction::relocate_lamports,
     pinocchio_add::clock,
-    state::{get_stake_state_mut, MergeKind, Meta, StakeHistorySysvar, StakeStateV2},
+    state::{get_stake_account_data_mut, StakeHistorySysvar, StakeStateV2},
+    transition::{self, account_signers},
 };
 
 pub fn process_merge(accounts: &[AccountInfo], _data: &[u8]) -> ProgramResult {
@@ -20,41 +21,26 @@ pub fn process_merge(accounts: &[AccountInfo], _data: &[u8]) -> ProgramResult {
         return Err(ProgramError::InvalidArgument);
     }
 
-    let mut destination_stake = get_stake_state_mut(destination_stake_account_info)?;
-
-    #[cfg(feature = "logging")]
-    pinocchio::msg!("Checking if destination stake is mergeable");
-    let destination_merge_kind = MergeKind::get_if_mergeable(
-        &destination_stake,
-        destination_stake_account_info.lamports(),
-        &clock,
-        stake_history,
-    )?;
-
-    // Authorized staker is allowed to split/merge accounts
-    check_signers(accounts, destination_merge_kind.meta())?;
-
-    let mut source_stake = get_stake_state_mut(source_stake_account_info)?;
-
-    #[cfg(feature = "logging")]
-    pinocchio::msg!("Checking if source stake is mergeable");
-    let source_merge_kind = MergeKind::get_if_mergeable(
-        &source_stake,
-        source_stake_account_info.lamports(),
-        &clock,
-        stake_history,
-    )?;
-
-    #[cfg(feature = "logging")]
-    pinocchio::msg!("Merging stake accounts");
-    if let Some(merged_state) = destination_merge_kind.merge(source_merge_kind, &clock)? {
-        // set_stake_state(destination_stake_account_info, &merged_state)?;
-        *destination_stake = merged_state;
+    {
+        let mut destination_data = get_stake_account_data_mut(destination_stake_account_info)?;
+        let (destination_stake, destination_extensions) =
+            StakeStateV2::split_data_mut(&mut destination_data)?;
+        let mut source_data = get_stake_account_data_mut(source_stake_account_info)?;
+        let (source_stake, source_extensions) = StakeStateV2::split_data_mut(&mut source_data)?;
+
+        transition::merge(
+            destination_stake,
+            destination_extensions,
+            destination_stake_account_info.lamports(),
+            source_stake,
+            source_extensions,
+            source_stake_account_info.lamports(),
+            account_signers(accounts),
+            &clock,
+            stake_history,
+        )?;
     }
 
-    // Source is about to be drained, deinitialize its state
-    *source_stake = StakeStateV2::Uninitialized;
-
     // Drain the source stake account
     relocate_lamports(
         source_stake_account_info,
@@ -64,17 +50,3 @@ pub fn process_merge(accounts: &[AccountInfo], _data: &[u8]) -> ProgramResult {
 
     Ok(())
 }
-
-#[inline]
-fn check_signers(accounts: &[AccountInfo], meta: &Meta) -> Result<(), ProgramError> {
-    let mut has_signer = false;
-    for account in accounts {
-        if account.is_signer() && meta.authorized.staker == *account.key() {
-            has_signer = true;
-        }
-    }
-    if !has_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
-    Ok(())
-}
@@ -0,0 +1,214 @@
+use pinocchio::{
+    account_info::AccountInfo, cpi::set_return_data, program_error::ProgramError,
+    sysvars::clock::Clock, ProgramResult,
+};
+
+use crate::{
+    instruction::relocate_lamports,
+    pinocchio_add::clock,
+    state::{get_stake_account_data_mut, get_stake_state, StakeHistorySysvar, StakeStateV2},
+    transition::{account_signers, MergeMany},
+};
+
+/// Merges the trailing sources one after the other. If a source cannot be
+/// merged, its index among the sources is set as return data, as a `u64`, and
+/// the error of its merge is returned.
+pub fn process_merge_many(accounts: &[AccountInfo], _data: &[u8]) -> ProgramResult {
+    let [destination_stake_account_info, clock_info, _stake_history_info, stake_authority_info, source_stake_account_infos @ ..] =
+        accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if source_stake_account_infos.is_empty() {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+
+    let clock = clock::from_account_info(clock_info)?;
+    let stake_history = &StakeHistorySysvar(clock.epoch);
+
+    // Authorized staker is allowed to split/merge accounts
+    let is_signer = account_signers(core::slice::from_ref(stake_authority_info));
+
+    let mut merge_many = MergeMany::new(
+        &*get_stake_state(destination_stake_account_info)?,
+        destination_stake_account_info.lamports(),
+        &is_signer,
+        &clock,
+        stake_history,
+    )?;
+
+    for (index, source_stake_account_info) in source_stake_account_infos.iter().enumerate() {
+        merge_many = merge_source(
+            destination_stake_account_info,
+            merge_many,
+            source_stake_account_info,
+            &clock,
+            stake_history,
+        )
+        .inspect_err(|_| {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Failed to merge source stake account, index in return data");
+            set_return_data(&(index as u64).to_le_bytes());
+        })?;
+    }
+
+    let mut data = get_stake_account_data_mut(destination_stake_account_info)?;
+    let (destination_stake, destination_extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    merge_many.finish(destination_stake, destination_extensions, is_signer, &clock)
+}
+
+/// Merges a single source into the destination and drains it
+#[inline(always)]
+fn merge_source(
+    destination_stake_account_info: &AccountInfo,
+    merge_many: MergeMany,
+    source_stake_account_info: &AccountInfo,
+    clock: &Clock,
+    stake_history: &StakeHistorySysvar,
+) -> Result<MergeMany, ProgramError> {
+    if source_stake_account_info.key() == destination_stake_account_info.key() {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let merge_many = {
+        let mut destination_data = get_stake_account_data_mut(destination_stake_account_info)?;
+        let (_, destination_extensions) = StakeStateV2::split_data_mut(&mut destination_data)?;
+        let mut source_data = get_stake_account_data_mut(source_stake_account_info)?;
+        let (source_stake, source_extensions) = StakeStateV2::split_data_mut(&mut source_data)?;
+
+        merge_many.merge_source(
+            destination_extensions,
+            source_stake,
+            source_extensions,
+            source_stake_account_info.lamports(),
+            clock,
+            stake_history,
+        )?
+    };
+
+    // Drain the source stake account
+    relocate_lamports(
+        source_stake_account_info,
+        destination_stake_account_info,
+        source_stake_account_info.lamports(),
+    )?;
+
+    Ok(merge_many)
+}
+
+#[cfg(test)]
+mod tests {
+    use pinocchio::program_error::ProgramError;
+
+    use crate::{
+        state::{StakeHistoryEntry, StakeStateV2},
+        test_utils::*,
+        transition::*,
+    };
+
+    #[test]
+    fn test_merge_many() {
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let lamports = RESERVE + 1_000;
+        let merge_sources = |sources: &mut [StakeStateV2]| {
+            let mut destination = initialized();
+            let mut merge_many = MergeMany::new(
+                &destination,
+                lamports,
+                signed_by(STAKER),
+                &clock(1),
+                history,
+            )?;
+            for source in sources.iter_mut() {
+                merge_many = merge_many.merge_source(
+                    &mut [],
+                    source,
+                    &mut [],
+                    lamports,
+                    &clock(1),
+                    history,
+                )?;
+            }
+            merge_many.finish(&mut destination, &mut [], signed_by(STAKER), &clock(1))?;
+            Ok::<_, ProgramError>(destination)
+        };
+
+        let mut sources = [initialized(), initialized()];
+        assert_eq!(merge_sources(&mut sources), Ok(initialized()));
+        assert_eq!(
+            sources,
+            [StakeStateV2::Uninitialized, StakeStateV2::Uninitialized]
+        );
+
+        assert_eq!(
+            MergeMany::new(
+                &initialized(),
+                lamports,
+                signed_by(WITHDRAWER),
+                &clock(1),
+                history
+            )
+            .err(),
+            Some(ProgramError::MissingRequiredSignature)
+        );
+
+        // a source with other authorities fails after the ones before it merged
+        let mut mismatched = initialized();
+        if let StakeStateV2::Initialized(meta) = &mut mismatched {
+            meta.authorized.staker = [9; 32];
+        }
+        let mut sources = [initialized(), mismatched];
+        let mut merge_many = MergeMany::new(
+            &initialized(),
+            lamports,
+            signed_by(STAKER),
+            &clock(1),
+            history,
+        )
+        .unwrap();
+        merge_many = merge_many
+            .merge_source(
+                &mut [],
+                &mut sources[0],
+                &mut [],
+                lamports,
+                &clock(1),
+                history,
+            )
+            .unwrap();
+        assert_eq!(
+            merge_many
+                .merge_source(
+                    &mut [],
+                    &mut sources[1],
+                    &mut [],
+                    lamports,
+                    &clock(1),
+                    history
+                )
+                .err(),
+            // StakeError::MergeMismatch
+            Some(ProgramError::Custom(6))
+        );
+
+        // a source listed twice is drained by its first merge
+        let mut source = initialized();
+        let merge_many = MergeMany::new(
+            &initialized(),
+            lamports,
+            signed_by(STAKER),
+            &clock(1),
+            history,
+        )
+        .unwrap()
+        .merge_source(&mut [], &mut source, &mut [], lamports, &clock(1), history)
+        .unwrap();
+        assert_eq!(
+            merge_many
+                .merge_source(&mut [], &mut source, &mut [], lamports, &clock(1), history)
+                .err(),
+            Some(ProgramError::InvalidAccountData)
+        );
+    }
+}
@@ -1,22 +1,62 @@
 pub mod authorize;
+#[cfg(feature = "create-account")]
+pub mod create_account;
 pub mod deactivate;
 pub mod deactivate_delinquent;
+#[cfg(feature = "deactivation-schedule")]
+pub mod deactivation_schedule;
+#[cfg(feature = "delayed-withdrawer-transfer")]
+pub mod delayed_withdrawer_transfer;
 pub mod delegate;
 pub mod initialize;
+#[cfg(feature = "initialize-and-delegate")]
+pub mod initialize_and_delegate;
 pub mod merge;
+#[cfg(feature = "merge-many")]
+pub mod merge_many;
 pub mod move_stake_lamports;
+#[cfg(feature = "recovery-authority")]
+pub mod recovery_authority;
 pub mod set_lockup;
+#[cfg(feature = "metadata")]
+pub mod set_metadata;
+#[cfg(feature = "role-authorities")]
+pub mod set_role_authority;
+#[cfg(feature = "vesting")]
+pub mod set_vesting_schedule;
+#[cfg(feature = "withdraw-allowlist")]
+pub mod set_withdraw_allowlist;
 pub mod split;
 pub mod withdraw;
 
 pub use authorize::*;
+#[cfg(feature = "create-account")]
+pub use create_account::*;
 pub use deactivate::*;
 pub use deactivate_delinquent::*;
+#[cfg(feature = "deactivation-schedule")]
+pub use deactivation_schedule::*;
+#[cfg(feature = "delayed-withdrawer-transfer")]
+pub use delayed_withdrawer_transfer::*;
 pub use delegate::*;
 pub use initialize::*;
+#[cfg(feature = "initialize-and-delegate")]
+pub use initialize_and_delegate::*;
 pub use merge::*;
+#[cfg(feature = "merge-many")]
+pub use merge_many::*;
 pub use move_stake_lamports::*;
+#[cfg(feature = "recovery-authority")]
+pub use recovery_authority::*;
 pub use set_lockup::*;
+#[cfg(feature = "metadata")]
+pub use set_metadata::*;
+#[cfg(feature = "role-authorities")]
+pub use set_role_authority::*;
+#[cfg(feature = "vesting")]
+pub use set_vesting_schedule::*;
+#[cfg(feature = "withdraw-allowlist")]
+pub use set_withdraw_allowlist::*;
 pub use split::*;
 pub use withdraw::*;
 
@@ -0,0 +1,368 @@
+use pinocchio::{
+    account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey, ProgramResult,
+};
+
+use crate::{
+    pinocchio_add::clock,
+    state::{get_stake_account_data_mut, StakeStateV2},
+    transition::{self, account_signers},
+};
+
+pub fn process_set_recovery_authority(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    // recovery authority, then the inactivity period in epochs
+    if data.len() != 32 + 8 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let authority = unsafe { &*(data.as_ptr() as *const Pubkey) };
+    let inactivity_epochs = u64::from_le_bytes(data[32..40].try_into().unwrap());
+
+    let [stake_account_info, clock_info, withdraw_authority_info, _remaining @ ..] = accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let clock = clock::from_account_info(clock_info)?;
+
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::set_recovery_authority(
+        stake_account,
+        extensions,
+        authority,
+        inactivity_epochs,
+        account_signers(core::slice::from_ref(withdraw_authority_info)),
+        &clock,
+    )
+}
+
+pub fn process_recover_withdrawer(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() != 32 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let new_withdrawer = unsafe { &*(data.as_ptr() as *const Pubkey) };
+
+    let [stake_account_info, clock_info, recovery_authority_info, remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let clock = &clock::from_account_info(clock_info)?;
+
+    let custodian = if !remaining.is_empty() {
+        let lockup_authority_info = unsafe { remaining.get_unchecked(0) };
+        if lockup_authority_info.is_signer() {
+            Some(lockup_authority_info.key())
+        } else {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+    } else {
+        None
+    };
+
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::recover_withdrawer(
+        stake_account,
+        extensions,
+        new_withdrawer,
+        account_signers(core::slice::from_ref(recovery_authority_info)),
+        custodian,
+        clock,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pinocchio::program_error::ProgramError;
+
+    #[cfg(feature = "delayed-withdrawer-transfer")]
+    use crate::state::DelayedWithdrawerTransfer;
+    #[cfg(feature = "metadata")]
+    use crate::state::Metadata;
+    #[cfg(feature = "role-authorities")]
+    use crate::state::StakeRole;
+    #[cfg(feature = "vesting")]
+    use crate::state::VestingSchedule;
+    use crate::{
+        instruction::LockupArgs,
+        state::{RecoveryAuthority, StakeHistoryEntry, StakeStateV2},
+        test_utils::*,
+        transition::*,
+    };
+
+    #[test]
+    fn test_set_lockup_and_split_record_activity() {
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let lockup = LockupArgs {
+            unix_timestamp: None,
+            epoch: Some(1.into()),
+            custodian: None,
+        };
+
+        // the custodian alone is no activity of the owners
+        let mut extensions = with_recovery_authority();
+        let mut stake_state = initialized();
+        if let StakeStateV2::Initialized(meta) = &mut stake_state {
+            meta.lockup.epoch = 10.into();
+            meta.lockup.custodian = [6; 32];
+        }
+        set_lockup(
+            &mut stake_state,
+            &mut extensions,
+            &lockup,
+            signed_by([6; 32]),
+            &clock(3),
+        )
+        .unwrap();
+        assert_eq!(last_activity_epoch(&extensions), 0);
+        // the lockup has expired, so the withdrawer may change it
+        set_lockup(
+            &mut stake_state,
+            &mut extensions,
+            &lockup,
+            signed_by(WITHDRAWER),
+            &clock(3),
+        )
+        .unwrap();
+        assert_eq!(last_activity_epoch(&extensions), 3);
+
+        // the split destination starts with the activity of the split
+        let mut source_extensions = with_recovery_authority();
+        let mut destination = StakeStateV2::Uninitialized;
+        let mut destination_extensions =
+            vec![0; crate::state::extension_space::<RecoveryAuthority>()];
+        split(
+            &mut initialized(),
+            &mut source_extensions,
+            &[9; 32],
+            4 * RESERVE,
+            &mut destination,
+            &mut destination_extensions,
+            0,
+            2 * RESERVE,
+            signed_by(STAKER),
+            &clock(5),
+            history,
+            &rent(),
+        )
+        .unwrap();
+        assert_eq!(last_activity_epoch(&source_extensions), 5);
+        assert_eq!(last_activity_epoch(&destination_extensions), 5);
+    }
+
+    #[test]
+    fn test_extension_instructions_record_activity() {
+        let mut extensions = with_recovery_authority();
+
+        // each instruction is run in its own epoch
+
+        #[cfg(feature = "vesting")]
+        {
+            let vesting_schedule = VestingSchedule {
+                amount: 1.into(),
+                start_timestamp: 0.into(),
+                end_timestamp: 1.into(),
+                period: 0.into(),
+            };
+            set_vesting_schedule(
+                &initialized(),
+                &mut extensions,
+                &vesting_schedule,
+                signed_by(WITHDRAWER),
+                None,
+                &clock(1),
+            )
+            .unwrap();
+            assert_eq!(last_activity_epoch(&extensions), 1);
+        }
+        #[cfg(feature = "metadata")]
+        {
+            set_metadata(
+                &initialized(),
+                &mut extensions,
+                Metadata::new(b"name", &[]).unwrap(),
+                signed_by(WITHDRAWER),
+                &clock(2),
+            )
+            .unwrap();
+            assert_eq!(last_activity_epoch(&extensions), 2);
+        }
+        #[cfg(feature = "deactivation-schedule")]
+        {
+            set_deactivation_schedule(
+                &initialized(),
+                &mut extensions,
+                100,
+                signed_by(STAKER),
+                &clock(3),
+            )
+            .unwrap();
+            assert_eq!(last_activity_epoch(&extensions), 3);
+        }
+        #[cfg(feature = "withdraw-allowlist")]
+        {
+            set_withdraw_allowlist(
+                &initialized(),
+                &mut extensions,
+                0,
+                &[[9; 32]],
+                signed_by(WITHDRAWER),
+                &clock(4),
+            )
+            .unwrap();
+            assert_eq!(last_activity_epoch(&extensions), 4);
+        }
+        #[cfg(feature = "role-authorities")]
+        {
+            set_role_authority(
+                &initialized(),
+                &mut extensions,
+                &[5; 32],
+                StakeRole::Deactivator,
+                signed_by(STAKER),
+                &clock(5),
+            )
+            .unwrap();
+            assert_eq!(last_activity_epoch(&extensions), 5);
+        }
+        #[cfg(feature = "delayed-withdrawer-transfer")]
+        {
+            let mut stake_state = initialized();
+            enable_delayed_withdrawer_transfer(
+                &stake_state,
+                &mut extensions,
+                0,
+                signed_by(WITHDRAWER),
+                &clock(6),
+            )
+            .unwrap();
+            assert_eq!(last_activity_epoch(&extensions), 6);
+            propose_withdrawer(
+                &stake_state,
+                &mut extensions,
+                &[5; 32],
+                signed_by(WITHDRAWER),
+                &clock(7),
+            )
+            .unwrap();
+            assert_eq!(last_activity_epoch(&extensions), 7);
+            cancel_withdrawer_proposal(
+                &stake_state,
+                &mut extensions,
+                signed_by(WITHDRAWER),
+                &clock(8),
+            )
+            .unwrap();
+            assert_eq!(last_activity_epoch(&extensions), 8);
+            propose_withdrawer(
+                &stake_state,
+                &mut extensions,
+                &[5; 32],
+                signed_by(WITHDRAWER),
+                &clock(9),
+            )
+            .unwrap();
+            assert_eq!(last_activity_epoch(&extensions), 9);
+            accept_withdrawer(
+                &mut stake_state,
+                &mut extensions,
+                &[5; 32],
+                signed_by([5; 32]),
+                None,
+                &clock(10),
+            )
+            .unwrap();
+            assert_eq!(last_activity_epoch(&extensions), 10);
+        }
+    }
+
+    #[test]
+    fn test_recover_withdrawer() {
+        let new_withdrawer = [5; 32];
+
+        // too early, then once the account has been inactive long enough
+        let mut extensions = with_recovery_authority();
+        let mut stake_state = initialized();
+        assert_eq!(
+            recover_withdrawer(
+                &mut stake_state,
+                &mut extensions,
+                &new_withdrawer,
+                signed_by(RECOVERER),
+                None,
+                &clock(9),
+            ),
+            Err(ProgramError::InvalidArgument)
+        );
+        recover_withdrawer(
+            &mut stake_state,
+            &mut extensions,
+            &new_withdrawer,
+            signed_by(RECOVERER),
+            None,
+            &clock(10),
+        )
+        .unwrap();
+        let StakeStateV2::Initialized(meta) = &stake_state else {
+            panic!("not initialized");
+        };
+        assert_eq!(meta.authorized.withdrawer, new_withdrawer);
+        assert_eq!(last_activity_epoch(&extensions), 10);
+    }
+
+    #[cfg(feature = "delayed-withdrawer-transfer")]
+    #[test]
+    fn test_recover_withdrawer_is_delayed() {
+        let new_withdrawer = [5; 32];
+        let mut extensions = with_recovery_authority();
+        *crate::state::init_extension::<DelayedWithdrawerTransfer>(&mut extensions).unwrap() =
+            DelayedWithdrawerTransfer::new(2);
+
+        // recovery only proposes the new withdrawer
+        let mut stake_state = initialized();
+        recover_withdrawer(
+            &mut stake_state,
+            &mut extensions,
+            &new_withdrawer,
+            signed_by(RECOVERER),
+            None,
+            &clock(10),
+        )
+        .unwrap();
+        assert_eq!(stake_state, initialized());
+
+        // the current withdrawer can still cancel within the delay
+        cancel_withdrawer_proposal(
+            &stake_state,
+            &mut extensions.clone(),
+            signed_by(WITHDRAWER),
+            &clock(11),
+        )
+        .unwrap();
+
+        assert_eq!(
+            accept_withdrawer(
+                &mut stake_state,
+                &mut extensions,
+                &new_withdrawer,
+                signed_by(new_withdrawer),
+                None,
+                &clock(11),
+            ),
+            Err(ProgramError::InvalidArgument)
+        );
+        accept_withdrawer(
+            &mut stake_state,
+            &mut extensions,
+            &new_withdrawer,
+            signed_by(new_withdrawer),
+            None,
+            &clock(12),
+        )
+        .unwrap();
+        let StakeStateV2::Initialized(meta) = &stake_state else {
+            panic!("not initialized");
+        };
+        assert_eq!(meta.authorized.withdrawer, new_withdrawer);
+        assert_eq!(last_activity_epoch(&extensions), 12);
+    }
+}
@@ -6,8 +6,11 @@ use pinocchio::{
     ProgramResult,
 };
 
-use crate::state::{
-    get_stake_state, get_stake_state_mut, Epoch, SetLockupSignerArgs, StakeStateV2, UnixTimestamp,
+#[cfg(feature = "set-lockup-with-seed")]
+use crate::pinocchio_add::pubkey::create_with_seed;
+use crate::{
+    state::{get_stake_account_data_mut, Epoch, StakeStateV2, UnixTimestamp},
+    transition::{self, account_signers},
 };
 
 #[repr(C)]
@@ -181,11 +184,14 @@ pub fn process_set_lockup(accounts: &[AccountInfo], data: &[u8]) -> ProgramResul
         return Err(ProgramError::NotEnoughAccountKeys);
     };
 
-    let signer_args = get_set_lockup_signer_args(stake_account_info, accounts)?;
-
     let clock = Clock::get()?;
 
-    do_set_lookup(stake_account_info, &lockup_args, signer_args, &clock)?;
+    do_set_lookup(
+        stake_account_info,
+        &lockup_args,
+        account_signers(accounts),
+        &clock,
+    )?;
 
     Ok(())
 }
@@ -211,64 +217,102 @@ pub fn process_set_lockup_checked(accounts: &[AccountInfo], data: &[u8]) -> Prog
         None
     };
 
-    let signer_args = get_set_lockup_signer_args(stake_account_info, accounts)?;
-
     let lockup_args = LockupArgs {
         unix_timestamp: lockup_checked_args.unix_timestamp,
         epoch: lockup_checked_args.epoch,
         custodian: custodian.copied(),
     };
 
-    do_set_lookup(stake_account_info, &lockup_args, signer_args, &clock)?;
+    do_set_lookup(
+        stake_account_info,
+        &lockup_args,
+        account_signers(accounts),
+        &clock,
+    )?;
 
     Ok(())
 }
 
-fn do_set_lookup(
-    stake_account_info: &AccountInfo,
-    lockup: &LockupArgs,
-    signer_args: SetLockupSignerArgs,
-    clock: &Clock,
-) -> ProgramResult {
-    let mut stake_account = get_stake_state_mut(stake_account_info)?;
-    match &mut *stake_account {
-        StakeStateV2::Initialized(meta) => meta.set_lockup(lockup, signer_args, clock),
-        StakeStateV2::Stake(meta, _stake, _stake_flags) => {
-            meta.set_lockup(lockup, signer_args, clock)
-        }
-        _ => Err(ProgramError::InvalidAccountData),
-    }
+#[cfg(feature = "set-lockup-with-seed")]
+#[derive(Debug, PartialEq)]
+struct SetLockupWithSeedArgs<'a> {
+    authority_seed: &'a [u8],
+    authority_owner: &'a Pubkey,
+    lockup_args: LockupArgs,
 }
 
-fn get_set_lockup_signer_args(
-    stake_account_info: &AccountInfo,
-    accounts: &[AccountInfo],
-) -> Result<SetLockupSignerArgs, ProgramError> {
-    let stake_account = get_stake_state(stake_account_info)?;
-
-    let mut has_custodian_signer = false;
-    let mut has_withdrawer_signer = false;
-    match *stake_account {
-        StakeStateV2::Initialized(ref meta) | StakeStateV2::Stake(ref meta, _, _) => {
-            for account in accounts {
-                if account.is_signer() {
-                    if meta.lockup.custodian == *account.key() {
-                        has_custodian_signer = true;
-                    }
-                    if meta.authorized.withdrawer == *account.key() {
-                        has_withdrawer_signer = true;
-                    }
-                }
-            }
+#[cfg(feature = "set-lockup-with-seed")]
+impl SetLockupWithSeedArgs<'_> {
+    #[inline(always)]
+    fn from_data(data: &[u8]) -> Result<SetLockupWithSeedArgs<'_>, ProgramError> {
+        // seed length | seed | owner | lockup args
+        if data.len() < 8 + 32 {
+            return Err(ProgramError::InvalidInstructionData);
         }
-        _ => {
-            return Err(ProgramError::InvalidAccountData);
+        let seed_size = u64::from_le_bytes(data[0..8].try_into().unwrap());
+        let seed_end = 8usize
+            .checked_add(seed_size as usize)
+            .ok_or(ProgramError::InvalidInstructionData)?;
+        if data.len().saturating_sub(32) < seed_end {
+            return Err(ProgramError::InvalidInstructionData);
         }
+        let authority_seed = &data[8..seed_end];
+        let authority_owner = unsafe { &*(data.as_ptr().add(seed_end) as *const Pubkey) };
+
+        let lockup_args = LockupArgs::from_data(&data[seed_end + 32..])?;
+
+        Ok(SetLockupWithSeedArgs {
+            authority_seed,
+            authority_owner,
+            lockup_args,
+        })
     }
-    Ok(SetLockupSignerArgs {
-        has_custodian_signer,
-        has_withdrawer_signer,
-    })
+}
+
+#[cfg(feature = "set-lockup-with-seed")]
+pub fn process_set_lockup_with_seed(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let SetLockupWithSeedArgs {
+        authority_seed,
+        authority_owner,
+        lockup_args,
+    } = SetLockupWithSeedArgs::from_data(data)?;
+
+    let [stake_account_info, withdraw_or_lockup_authority_base_info, _remaining @ ..] = accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let withdraw_or_lockup_auth = if withdraw_or_lockup_authority_base_info.is_signer() {
+        Some(create_with_seed(
+            withdraw_or_lockup_authority_base_info.key(),
+            authority_seed,
+            authority_owner,
+        )?)
+    } else {
+        None
+    };
+
+    // the derived address signs through its base
+    let is_signer = account_signers(accounts);
+    let is_signer =
+        |pubkey: &Pubkey| is_signer(pubkey) || withdraw_or_lockup_auth.as_ref() == Some(pubkey);
+
+    let clock = Clock::get()?;
+
+    do_set_lookup(stake_account_info, &lockup_args, is_signer, &clock)?;
+
+    Ok(())
+}
+
+fn do_set_lookup(
+    stake_account_info: &AccountInfo,
+    lockup: &LockupArgs,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    clock: &Clock,
+) -> ProgramResult {
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::set_lockup(stake_account, extensions, lockup, is_signer, clock)
 }
 
 #[cfg(test)]
@@ -368,4 +412,59 @@ mod test {
             assert_eq!(args, args_new);
         }
     }
+
+    #[cfg(feature = "set-lockup-with-seed")]
+    #[test]
+    fn test_set_lockup_with_seed_args() {
+        use super::SetLockupWithSeedArgs;
+        use pinocchio::program_error::ProgramError;
+
+        let lockup_args = LockupArgs {
+            unix_timestamp: None,
+            epoch: Some(5u64.into()),
+            custodian: None,
+        };
+        let set_lockup_with_seed_data = |seed_size: u64, seed: &[u8]| {
+            let mut data = seed_size.to_le_bytes().to_vec();
+            data.extend_from_slice(seed);
+            data.extend_from_slice(&[7; 32]);
+            data.extend_from_slice(&serialize(&lockup_args).unwrap());
+            data
+        };
+
+        assert_eq!(
+            SetLockupWithSeedArgs::from_data(&set_lockup_with_seed_data(4, b"seed")),
+            Ok(SetLockupWithSeedArgs {
+                authority_seed: b"seed",
+                authority_owner: &[7; 32],
+                lockup_args,
+            })
+        );
+        assert_eq!(
+            SetLockupWithSeedArgs::from_data(&set_lockup_with_seed_data(0, b"")),
+            Ok(SetLockupWithSeedArgs {
+                authority_seed: b"",
+                authority_owner: &[7; 32],
+                lockup_args,
+            })
+        );
+
+        // seed sizes reaching into the lockup args, past the data or overflowing
+        assert_eq!(
+            SetLockupWithSeedArgs::from_data(&set_lockup_with_seed_data(5, b"seed")),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(
+            SetLockupWithSeedArgs::from_data(&set_lockup_with_seed_data(1_000, b"seed")),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(
+            SetLockupWithSeedArgs::from_data(&set_lockup_with_seed_data(u64::MAX - 3, b"seed")),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(
+            SetLockupWithSeedArgs::from_data(&set_lockup_with_seed_data(u64::MAX, b"seed")),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
 }
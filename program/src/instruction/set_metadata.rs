@@ -0,0 +1,132 @@
+use pinocchio::{
+    account_info::AccountInfo,
+    program_error::ProgramError,
+    sysvars::{clock::Clock, Sysvar},
+    ProgramResult,
+};
+
+use crate::{
+    state::{get_stake_account_data_mut, Metadata, StakeStateV2},
+    transition::{self, account_signers},
+};
+
+pub fn process_set_metadata(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    // name length, name, then the encoded entries
+    let Some((name_len, rest)) = data.split_first() else {
+        return Err(ProgramError::InvalidInstructionData);
+    };
+    if rest.len() < *name_len as usize {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let (name, entries) = rest.split_at(*name_len as usize);
+    let metadata = Metadata::new(name, entries)?;
+
+    let [stake_account_info, withdraw_authority_info, _remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let clock = Clock::get()?;
+
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::set_metadata(
+        stake_account,
+        extensions,
+        metadata,
+        account_signers(core::slice::from_ref(withdraw_authority_info)),
+        &clock,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pinocchio::program_error::ProgramError;
+
+    use crate::{
+        state::{Metadata, StakeHistoryEntry, StakeStateV2},
+        test_utils::*,
+        transition::*,
+    };
+
+    #[test]
+    fn test_set_metadata() {
+        use crate::state::get_metadata;
+
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let space = crate::state::extension_space::<Metadata>();
+        let mut extensions = vec![0; space];
+
+        // only the withdrawer sets the metadata
+        let metadata = Metadata::new(b"name", &[3, b'k', b'e', b'y', 1, b'v']).unwrap();
+        assert_eq!(
+            set_metadata(
+                &initialized(),
+                &mut extensions,
+                metadata.clone(),
+                signed_by(STAKER),
+                &clock(1),
+            ),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        set_metadata(
+            &initialized(),
+            &mut extensions,
+            metadata.clone(),
+            signed_by(WITHDRAWER),
+            &clock(1),
+        )
+        .unwrap();
+        let stored = get_metadata(&extensions).unwrap().unwrap();
+        assert_eq!(stored.name(), b"name");
+        assert_eq!(stored.get(b"key"), Some(&b"v"[..]));
+
+        // setting it again replaces it in place
+        let renamed = Metadata::new(b"other name", &[]).unwrap();
+        set_metadata(
+            &initialized(),
+            &mut extensions,
+            renamed.clone(),
+            signed_by(WITHDRAWER),
+            &clock(1),
+        )
+        .unwrap();
+        assert_eq!(get_metadata(&extensions), Ok(Some(&renamed)));
+
+        // the split destination gets a copy if it has room for it
+        let rent = rent();
+        let split_into = |destination_extensions: &mut [u8]| {
+            split(
+                &mut initialized(),
+                &mut extensions.clone(),
+                &[9; 32],
+                4 * RESERVE,
+                &mut StakeStateV2::Uninitialized,
+                destination_extensions,
+                0,
+                2 * RESERVE,
+                signed_by(STAKER),
+                &clock(1),
+                history,
+                &rent,
+            )
+        };
+        let mut destination_extensions = vec![0; space];
+        split_into(&mut destination_extensions).unwrap();
+        assert_eq!(get_metadata(&destination_extensions), Ok(Some(&renamed)));
+        let mut destination_extensions = [];
+        split_into(&mut destination_extensions).unwrap();
+        assert_eq!(get_metadata(&destination_extensions), Ok(None));
+
+        // an empty name without entries removes it
+        set_metadata(
+            &initialized(),
+            &mut extensions,
+            Metadata::new(b"", &[]).unwrap(),
+            signed_by(WITHDRAWER),
+            &clock(1),
+        )
+        .unwrap();
+        assert_eq!(get_metadata(&extensions), Ok(None));
+        assert_eq!(extensions, vec![0; space]);
+    }
+}
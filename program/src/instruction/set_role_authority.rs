@@ -0,0 +1,217 @@
+use pinocchio::{
+    account_info::AccountInfo,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvars::{clock::Clock, Sysvar},
+    ProgramResult,
+};
+
+use crate::{
+    state::{get_stake_account_data_mut, StakeRole, StakeStateV2},
+    transition::{self, account_signers},
+};
+
+pub fn process_set_role_authority(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    // new authority, then the role as a u32
+    if data.len() != 32 + 4 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let new_authority = unsafe { &*(data.as_ptr() as *const Pubkey) };
+    let role = match u32::from_le_bytes(data[32..36].try_into().unwrap()) {
+        0 => StakeRole::Deactivator,
+        1 => StakeRole::Rebalancer,
+        _ => return Err(ProgramError::InvalidInstructionData),
+    };
+
+    let [stake_account_info, _remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let clock = Clock::get()?;
+
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::set_role_authority(
+        stake_account,
+        extensions,
+        new_authority,
+        role,
+        account_signers(accounts),
+        &clock,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pinocchio::{program_error::ProgramError, pubkey::Pubkey, sysvars::rent::Rent};
+
+    use crate::{
+        state::{RoleAuthorities, StakeHistoryEntry, StakeRole, StakeStateV2},
+        test_utils::*,
+        transition::*,
+    };
+
+    #[test]
+    fn test_role_authorities() {
+        const DEACTIVATOR: Pubkey = [5; 32];
+        const REBALANCER: Pubkey = [6; 32];
+
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let active = delegated(RESERVE + 1_000, 0);
+        let mut extensions = vec![0; crate::state::extension_space::<RoleAuthorities>()];
+
+        // the staker or the withdrawer grants roles, a role holder cannot
+        set_role_authority(
+            &active,
+            &mut extensions,
+            &DEACTIVATOR,
+            StakeRole::Deactivator,
+            signed_by(STAKER),
+            &clock(1),
+        )
+        .unwrap();
+        set_role_authority(
+            &active,
+            &mut extensions,
+            &REBALANCER,
+            StakeRole::Rebalancer,
+            signed_by(WITHDRAWER),
+            &clock(1),
+        )
+        .unwrap();
+        for role_authority in [DEACTIVATOR, REBALANCER] {
+            assert_eq!(
+                set_role_authority(
+                    &active,
+                    &mut extensions.clone(),
+                    &role_authority,
+                    StakeRole::Rebalancer,
+                    signed_by(role_authority),
+                    &clock(1),
+                ),
+                Err(ProgramError::MissingRequiredSignature)
+            );
+        }
+
+        // only the deactivator deactivates
+        let mut stake_state = active.clone();
+        deactivate(
+            &mut stake_state,
+            &mut extensions,
+            signed_by(DEACTIVATOR),
+            &clock(1),
+        )
+        .unwrap();
+        assert_eq!(
+            deactivate(
+                &mut active.clone(),
+                &mut extensions,
+                signed_by(REBALANCER),
+                &clock(1),
+            ),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+
+        // only the rebalancer moves stake and lamports, and only between accounts
+        // with the same authorities
+        let move_stake_signed_by = |destination: StakeStateV2, authority: &Pubkey| {
+            move_stake(
+                &mut active.clone(),
+                &mut extensions.clone(),
+                RESERVE + 1_000,
+                &mut destination.clone(),
+                &mut [],
+                RESERVE,
+                1_000,
+                authority,
+                &clock(1),
+                history,
+            )
+        };
+        let move_lamports_signed_by = |destination: StakeStateV2, authority: &Pubkey| {
+            move_lamports(
+                &active,
+                &mut extensions.clone(),
+                RESERVE + 1_500,
+                &destination,
+                &mut [],
+                RESERVE,
+                500,
+                authority,
+                &clock(1),
+                history,
+            )
+        };
+        let mut other_staker = initialized();
+        if let StakeStateV2::Initialized(meta) = &mut other_staker {
+            meta.authorized.staker = [7; 32];
+        }
+        assert_eq!(move_stake_signed_by(initialized(), &REBALANCER), Ok(()));
+        assert_eq!(move_lamports_signed_by(initialized(), &REBALANCER), Ok(()));
+        assert_eq!(
+            move_stake_signed_by(other_staker.clone(), &REBALANCER),
+            Err(ProgramError::Custom(6))
+        );
+        assert_eq!(
+            move_lamports_signed_by(other_staker, &REBALANCER),
+            Err(ProgramError::Custom(6))
+        );
+        assert_eq!(
+            move_stake_signed_by(initialized(), &DEACTIVATOR),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        assert_eq!(
+            move_lamports_signed_by(initialized(), &DEACTIVATOR),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+
+        // neither role stands in for the staker or the withdrawer otherwise
+        for role_authority in [DEACTIVATOR, REBALANCER] {
+            assert_eq!(
+                delegate(
+                    &mut initialized(),
+                    &mut extensions.clone(),
+                    RESERVE + 1_000,
+                    &VOTER,
+                    0,
+                    signed_by(role_authority),
+                    &clock(1),
+                    history,
+                ),
+                Err(ProgramError::MissingRequiredSignature)
+            );
+            assert_eq!(
+                split(
+                    &mut active.clone(),
+                    &mut extensions.clone(),
+                    &[9; 32],
+                    RESERVE + 1_000,
+                    &mut StakeStateV2::Uninitialized,
+                    &mut [],
+                    0,
+                    500,
+                    signed_by(role_authority),
+                    &clock(1),
+                    history,
+                    &Rent::default(),
+                ),
+                Err(ProgramError::MissingRequiredSignature)
+            );
+            assert_eq!(
+                withdraw(
+                    &mut initialized(),
+                    &mut extensions.clone(),
+                    &[9; 32],
+                    2 * RESERVE,
+                    RESERVE,
+                    &[8; 32],
+                    &role_authority,
+                    None,
+                    &clock(1),
+                    history,
+                ),
+                Err(ProgramError::MissingRequiredSignature)
+            );
+        }
+    }
+}
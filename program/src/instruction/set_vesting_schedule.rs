@@ -0,0 +1,181 @@
+use pinocchio::{account_info::AccountInfo, program_error::ProgramError, ProgramResult};
+
+use crate::{
+    pinocchio_add::clock,
+    state::{get_stake_account_data_mut, StakeStateV2, VestingSchedule},
+    transition::{self, account_signers},
+};
+
+pub fn process_set_vesting_schedule(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() != core::mem::size_of::<VestingSchedule>() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let vesting_schedule = unsafe { &*(data.as_ptr() as *const VestingSchedule) };
+
+    let [stake_account_info, clock_info, withdraw_authority_info, remaining @ ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let clock = clock::from_account_info(clock_info)?;
+
+    let custodian = match remaining.first() {
+        Some(custodian_info) if custodian_info.is_signer() => Some(custodian_info.key()),
+        Some(_) => return Err(ProgramError::MissingRequiredSignature),
+        None => None,
+    };
+
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::set_vesting_schedule(
+        stake_account,
+        extensions,
+        vesting_schedule,
+        account_signers(core::slice::from_ref(withdraw_authority_info)),
+        custodian,
+        &clock,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pinocchio::{program_error::ProgramError, pubkey::Pubkey};
+
+    use crate::{
+        instruction::LockupArgs,
+        state::{extension_space, StakeHistoryEntry, StakeStateV2, VestingSchedule},
+        test_utils::*,
+        transition::*,
+    };
+
+    #[test]
+    fn test_withdraw_keeps_unvested_lamports() {
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let lamports = RESERVE + 1_000;
+
+        let mut extensions = with_extension(VestingSchedule {
+            amount: 1.into(),
+            start_timestamp: 0.into(),
+            end_timestamp: 1.into(),
+            period: 0.into(),
+        });
+
+        // unvested lamports stay, unless the custodian signs
+        assert_eq!(
+            withdraw(
+                &mut initialized(),
+                &mut extensions,
+                &[9; 32],
+                lamports,
+                1_000,
+                &[8; 32],
+                &WITHDRAWER,
+                None,
+                &clock(2),
+                history
+            ),
+            Err(ProgramError::InsufficientFunds)
+        );
+        assert_eq!(
+            withdraw(
+                &mut initialized(),
+                &mut extensions,
+                &[9; 32],
+                lamports,
+                999,
+                &[8; 32],
+                &WITHDRAWER,
+                None,
+                &clock(2),
+                history
+            ),
+            Ok(999)
+        );
+        assert_eq!(
+            withdraw(
+                &mut initialized(),
+                &mut extensions,
+                &[9; 32],
+                lamports,
+                1_000,
+                &[8; 32],
+                &WITHDRAWER,
+                Some(&Pubkey::default()),
+                &clock(2),
+                history
+            ),
+            Ok(1_000)
+        );
+    }
+
+    #[test]
+    fn test_set_lockup_keeps_custodian_while_unvested() {
+        let lamports = RESERVE + 1_000;
+        let mut extensions = with_extension(VestingSchedule {
+            amount: 1_000.into(),
+            start_timestamp: 0.into(),
+            end_timestamp: 1.into(),
+            period: 0.into(),
+        });
+        let custodian = [4; 32];
+        let mut stake_state = initialized();
+        if let StakeStateV2::Initialized(meta) = &mut stake_state {
+            meta.lockup.custodian = custodian;
+        }
+        let make_withdrawer_custodian = LockupArgs {
+            unix_timestamp: None,
+            epoch: None,
+            custodian: Some(WITHDRAWER),
+        };
+
+        // the lockup expired, but the withdrawer cannot take over the custodian
+        // to release the unvested lamports
+        assert_eq!(
+            set_lockup(
+                &mut stake_state,
+                &mut extensions,
+                &make_withdrawer_custodian,
+                signed_by(WITHDRAWER),
+                &clock(2)
+            ),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        assert_eq!(
+            withdraw(
+                &mut stake_state.clone(),
+                &mut extensions,
+                &[9; 32],
+                lamports,
+                1_000,
+                &[8; 32],
+                &WITHDRAWER,
+                Some(&WITHDRAWER),
+                &clock(2),
+                &[] as &[(u64, StakeHistoryEntry)]
+            ),
+            Err(ProgramError::InsufficientFunds)
+        );
+
+        // the custodian still can
+        set_lockup(
+            &mut stake_state,
+            &mut extensions,
+            &make_withdrawer_custodian,
+            signed_by(custodian),
+            &clock(2),
+        )
+        .unwrap();
+
+        // once everything is vested, the withdrawer sets the lockup again
+        let mut vested_extensions = [0; extension_space::<VestingSchedule>()];
+        assert_eq!(
+            set_lockup(
+                &mut initialized(),
+                &mut vested_extensions,
+                &make_withdrawer_custodian,
+                signed_by(WITHDRAWER),
+                &clock(2)
+            ),
+            Ok(())
+        );
+    }
+}
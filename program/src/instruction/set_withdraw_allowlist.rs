@@ -0,0 +1,167 @@
+use pinocchio::{
+    account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey, ProgramResult,
+};
+
+use crate::{
+    pinocchio_add::clock,
+    state::{get_stake_account_data_mut, StakeStateV2, MAX_ALLOWED_DESTINATIONS},
+    transition::{self, account_signers},
+};
+
+pub fn process_set_withdraw_allowlist(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    // timelock, destination count, then the destinations
+    if data.len() < 8 + 1 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let timelock_epochs = u64::from_le_bytes(data[0..8].try_into().unwrap());
+    let destinations_len = data[8] as usize;
+    if destinations_len > MAX_ALLOWED_DESTINATIONS || data.len() != 9 + destinations_len * 32 {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+    let destinations = unsafe {
+        core::slice::from_raw_parts(data.as_ptr().add(9) as *const Pubkey, destinations_len)
+    };
+
+    let [stake_account_info, clock_info, withdraw_authority_info, _remaining @ ..] = accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    let clock = clock::from_account_info(clock_info)?;
+
+    let mut data = get_stake_account_data_mut(stake_account_info)?;
+    let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+    transition::set_withdraw_allowlist(
+        stake_account,
+        extensions,
+        timelock_epochs,
+        destinations,
+        account_signers(core::slice::from_ref(withdraw_authority_info)),
+        &clock,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pinocchio::{program_error::ProgramError, pubkey::Pubkey};
+
+    use crate::{
+        state::{StakeHistoryEntry, WithdrawAllowlist},
+        test_utils::*,
+        transition::*,
+    };
+
+    #[test]
+    fn test_withdraw_allowlist() {
+        use crate::state::{WITHDRAW_ALLOWLIST_MISMATCH, WITHDRAW_DESTINATION_NOT_ALLOWED};
+
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let space = crate::state::extension_space::<WithdrawAllowlist>();
+        let with_allowlist = |destinations: &[Pubkey]| {
+            let mut extensions = vec![0; space];
+            set_withdraw_allowlist(
+                &initialized(),
+                &mut extensions,
+                2,
+                destinations,
+                signed_by(WITHDRAWER),
+                &clock(1),
+            )
+            .unwrap();
+            extensions
+        };
+
+        // only allowed destinations can be withdrawn to
+        let withdraw_to = |destination: &Pubkey| {
+            withdraw(
+                &mut initialized(),
+                &mut with_allowlist(&[[5; 32]]),
+                &[9; 32],
+                2 * RESERVE,
+                RESERVE,
+                destination,
+                &WITHDRAWER,
+                None,
+                &clock(1),
+                history,
+            )
+        };
+        assert_eq!(withdraw_to(&[5; 32]), Ok(RESERVE));
+        assert_eq!(
+            withdraw_to(&[6; 32]),
+            Err(ProgramError::Custom(WITHDRAW_DESTINATION_NOT_ALLOWED))
+        );
+
+        // merging requires the same allowlist
+        let merge_into = |destination_extensions: &mut [u8]| {
+            merge(
+                &mut initialized(),
+                destination_extensions,
+                2 * RESERVE,
+                &mut initialized(),
+                &mut with_allowlist(&[[5; 32]]),
+                2 * RESERVE,
+                signed_by(STAKER),
+                &clock(1),
+                history,
+            )
+        };
+        assert_eq!(merge_into(&mut with_allowlist(&[[5; 32]])), Ok(()));
+        assert_eq!(
+            merge_into(&mut with_allowlist(&[[6; 32]])),
+            Err(ProgramError::Custom(WITHDRAW_ALLOWLIST_MISMATCH))
+        );
+        assert_eq!(
+            merge_into(&mut vec![0; space]),
+            Err(ProgramError::Custom(WITHDRAW_ALLOWLIST_MISMATCH))
+        );
+
+        // and so does moving lamports or stake
+        let move_lamports_into = |destination_extensions: &mut [u8]| {
+            move_lamports(
+                &initialized(),
+                &mut with_allowlist(&[[5; 32]]),
+                2 * RESERVE,
+                &initialized(),
+                destination_extensions,
+                RESERVE,
+                1,
+                &STAKER,
+                &clock(1),
+                history,
+            )
+        };
+        assert_eq!(move_lamports_into(&mut with_allowlist(&[[5; 32]])), Ok(()));
+        assert_eq!(
+            move_lamports_into(&mut with_allowlist(&[[6; 32]])),
+            Err(ProgramError::Custom(WITHDRAW_ALLOWLIST_MISMATCH))
+        );
+        assert_eq!(
+            move_lamports_into(&mut vec![0; space]),
+            Err(ProgramError::Custom(WITHDRAW_ALLOWLIST_MISMATCH))
+        );
+
+        let move_stake_into = |destination_extensions: &mut [u8]| {
+            move_stake(
+                &mut delegated(2 * RESERVE, 0),
+                &mut with_allowlist(&[[5; 32]]),
+                4 * RESERVE,
+                &mut initialized(),
+                destination_extensions,
+                RESERVE,
+                RESERVE,
+                &STAKER,
+                &clock(1),
+                history,
+            )
+        };
+        assert_eq!(
+            move_stake_into(&mut with_allowlist(&[[6; 32]])),
+            Err(ProgramError::Custom(WITHDRAW_ALLOWLIST_MISMATCH))
+        );
+        assert_eq!(
+            move_stake_into(&mut vec![0; space]),
+            Err(ProgramError::Custom(WITHDRAW_ALLOWLIST_MISMATCH))
+        );
+    }
+}
@@ -1,13 +1,20 @@
+#[cfg(feature = "withdraw-max")]
+use pinocchio::cpi::set_return_data;
 use pinocchio::{account_info::AccountInfo, program_error::ProgramError, ProgramResult};
 
 use crate::{
     pinocchio_add::clock,
-    state::{get_stake_state_mut, StakeHistorySysvar, StakeStateV2},
-    PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH,
+    state::{get_stake_account_data_mut, StakeHistorySysvar, StakeStateV2},
+    transition,
 };
 
 use super::relocate_lamports;
 
+/// Withdraw amount that requests the maximum amount currently permitted to be
+/// withdrawn. The withdrawn amount is returned via return data.
+#[cfg(feature = "withdraw-max")]
+pub const WITHDRAW_MAX_LAMPORTS: u64 = u64::MAX;
+
 pub fn process_withdraw(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
     if data.len() < 8 {
         return Err(ProgramError::InvalidInstructionData);
@@ -41,106 +48,109 @@ pub fn process_withdraw(accounts: &[AccountInfo], data: &[u8]) -> ProgramResult
 
     let stake_history = &StakeHistorySysvar(clock.epoch);
 
-    let mut stake_account = get_stake_state_mut(source_stake_account_info)?;
-
-    let (reserve, is_staked) = match &*stake_account {
-        StakeStateV2::Stake(meta, stake, _stake_flag) => {
-            if let Some(custodian) = custodian {
-                if meta.authorized.withdrawer != *custodian
-                    && meta.authorized.withdrawer != *withdraw_authority
-                {
-                    return Err(ProgramError::MissingRequiredSignature);
-                }
-            } else if meta.authorized.withdrawer != *withdraw_authority {
-                return Err(ProgramError::MissingRequiredSignature);
-            }
-
-            // if we have a deactivation epoch and we're in cooldown
-            let staked = if clock.epoch >= u64::from(stake.delegation.deactivation_epoch) {
-                stake.delegation.get_effective_stake(
-                    clock.epoch,
-                    stake_history,
-                    PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH,
-                )
-            } else {
-                // Assume full stake if the stake account hasn't been
-                //  de-activated, because in the future the exposed stake
-                //  might be higher than stake.stake() due to warmup
-                stake.delegation.stake.into()
-            };
-            let staked_and_reserve = staked
-                .checked_add(meta.rent_exempt_reserve.into())
-                .ok_or(ProgramError::InsufficientFunds)?;
-
-            // verify that lockup has expired or that the withdrawal is signed by the
-            // custodian both epoch and unix_timestamp must have passed
-            if meta.lockup.is_in_force(clock, custodian) {
-                // StakeError::LockupInForce
-                return Err(ProgramError::Custom(1));
-            }
-
-            (staked_and_reserve, staked != 0)
-        }
-        StakeStateV2::Initialized(meta) => {
-            if let Some(custodian) = custodian {
-                if meta.authorized.withdrawer != *custodian
-                    && meta.authorized.withdrawer != *withdraw_authority
-                {
-                    return Err(ProgramError::MissingRequiredSignature);
-                }
-            } else if meta.authorized.withdrawer != *withdraw_authority {
-                return Err(ProgramError::MissingRequiredSignature);
-            }
-            // verify that lockup has expired or that the withdrawal is signed by the
-            // custodian both epoch and unix_timestamp must have passed
-            if meta.lockup.is_in_force(clock, custodian) {
-                // StakeError::LockupInForce
-                return Err(ProgramError::Custom(1));
-            }
-
-            // stake accounts must have a balance >= rent_exempt_reserve
-            (meta.rent_exempt_reserve.into(), false)
-        }
-        StakeStateV2::Uninitialized => {
-            if let Some(custodian) = custodian {
-                if *source_stake_account_info.key() != *custodian
-                    && *source_stake_account_info.key() != *withdraw_authority
-                {
-                    return Err(ProgramError::MissingRequiredSignature);
-                }
-            } else if *source_stake_account_info.key() != *withdraw_authority {
-                return Err(ProgramError::MissingRequiredSignature);
-            }
-
-            (0, false)
-        }
-        _ => return Err(ProgramError::InvalidAccountData),
+    let withdrawn_lamports = {
+        let mut data = get_stake_account_data_mut(source_stake_account_info)?;
+        let (stake_account, extensions) = StakeStateV2::split_data_mut(&mut data)?;
+        transition::withdraw(
+            stake_account,
+            extensions,
+            source_stake_account_info.key(),
+            source_stake_account_info.lamports(),
+            withdraw_lamports,
+            destination_info.key(),
+            withdraw_authority,
+            custodian,
+            clock,
+            stake_history,
+        )?
     };
 
-    let stake_account_lamports = source_stake_account_info.lamports();
-    if withdraw_lamports == stake_account_lamports {
-        // if the stake is active, we mustn't allow the account to go away
-        if is_staked {
-            return Err(ProgramError::InsufficientFunds);
-        }
-
-        // Deinitialize state upon zero balance
-        *stake_account = StakeStateV2::Uninitialized;
-    } else {
-        // a partial withdrawal must not deplete the reserve
-        let withdraw_lamports_and_reserve = withdraw_lamports
-            .checked_add(reserve)
-            .ok_or(ProgramError::InsufficientFunds)?;
-        if withdraw_lamports_and_reserve > stake_account_lamports {
-            return Err(ProgramError::InsufficientFunds);
-        }
+    #[cfg(feature = "withdraw-max")]
+    if withdraw_lamports == WITHDRAW_MAX_LAMPORTS {
+        set_return_data(&withdrawn_lamports.to_le_bytes());
     }
 
     relocate_lamports(
         source_stake_account_info,
         destination_info,
-        withdraw_lamports,
+        withdrawn_lamports,
     )?;
 
     Ok(())
 }
+
+#[cfg(all(test, feature = "withdraw-max"))]
+mod tests {
+    #[cfg(feature = "vesting")]
+    use crate::state::VestingSchedule;
+    use crate::{
+        state::{StakeHistoryEntry, StakeStateV2},
+        test_utils::*,
+        transition::*,
+    };
+
+    use super::WITHDRAW_MAX_LAMPORTS;
+
+    #[test]
+    fn test_withdraw_max() {
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let withdraw_max = |stake_state: &mut StakeStateV2, extensions: &mut [u8], lamports| {
+            withdraw(
+                stake_state,
+                extensions,
+                &[9; 32],
+                lamports,
+                WITHDRAW_MAX_LAMPORTS,
+                &[8; 32],
+                &WITHDRAWER,
+                None,
+                &clock(2),
+                history,
+            )
+        };
+
+        // an account without stake is closed
+        let mut stake_state = initialized();
+        assert_eq!(
+            withdraw_max(&mut stake_state, &mut [], 3 * RESERVE),
+            Ok(3 * RESERVE)
+        );
+        assert_eq!(stake_state, StakeStateV2::Uninitialized);
+
+        // a delegated account keeps its reserve and the staked amount
+        let mut stake_state = delegated(3 * RESERVE, 0);
+        let delegated_state = stake_state.clone();
+        assert_eq!(
+            withdraw_max(&mut stake_state, &mut [], 3 * RESERVE + 1_000),
+            Ok(1_000)
+        );
+        assert_eq!(stake_state, delegated_state);
+        assert_eq!(withdraw_max(&mut stake_state, &mut [], 3 * RESERVE), Ok(0));
+
+        // so does a deactivated one until it has cooled down
+        let mut stake_state = delegated(3 * RESERVE, 0);
+        deactivate(&mut stake_state, &mut [], signed_by(STAKER), &clock(1)).unwrap();
+        assert_eq!(
+            withdraw_max(&mut stake_state, &mut [], 3 * RESERVE),
+            Ok(3 * RESERVE)
+        );
+        assert_eq!(stake_state, StakeStateV2::Uninitialized);
+
+        // unvested lamports stay in the account, which cannot be closed
+        #[cfg(feature = "vesting")]
+        {
+            let mut extensions = with_extension(VestingSchedule {
+                amount: 1_000.into(),
+                start_timestamp: 0.into(),
+                end_timestamp: 1.into(),
+                period: 0.into(),
+            });
+            let mut stake_state = initialized();
+            assert_eq!(
+                withdraw_max(&mut stake_state, &mut extensions, 3 * RESERVE),
+                Ok(2 * RESERVE - 1_000)
+            );
+            assert_eq!(stake_state, initialized());
+        }
+    }
+}
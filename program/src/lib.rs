@@ -1,10 +1,20 @@
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 
 mod entrypoint;
 
+#[cfg(any(test, feature = "std"))]
+pub mod aggregate;
 pub mod instruction;
 pub mod pinocchio_add;
+#[cfg(kani)]
+mod proofs;
+pub mod query;
+#[cfg(any(test, feature = "std"))]
+pub mod simulator;
 pub mod state;
+#[cfg(test)]
+mod test_utils;
+pub mod transition;
 
 pinocchio_pubkey::declare_id!("Stake11111111111111111111111111111111111111");
 
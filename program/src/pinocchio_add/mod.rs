@@ -1,4 +1,6 @@
 pub mod clock;
 pub mod epoch_rewards;
 pub mod pubkey;
+#[cfg(feature = "create-account")]
+pub mod system;
 pub mod sysvar;
@@ -0,0 +1,77 @@
+use pinocchio::{
+    account_info::AccountInfo,
+    cpi::invoke,
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::{Pubkey, MAX_SEED_LEN},
+    ProgramResult,
+};
+use pinocchio_pubkey::pubkey;
+
+pub const SYSTEM_PROGRAM_ID: Pubkey = pubkey!("11111111111111111111111111111111");
+
+/// Creates a new account via `SystemInstruction::CreateAccount`, both `from` and
+/// `to` must sign
+pub fn create_account(
+    from: &AccountInfo,
+    to: &AccountInfo,
+    lamports: u64,
+    space: u64,
+    owner: &Pubkey,
+) -> ProgramResult {
+    // discriminant (0) | lamports | space | owner
+    let mut data = [0u8; 4 + 8 + 8 + 32];
+    data[4..12].copy_from_slice(&lamports.to_le_bytes());
+    data[12..20].copy_from_slice(&space.to_le_bytes());
+    data[20..52].copy_from_slice(owner);
+
+    let instruction = Instruction {
+        program_id: &SYSTEM_PROGRAM_ID,
+        accounts: &[
+            AccountMeta::writable_signer(from.key()),
+            AccountMeta::writable_signer(to.key()),
+        ],
+        data: &data,
+    };
+
+    invoke(&instruction, &[from, to])
+}
+
+/// Creates a new account at an address derived from `base`, `seed` and `owner`
+/// via `SystemInstruction::CreateAccountWithSeed`, both `from` and `base` must sign
+pub fn create_account_with_seed(
+    from: &AccountInfo,
+    to: &AccountInfo,
+    base: &AccountInfo,
+    seed: &[u8],
+    lamports: u64,
+    space: u64,
+    owner: &Pubkey,
+) -> ProgramResult {
+    if seed.len() > MAX_SEED_LEN {
+        return Err(ProgramError::MaxSeedLengthExceeded);
+    }
+
+    // discriminant (3) | base | seed length | seed | lamports | space | owner
+    let mut data = [0u8; 4 + 32 + 8 + MAX_SEED_LEN + 8 + 8 + 32];
+    data[0] = 3;
+    data[4..36].copy_from_slice(base.key());
+    data[36..44].copy_from_slice(&(seed.len() as u64).to_le_bytes());
+    let offset = 44 + seed.len();
+    data[44..offset].copy_from_slice(seed);
+    data[offset..offset + 8].copy_from_slice(&lamports.to_le_bytes());
+    data[offset + 8..offset + 16].copy_from_slice(&space.to_le_bytes());
+    data[offset + 16..offset + 48].copy_from_slice(owner);
+
+    let instruction = Instruction {
+        program_id: &SYSTEM_PROGRAM_ID,
+        accounts: &[
+            AccountMeta::writable_signer(from.key()),
+            AccountMeta::writable(to.key()),
+            AccountMeta::readonly_signer(base.key()),
+        ],
+        data: &data[..offset + 48],
+    };
+
+    invoke(&instruction, &[from, to, base])
+}
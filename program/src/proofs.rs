@@ -0,0 +1,413 @@
+//! Kani harnesses proving, for bounded inputs, the lamport and stake invariants
+//! the processors rely on: lamports are conserved, accounts keep their rent
+//! exempt reserve and delegated stake never exceeds the balance minus the
+//! reserve. They exercise the `transition` functions the processors call; the
+//! account plumbing around them only moves the lamports these functions
+//! compute.
+//!
+//! Run with `cargo kani`.
+
+use pinocchio::sysvars::{clock::Clock, rent::Rent};
+
+use crate::{
+    state::{Authorized, Delegation, Lockup, Meta, Stake, StakeHistoryEntry, StakeStateV2},
+    transition::{merge, move_lamports, move_stake, split, withdraw},
+};
+
+/// Bound keeping the solver tractable, still far above any rent exempt reserve
+const MAX_LAMPORTS: u64 = 1 << 40;
+
+/// Smaller bound for harnesses going through the 128-bit credits arithmetic
+const MAX_CREDITS: u64 = 1 << 16;
+
+fn any_below(bound: u64) -> u64 {
+    let value: u64 = kani::any();
+    kani::assume(value <= bound);
+    value
+}
+
+fn clock(epoch: u64) -> Clock {
+    Clock {
+        slot: 0,
+        epoch_start_timestamp: 0,
+        epoch,
+        leader_schedule_epoch: epoch,
+        unix_timestamp: 0,
+    }
+}
+
+fn meta(rent_exempt_reserve: u64) -> Meta {
+    Meta {
+        rent_exempt_reserve: rent_exempt_reserve.into(),
+        authorized: Authorized {
+            staker: [1; 32],
+            withdrawer: [2; 32],
+        },
+        lockup: Lockup::default(),
+    }
+}
+
+fn stake(amount: u64, credits_observed: u64) -> Stake {
+    Stake {
+        delegation: Delegation::new(&[3; 32], amount.into(), 0.into()),
+        credits_observed: credits_observed.into(),
+    }
+}
+
+/// `Split` of initialized or delegated stake into an account whose reserve
+/// follows from the rent, independently of the reserve of the source
+#[kani::proof]
+fn split_conserves_lamports_and_bounds_stake() {
+    let source_reserve = any_below(MAX_LAMPORTS);
+    let source_lamports = any_below(MAX_LAMPORTS);
+    let source_stake = any_below(MAX_LAMPORTS);
+    let is_source_delegated: bool = kani::any();
+    let destination_lamports = any_below(MAX_LAMPORTS);
+    let split_lamports = any_below(MAX_LAMPORTS);
+    let rent = Rent {
+        lamports_per_byte_year: any_below(MAX_LAMPORTS >> 10),
+        exemption_threshold: 2.0,
+        burn_percent: 50,
+    };
+    let destination_reserve = rent.minimum_balance(StakeStateV2::size_of());
+
+    // the source satisfies the invariant before the split
+    kani::assume(source_stake <= source_lamports.saturating_sub(source_reserve));
+    kani::assume(source_lamports >= source_reserve);
+
+    let mut source = if is_source_delegated {
+        StakeStateV2::Stake(
+            meta(source_reserve),
+            stake(source_stake, 0),
+            Default::default(),
+        )
+    } else {
+        StakeStateV2::Initialized(meta(source_reserve))
+    };
+    let mut destination = StakeStateV2::Uninitialized;
+
+    let history: &[(u64, StakeHistoryEntry)] = &[];
+    if split(
+        &mut source,
+        &mut [],
+        &[9; 32],
+        source_lamports,
+        &mut destination,
+        &mut [],
+        destination_lamports,
+        split_lamports,
+        |_| true,
+        &clock(1),
+        history,
+        &rent,
+    )
+    .is_err()
+    {
+        return;
+    }
+
+    // the split lamports leave the source for the destination
+    let source_final_lamports = source_lamports - split_lamports;
+    let destination_final_lamports = destination_lamports + split_lamports;
+
+    assert!(destination_final_lamports >= destination_reserve);
+    match destination {
+        StakeStateV2::Stake(meta, stake, _) => {
+            assert_eq!(u64::from(meta.rent_exempt_reserve), destination_reserve);
+            let destination_stake = u64::from(stake.delegation.stake);
+            if source_final_lamports == 0 {
+                // the whole stake moves, backed by the lamports above the reserve of
+                // the source like upstream, even if the destination needs a larger one
+                assert!(destination_stake <= destination_final_lamports - source_reserve);
+            } else {
+                assert!(destination_stake <= destination_final_lamports - destination_reserve);
+            }
+        }
+        StakeStateV2::Initialized(meta) => {
+            assert!(!is_source_delegated);
+            assert_eq!(u64::from(meta.rent_exempt_reserve), destination_reserve);
+        }
+        _ => panic!("destination is neither delegated nor initialized"),
+    }
+
+    // the source keeps its reserve and the remaining stake, unless it is closed
+    match source {
+        StakeStateV2::Uninitialized => assert_eq!(source_final_lamports, 0),
+        StakeStateV2::Stake(_, stake, _) => {
+            assert!(source_final_lamports >= source_reserve);
+            assert!(u64::from(stake.delegation.stake) <= source_final_lamports - source_reserve);
+        }
+        StakeStateV2::Initialized(_) => assert!(source_final_lamports >= source_reserve),
+        _ => panic!("source is neither delegated, initialized nor closed"),
+    }
+}
+
+/// `Merge` of initialized, activating or fully active accounts with
+/// independent reserves
+#[kani::proof]
+fn merge_conserves_stake_and_bounds_credits() {
+    let destination_reserve = any_below(MAX_CREDITS);
+    let destination_lamports = any_below(MAX_CREDITS);
+    let destination_stake = any_below(MAX_CREDITS);
+    let destination_credits = any_below(MAX_CREDITS);
+    let destination_activation_epoch = any_below(1);
+    let is_destination_delegated: bool = kani::any();
+    let source_reserve = any_below(MAX_CREDITS);
+    let source_lamports = any_below(MAX_CREDITS);
+    let source_stake = any_below(MAX_CREDITS);
+    let source_credits = any_below(MAX_CREDITS);
+    let source_activation_epoch = any_below(1);
+    let is_source_delegated: bool = kani::any();
+
+    kani::assume(destination_stake <= destination_lamports.saturating_sub(destination_reserve));
+    kani::assume(destination_lamports >= destination_reserve);
+    kani::assume(source_stake <= source_lamports.saturating_sub(source_reserve));
+    kani::assume(source_lamports >= source_reserve);
+
+    let delegated = |reserve, amount, credits, activation_epoch| {
+        let mut stake = stake(amount, credits);
+        stake.delegation.activation_epoch = u64::into(activation_epoch);
+        StakeStateV2::Stake(meta(reserve), stake, Default::default())
+    };
+    let mut destination = if is_destination_delegated {
+        delegated(
+            destination_reserve,
+            destination_stake,
+            destination_credits,
+            destination_activation_epoch,
+        )
+    } else {
+        StakeStateV2::Initialized(meta(destination_reserve))
+    };
+    let mut source = if is_source_delegated {
+        delegated(
+            source_reserve,
+            source_stake,
+            source_credits,
+            source_activation_epoch,
+        )
+    } else {
+        StakeStateV2::Initialized(meta(source_reserve))
+    };
+
+    let history: &[(u64, StakeHistoryEntry)] = &[];
+    if merge(
+        &mut destination,
+        &mut [],
+        destination_lamports,
+        &mut source,
+        &mut [],
+        source_lamports,
+        |_| true,
+        &clock(1),
+        history,
+    )
+    .is_err()
+    {
+        return;
+    }
+
+    // the source is drained into the destination, which keeps its reserve
+    assert_eq!(source, StakeStateV2::Uninitialized);
+    let merged_lamports = destination_lamports + source_lamports;
+
+    // an initialized destination absorbs an activating source as lamports
+    let StakeStateV2::Stake(meta, merged_stake, _) = destination else {
+        assert!(!is_destination_delegated);
+        return;
+    };
+    assert_eq!(u64::from(meta.rent_exempt_reserve), destination_reserve);
+    assert!(u64::from(merged_stake.delegation.stake) <= merged_lamports - destination_reserve);
+
+    // the credits observed are those of the merged stakes, or in between
+    let (min_credits, max_credits) = match (is_destination_delegated, is_source_delegated) {
+        (true, true) => (
+            destination_credits.min(source_credits),
+            destination_credits.max(source_credits),
+        ),
+        (true, false) => (destination_credits, destination_credits),
+        _ => (source_credits, source_credits),
+    };
+    let merged_credits = u64::from(merged_stake.credits_observed);
+    assert!(merged_credits >= min_credits);
+    assert!(merged_credits <= max_credits);
+}
+
+/// `MoveStake` from a fully active account to an active or inactive one, as
+/// in `process_move_stake`
+#[kani::proof]
+fn move_stake_keeps_reserves() {
+    let source_reserve = any_below(MAX_CREDITS);
+    let source_lamports = any_below(MAX_CREDITS);
+    let source_stake = any_below(MAX_CREDITS);
+    let source_credits = any_below(MAX_CREDITS);
+    let destination_reserve = any_below(MAX_CREDITS);
+    let destination_lamports = any_below(MAX_CREDITS);
+    let destination_stake = any_below(MAX_CREDITS);
+    let destination_credits = any_below(MAX_CREDITS);
+    let is_destination_active: bool = kani::any();
+    let lamports = any_below(MAX_CREDITS);
+
+    kani::assume(source_stake <= source_lamports.saturating_sub(source_reserve));
+    kani::assume(source_lamports >= source_reserve);
+    kani::assume(destination_stake <= destination_lamports.saturating_sub(destination_reserve));
+    kani::assume(destination_lamports >= destination_reserve);
+
+    let mut source = StakeStateV2::Stake(
+        meta(source_reserve),
+        stake(source_stake, source_credits),
+        Default::default(),
+    );
+    let mut destination = if is_destination_active {
+        StakeStateV2::Stake(
+            meta(destination_reserve),
+            stake(destination_stake, destination_credits),
+            Default::default(),
+        )
+    } else {
+        StakeStateV2::Initialized(meta(destination_reserve))
+    };
+
+    let history: &[(u64, StakeHistoryEntry)] = &[];
+    if move_stake(
+        &mut source,
+        &mut [],
+        source_lamports,
+        &mut destination,
+        &mut [],
+        destination_lamports,
+        lamports,
+        &[1; 32],
+        &clock(1),
+        history,
+    )
+    .is_err()
+    {
+        return;
+    }
+
+    // the moved lamports leave the source along with the stake
+    let source_final_lamports = source_lamports - lamports;
+    let destination_final_lamports = destination_lamports + lamports;
+
+    assert!(source_final_lamports >= source_reserve);
+    match source {
+        StakeStateV2::Stake(_, stake, _) => {
+            assert!(u64::from(stake.delegation.stake) <= source_final_lamports - source_reserve);
+        }
+        StakeStateV2::Initialized(_) => assert_eq!(lamports, source_stake),
+        _ => panic!("source is neither delegated nor initialized"),
+    }
+
+    let StakeStateV2::Stake(_, destination_final_stake, _) = destination else {
+        panic!("destination is not delegated");
+    };
+    assert!(destination_final_lamports >= destination_reserve);
+    assert!(
+        u64::from(destination_final_stake.delegation.stake)
+            <= destination_final_lamports - destination_reserve
+    );
+}
+
+/// `MoveLamports` from a fully active or inactive account, as in
+/// `process_move_lamports`
+#[kani::proof]
+fn move_lamports_keeps_reserve_and_stake() {
+    let reserve = any_below(MAX_LAMPORTS);
+    let source_lamports = any_below(MAX_LAMPORTS);
+    let source_stake = any_below(MAX_LAMPORTS);
+    let is_source_active: bool = kani::any();
+    let destination_lamports = any_below(MAX_LAMPORTS);
+    let lamports = any_below(MAX_LAMPORTS);
+
+    kani::assume(source_stake <= source_lamports.saturating_sub(reserve));
+    kani::assume(source_lamports >= reserve);
+    kani::assume(destination_lamports >= reserve);
+
+    let source = if is_source_active {
+        StakeStateV2::Stake(meta(reserve), stake(source_stake, 0), Default::default())
+    } else {
+        StakeStateV2::Initialized(meta(reserve))
+    };
+
+    let history: &[(u64, StakeHistoryEntry)] = &[];
+    if move_lamports(
+        &source,
+        &mut [],
+        source_lamports,
+        &StakeStateV2::Initialized(meta(reserve)),
+        &mut [],
+        destination_lamports,
+        lamports,
+        &[1; 32],
+        &clock(1),
+        history,
+    )
+    .is_err()
+    {
+        return;
+    }
+
+    let source_final_lamports = source_lamports - lamports;
+    assert!(source_final_lamports >= reserve);
+    if is_source_active {
+        assert!(source_stake <= source_final_lamports - reserve);
+    }
+}
+
+/// `Withdraw` of any amount from initialized or delegated stake, active or
+/// deactivated
+#[kani::proof]
+fn withdraw_keeps_reserve_and_stake() {
+    let reserve = any_below(MAX_LAMPORTS);
+    let lamports = any_below(MAX_LAMPORTS);
+    let staked = any_below(MAX_LAMPORTS);
+    let withdraw_lamports: u64 = kani::any();
+    let is_delegated: bool = kani::any();
+    let is_deactivated: bool = kani::any();
+
+    kani::assume(staked <= lamports.saturating_sub(reserve));
+    kani::assume(lamports >= reserve);
+
+    let mut stake_state = if is_delegated {
+        let mut delegated_stake = stake(staked, 0);
+        if is_deactivated {
+            // fully deactivated without history
+            delegated_stake.delegation.deactivation_epoch = 1.into();
+        }
+        StakeStateV2::Stake(meta(reserve), delegated_stake, Default::default())
+    } else {
+        StakeStateV2::Initialized(meta(reserve))
+    };
+    let is_staked = is_delegated && !is_deactivated && staked != 0;
+
+    let history: &[(u64, StakeHistoryEntry)] = &[];
+    let Ok(withdrawn) = withdraw(
+        &mut stake_state,
+        &mut [],
+        &[9; 32],
+        lamports,
+        withdraw_lamports,
+        &[8; 32],
+        &[2; 32],
+        None,
+        &clock(2),
+        history,
+    ) else {
+        return;
+    };
+
+    assert!(withdrawn <= lamports);
+    let remaining_lamports = lamports - withdrawn;
+    // an account is either closed or keeps its reserve, and the stake unless it
+    // is deactivated
+    if remaining_lamports == 0 {
+        assert!(!is_staked);
+        assert_eq!(stake_state, StakeStateV2::Uninitialized);
+    } else {
+        assert!(remaining_lamports >= reserve);
+        if is_staked {
+            assert!(staked <= remaining_lamports - reserve);
+        }
+    }
+}
@@ -0,0 +1,744 @@
+//! Pure functions answering the questions clients usually ask before building
+//! an instruction: how much can be withdrawn, how much can be split and whether
+//! two accounts can be merged. They apply the same rules as the processors and
+//! take every sysvar as an argument, so they can be used off chain.
+
+use pinocchio::{
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvars::{clock::Clock, rent::Rent},
+};
+
+use crate::{
+    state::{MergeKind, StakeHistoryGetEntry, StakeStateV2},
+    transition::get_staked_and_reserve,
+    PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH,
+};
+
+#[cfg(feature = "extensions")]
+use crate::state::check_merge_extensions;
+#[cfg(feature = "delayed-withdrawer-transfer")]
+use crate::state::DelayedWithdrawerTransfer;
+#[cfg(feature = "vesting")]
+use crate::state::VestingSchedule;
+#[cfg(feature = "withdraw-allowlist")]
+use crate::state::WithdrawAllowlist;
+#[cfg(any(
+    feature = "delayed-withdrawer-transfer",
+    feature = "vesting",
+    feature = "withdraw-allowlist"
+))]
+use crate::state::{extension_space, get_extension};
+
+/// Reason why two stake accounts cannot be merged
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CannotMergeReason {
+    /// The destination is neither initialized nor delegated
+    InvalidDestination,
+    /// The source is neither initialized nor delegated
+    InvalidSource,
+    /// The destination is activating or deactivating with non-zero effective stake
+    TransientDestination,
+    /// The source is activating or deactivating with non-zero effective stake
+    TransientSource,
+    /// Authorities, lockups, voters or activation states do not match
+    Mismatch,
+    /// The merged stake does not fit into a `u64`
+    ArithmeticOverflow,
+    /// Withdraw allowlists or the timing of vesting schedules do not match
+    ExtensionMismatch,
+    /// The destination has no room for the extensions it takes over from the
+    /// source
+    DestinationTooSmall,
+    /// The extension area of either account is malformed
+    InvalidExtensions,
+}
+
+/// Maximum amount of lamports that can currently be withdrawn from a stake
+/// account holding `stake_lamports`. Returns zero while the lockup is in force
+/// and `custodian` is not the lockup custodian.
+///
+/// The extensions in `extensions`, the extension area of the account, restrict
+/// it further: unvested lamports stay in the account unless `custodian` is the
+/// lockup custodian, and nothing can be withdrawn to a `destination` the
+/// withdraw allowlist does not allow. The allowlist is not checked without a
+/// `destination`.
+#[allow(clippy::too_many_arguments)]
+pub fn max_withdrawable<T: StakeHistoryGetEntry + ?Sized>(
+    stake_state: &StakeStateV2,
+    extensions: &[u8],
+    stake_lamports: u64,
+    destination: Option<&Pubkey>,
+    clock: &Clock,
+    stake_history: &T,
+    custodian: Option<&Pubkey>,
+) -> Result<u64, ProgramError> {
+    let (meta, staked_and_reserve, is_staked) = match stake_state {
+        StakeStateV2::Stake(meta, stake, _stake_flags) => {
+            let (staked_and_reserve, is_staked) =
+                get_staked_and_reserve(meta, stake, clock, stake_history)?;
+            (meta, staked_and_reserve, is_staked)
+        }
+        StakeStateV2::Initialized(meta) => (meta, meta.rent_exempt_reserve.into(), false),
+        StakeStateV2::Uninitialized => return Ok(stake_lamports),
+        _ => return Err(ProgramError::InvalidAccountData),
+    };
+
+    if meta.lockup.is_in_force(clock, custodian) {
+        return Ok(0);
+    }
+
+    #[cfg(feature = "withdraw-allowlist")]
+    if let (Some(destination), Some(allowlist)) =
+        (destination, get_extension::<WithdrawAllowlist>(extensions)?)
+    {
+        if !allowlist.is_allowed(destination, clock.epoch) {
+            return Ok(0);
+        }
+    }
+    #[cfg(not(feature = "withdraw-allowlist"))]
+    let _ = destination;
+
+    #[cfg(feature = "vesting")]
+    let unvested_lamports = get_extension::<VestingSchedule>(extensions)?
+        .map(|vesting_schedule| vesting_schedule.unvested_amount(clock))
+        .unwrap_or(0);
+    #[cfg(not(feature = "vesting"))]
+    let unvested_lamports = {
+        let _ = extensions;
+        0
+    };
+
+    // unvested lamports may be staked, but must not leave the account, which
+    // therefore cannot be closed either
+    if unvested_lamports != 0 && custodian != Some(&meta.lockup.custodian) {
+        let vesting_reserve = u64::from(meta.rent_exempt_reserve).saturating_add(unvested_lamports);
+        return Ok(stake_lamports.saturating_sub(staked_and_reserve.max(vesting_reserve)));
+    }
+
+    // an account with active stake must not go away
+    if is_staked {
+        Ok(stake_lamports.saturating_sub(staked_and_reserve))
+    } else {
+        Ok(stake_lamports)
+    }
+}
+
+/// Maximum amount of lamports that can be split from a stake account holding
+/// `source_lamports` into a destination of `destination_data_len` bytes holding
+/// `destination_lamports`, while keeping the source account open. Returns zero
+/// if no such split is possible. Splitting the whole balance is a separate case,
+/// permitted whenever the destination ends up with at least the minimum
+/// delegation.
+///
+/// The extensions in `extensions`, the extension area of the source account,
+/// must fit into the destination: the withdraw allowlist, the delayed withdrawer transfer
+/// and the vesting schedule while it has unvested lamports. Returns zero if the
+/// destination is too small for them.
+#[allow(clippy::too_many_arguments)]
+pub fn max_split<T: StakeHistoryGetEntry + ?Sized>(
+    stake_state: &StakeStateV2,
+    extensions: &[u8],
+    source_lamports: u64,
+    destination_lamports: u64,
+    destination_data_len: usize,
+    clock: &Clock,
+    stake_history: &T,
+    rent: &Rent,
+) -> Result<u64, ProgramError> {
+    if destination_data_len < split_destination_space(extensions, clock)? {
+        return Ok(0);
+    }
+
+    let destination_rent_exempt_reserve = rent.minimum_balance(destination_data_len);
+    let destination_deficit = destination_rent_exempt_reserve.saturating_sub(destination_lamports);
+
+    let (max_split_lamports, min_split_lamports) = match stake_state {
+        StakeStateV2::Stake(meta, stake, _stake_flags) => {
+            let minimum_delegation = crate::get_minimum_delegation();
+
+            let is_active = stake.delegation.get_effective_stake(
+                clock.epoch,
+                stake_history,
+                PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH,
+            ) > 0;
+
+            // active stake can only be partially split into a prefunded destination
+            if is_active && destination_lamports < destination_rent_exempt_reserve {
+                return Ok(0);
+            }
+
+            // the source must retain its reserve and the minimum delegation, both in
+            // lamports and in delegated stake
+            let max_split_lamports = source_lamports
+                .saturating_sub(meta.rent_exempt_reserve.into())
+                .saturating_sub(minimum_delegation)
+                .min(u64::from(stake.delegation.stake).saturating_sub(minimum_delegation));
+
+            // the destination must be brought up to its reserve and the minimum delegation
+            let min_split_lamports = destination_deficit
+                .saturating_add(minimum_delegation)
+                .max(1);
+
+            (max_split_lamports, min_split_lamports)
+        }
+        StakeStateV2::Initialized(meta) => (
+            source_lamports.saturating_sub(meta.rent_exempt_reserve.into()),
+            destination_deficit.max(1),
+        ),
+        StakeStateV2::Uninitialized => (source_lamports, 1),
+        _ => return Err(ProgramError::InvalidAccountData),
+    };
+
+    if max_split_lamports < min_split_lamports {
+        Ok(0)
+    } else {
+        Ok(max_split_lamports)
+    }
+}
+
+/// Size a split destination needs to hold the extensions `Split` must carry
+/// over from a source with the extension area `extensions`. The vesting schedule is assumed to
+/// be carried over while it has unvested lamports, even though a small enough
+/// share of it may round down to nothing.
+fn split_destination_space(_extensions: &[u8], _clock: &Clock) -> Result<usize, ProgramError> {
+    #[allow(unused_mut)]
+    let mut space = StakeStateV2::size_of();
+
+    #[cfg(feature = "vesting")]
+    if get_extension::<VestingSchedule>(_extensions)?
+        .is_some_and(|vesting_schedule| vesting_schedule.unvested_amount(_clock) != 0)
+    {
+        space += extension_space::<VestingSchedule>();
+    }
+    #[cfg(feature = "delayed-withdrawer-transfer")]
+    if get_extension::<DelayedWithdrawerTransfer>(_extensions)?.is_some() {
+        space += extension_space::<DelayedWithdrawerTransfer>();
+    }
+    #[cfg(feature = "withdraw-allowlist")]
+    if get_extension::<WithdrawAllowlist>(_extensions)?.is_some() {
+        space += extension_space::<WithdrawAllowlist>();
+    }
+
+    Ok(space)
+}
+
+/// Checks whether `source` can be merged into `destination` and returns the
+/// merge kind the destination would have afterwards. The extension areas of
+/// both accounts are checked like `Merge` does.
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(not(feature = "extensions"), allow(unused_variables))]
+pub fn can_merge<T: StakeHistoryGetEntry + ?Sized>(
+    destination_stake_state: &StakeStateV2,
+    destination_extensions: &[u8],
+    destination_lamports: u64,
+    source_stake_state: &StakeStateV2,
+    source_extensions: &[u8],
+    source_lamports: u64,
+    clock: &Clock,
+    stake_history: &T,
+) -> Result<MergeKind, CannotMergeReason> {
+    let destination_merge_kind = MergeKind::get_if_mergeable(
+        destination_stake_state,
+        destination_lamports,
+        clock,
+        stake_history,
+    )
+    .map_err(|error| match error {
+        ProgramError::Custom(5) => CannotMergeReason::TransientDestination,
+        _ => CannotMergeReason::InvalidDestination,
+    })?;
+
+    let source_merge_kind =
+        MergeKind::get_if_mergeable(source_stake_state, source_lamports, clock, stake_history)
+            .map_err(|error| match error {
+                ProgramError::Custom(5) => CannotMergeReason::TransientSource,
+                _ => CannotMergeReason::InvalidSource,
+            })?;
+
+    let merge_kind = destination_merge_kind
+        .merge_kind(source_merge_kind, clock)
+        .map_err(|error| match error {
+            ProgramError::Custom(6) => CannotMergeReason::Mismatch,
+            _ => CannotMergeReason::ArithmeticOverflow,
+        })?;
+
+    #[cfg(feature = "extensions")]
+    check_merge_extensions(destination_extensions, source_extensions, clock).map_err(|error| {
+        match error {
+            ProgramError::Custom(_) => CannotMergeReason::ExtensionMismatch,
+            ProgramError::AccountDataTooSmall => CannotMergeReason::DestinationTooSmall,
+            _ => CannotMergeReason::InvalidExtensions,
+        }
+    })?;
+
+    Ok(merge_kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::{
+        Authorized, Delegation, Lockup, Meta, Stake, StakeFlags, StakeHistoryEntry,
+    };
+
+    use super::*;
+
+    const RESERVE: u64 = 2_282_880;
+
+    fn clock(epoch: u64) -> Clock {
+        Clock {
+            slot: 0,
+            epoch_start_timestamp: 0,
+            epoch,
+            leader_schedule_epoch: epoch,
+            unix_timestamp: 0,
+        }
+    }
+
+    fn meta() -> Meta {
+        Meta {
+            rent_exempt_reserve: RESERVE.into(),
+            authorized: Authorized {
+                staker: [1; 32],
+                withdrawer: [2; 32],
+            },
+            lockup: Lockup::default(),
+        }
+    }
+
+    fn stake(voter: Pubkey, amount: u64, activation_epoch: u64) -> Stake {
+        Stake {
+            delegation: Delegation::new(&voter, amount.into(), activation_epoch.into()),
+            credits_observed: 0.into(),
+        }
+    }
+
+    #[test]
+    fn test_max_withdrawable() {
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let lamports = RESERVE + 1_000;
+
+        let initialized = StakeStateV2::Initialized(meta());
+        assert_eq!(
+            max_withdrawable(&initialized, &[], lamports, None, &clock(1), history, None),
+            Ok(lamports)
+        );
+
+        // delegated stake without history is fully active
+        let delegated = StakeStateV2::Stake(meta(), stake([3; 32], 600, 0), StakeFlags::empty());
+        assert_eq!(
+            max_withdrawable(&delegated, &[], lamports, None, &clock(1), history, None),
+            Ok(400)
+        );
+
+        let mut locked_meta = meta();
+        locked_meta.lockup.epoch = 10.into();
+        locked_meta.lockup.custodian = [4; 32];
+        let locked = StakeStateV2::Initialized(locked_meta);
+        assert_eq!(
+            max_withdrawable(&locked, &[], lamports, None, &clock(1), history, None),
+            Ok(0)
+        );
+        assert_eq!(
+            max_withdrawable(
+                &locked,
+                &[],
+                lamports,
+                None,
+                &clock(1),
+                history,
+                Some(&[4; 32])
+            ),
+            Ok(lamports)
+        );
+    }
+
+    #[test]
+    fn test_max_split() {
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let rent = Rent {
+            lamports_per_byte_year: 3_480,
+            exemption_threshold: 2.0,
+            burn_percent: 50,
+        };
+        let size = StakeStateV2::size_of();
+        let lamports = RESERVE + 1_000;
+
+        let uninitialized = StakeStateV2::Uninitialized;
+        assert_eq!(
+            max_split(
+                &uninitialized,
+                &[],
+                lamports,
+                0,
+                size,
+                &clock(1),
+                history,
+                &rent
+            ),
+            Ok(lamports)
+        );
+
+        // the destination is brought up to its reserve by the split
+        let initialized = StakeStateV2::Initialized(meta());
+        assert_eq!(
+            max_split(
+                &initialized,
+                &[],
+                lamports,
+                0,
+                size,
+                &clock(1),
+                history,
+                &rent
+            ),
+            Ok(0)
+        );
+        assert_eq!(
+            max_split(
+                &initialized,
+                &[],
+                lamports,
+                RESERVE,
+                size,
+                &clock(1),
+                history,
+                &rent
+            ),
+            Ok(1_000)
+        );
+
+        // active stake needs a prefunded destination and leaves the minimum
+        // delegation behind
+        let delegated = StakeStateV2::Stake(meta(), stake([3; 32], 600, 0), StakeFlags::empty());
+        assert_eq!(
+            max_split(
+                &delegated,
+                &[],
+                lamports,
+                0,
+                size,
+                &clock(1),
+                history,
+                &rent
+            ),
+            Ok(0)
+        );
+        assert_eq!(
+            max_split(
+                &delegated,
+                &[],
+                lamports,
+                RESERVE,
+                size,
+                &clock(1),
+                history,
+                &rent
+            ),
+            Ok(599)
+        );
+
+        // a destination too small to hold the stake state
+        assert_eq!(
+            max_split(
+                &initialized,
+                &[],
+                lamports,
+                RESERVE,
+                size - 1,
+                &clock(1),
+                history,
+                &rent
+            ),
+            Ok(0)
+        );
+    }
+
+    #[cfg(feature = "vesting")]
+    #[test]
+    fn test_vesting_schedule_limits() {
+        use crate::state::{extension_space, init_extension, VestingSchedule};
+
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let rent = Rent {
+            lamports_per_byte_year: 3_480,
+            exemption_threshold: 2.0,
+            burn_percent: 50,
+        };
+        let size = StakeStateV2::size_of() + extension_space::<VestingSchedule>();
+        let mut data = vec![0; extension_space::<VestingSchedule>()];
+        *init_extension::<VestingSchedule>(&mut data).unwrap() = VestingSchedule {
+            amount: 600.into(),
+            start_timestamp: 0.into(),
+            end_timestamp: 100.into(),
+            period: 0.into(),
+        };
+
+        let mut locked_meta = meta();
+        locked_meta.lockup.custodian = [4; 32];
+        let initialized = StakeStateV2::Initialized(locked_meta);
+        let lamports = RESERVE + 1_000;
+
+        // unvested lamports stay with the reserve, unless the custodian signs
+        assert_eq!(
+            max_withdrawable(
+                &initialized,
+                &data,
+                lamports,
+                None,
+                &clock(1),
+                history,
+                None
+            ),
+            Ok(400)
+        );
+        assert_eq!(
+            max_withdrawable(
+                &initialized,
+                &data,
+                lamports,
+                None,
+                &clock(1),
+                history,
+                Some(&[4; 32])
+            ),
+            Ok(lamports)
+        );
+
+        // the schedule must fit into the destination while lamports are unvested
+        let destination_lamports = rent.minimum_balance(size);
+        assert_eq!(
+            max_split(
+                &initialized,
+                &data,
+                lamports,
+                destination_lamports,
+                StakeStateV2::size_of(),
+                &clock(1),
+                history,
+                &rent
+            ),
+            Ok(0)
+        );
+        assert_eq!(
+            max_split(
+                &initialized,
+                &data,
+                lamports,
+                destination_lamports,
+                size,
+                &clock(1),
+                history,
+                &rent
+            ),
+            Ok(1_000)
+        );
+    }
+
+    #[cfg(feature = "withdraw-allowlist")]
+    #[test]
+    fn test_withdraw_allowlist_limits() {
+        use crate::state::{extension_space, init_extension, WithdrawAllowlist};
+
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let mut data = vec![0; extension_space::<WithdrawAllowlist>()];
+        *init_extension::<WithdrawAllowlist>(&mut data).unwrap() =
+            WithdrawAllowlist::new(0, &[[5; 32]]).unwrap();
+
+        let initialized = StakeStateV2::Initialized(meta());
+        let lamports = RESERVE + 1_000;
+
+        assert_eq!(
+            max_withdrawable(
+                &initialized,
+                &data,
+                lamports,
+                Some(&[5; 32]),
+                &clock(1),
+                history,
+                None
+            ),
+            Ok(lamports)
+        );
+        assert_eq!(
+            max_withdrawable(
+                &initialized,
+                &data,
+                lamports,
+                Some(&[6; 32]),
+                &clock(1),
+                history,
+                None
+            ),
+            Ok(0)
+        );
+        // without a destination, the allowlist is not checked
+        assert_eq!(
+            max_withdrawable(
+                &initialized,
+                &data,
+                lamports,
+                None,
+                &clock(1),
+                history,
+                None
+            ),
+            Ok(lamports)
+        );
+    }
+
+    #[test]
+    fn test_can_merge() {
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+
+        let active = StakeStateV2::Stake(meta(), stake([3; 32], 1_000, 0), StakeFlags::empty());
+        let other_voter =
+            StakeStateV2::Stake(meta(), stake([5; 32], 1_000, 0), StakeFlags::empty());
+        let activating = StakeStateV2::Stake(meta(), stake([3; 32], 1_000, 4), StakeFlags::empty());
+
+        assert!(matches!(
+            can_merge(
+                &active,
+                &[],
+                RESERVE + 1_000,
+                &active,
+                &[],
+                RESERVE + 1_000,
+                &clock(4),
+                history
+            ),
+            Ok(MergeKind::FullyActive(_, stake)) if u64::from(stake.delegation.stake) == 2_000
+        ));
+        assert_eq!(
+            can_merge(
+                &active,
+                &[],
+                RESERVE + 1_000,
+                &other_voter,
+                &[],
+                RESERVE + 1_000,
+                &clock(4),
+                history
+            ),
+            Err(CannotMergeReason::Mismatch)
+        );
+        assert_eq!(
+            can_merge(
+                &active,
+                &[],
+                RESERVE + 1_000,
+                &activating,
+                &[],
+                RESERVE + 1_000,
+                &clock(4),
+                history
+            ),
+            Err(CannotMergeReason::Mismatch)
+        );
+        assert_eq!(
+            can_merge(
+                &StakeStateV2::Uninitialized,
+                &[],
+                RESERVE,
+                &active,
+                &[],
+                RESERVE + 1_000,
+                &clock(4),
+                history
+            ),
+            Err(CannotMergeReason::InvalidDestination)
+        );
+    }
+
+    #[cfg(any(feature = "vesting", feature = "withdraw-allowlist"))]
+    #[test]
+    fn test_can_merge_checks_extensions() {
+        use crate::state::extension_space;
+
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let initialized = StakeStateV2::Initialized(meta());
+
+        // the query fails where `Merge` fails, for the same reason
+        let check_merge = |destination_extensions: &mut [u8], source_extensions: &mut [u8]| {
+            let query = can_merge(
+                &initialized,
+                destination_extensions,
+                RESERVE,
+                &initialized,
+                source_extensions,
+                RESERVE,
+                &clock(1),
+                history,
+            );
+            let merged = crate::transition::merge(
+                &mut initialized.clone(),
+                destination_extensions,
+                RESERVE,
+                &mut initialized.clone(),
+                source_extensions,
+                RESERVE,
+                |pubkey| *pubkey == [1; 32],
+                &clock(1),
+                history,
+            );
+            (query.map(|_| ()), merged)
+        };
+
+        #[cfg(feature = "withdraw-allowlist")]
+        {
+            use crate::state::{init_extension, WithdrawAllowlist, WITHDRAW_ALLOWLIST_MISMATCH};
+
+            let with_allowlist = |destination: Pubkey| {
+                let mut extensions = vec![0; extension_space::<WithdrawAllowlist>()];
+                *init_extension::<WithdrawAllowlist>(&mut extensions).unwrap() =
+                    WithdrawAllowlist::new(0, &[destination]).unwrap();
+                extensions
+            };
+            assert_eq!(
+                check_merge(&mut with_allowlist([5; 32]), &mut with_allowlist([6; 32])),
+                (
+                    Err(CannotMergeReason::ExtensionMismatch),
+                    Err(ProgramError::Custom(WITHDRAW_ALLOWLIST_MISMATCH))
+                )
+            );
+            assert_eq!(
+                check_merge(&mut with_allowlist([5; 32]), &mut with_allowlist([5; 32])),
+                (Ok(()), Ok(()))
+            );
+        }
+
+        #[cfg(feature = "vesting")]
+        {
+            use crate::state::{init_extension, VestingSchedule};
+
+            let with_vesting_schedule = |end_timestamp: i64| {
+                let mut extensions = vec![0; extension_space::<VestingSchedule>()];
+                *init_extension::<VestingSchedule>(&mut extensions).unwrap() = VestingSchedule {
+                    amount: 1_000.into(),
+                    start_timestamp: 0.into(),
+                    end_timestamp: end_timestamp.into(),
+                    period: 0.into(),
+                };
+                extensions
+            };
+            assert_eq!(
+                check_merge(&mut with_vesting_schedule(5), &mut with_vesting_schedule(9)),
+                (
+                    Err(CannotMergeReason::ExtensionMismatch),
+                    Err(ProgramError::Custom(6))
+                )
+            );
+            assert_eq!(
+                check_merge(&mut [], &mut with_vesting_schedule(9)),
+                (
+                    Err(CannotMergeReason::DestinationTooSmall),
+                    Err(ProgramError::AccountDataTooSmall)
+                )
+            );
+            assert_eq!(
+                check_merge(&mut with_vesting_schedule(9), &mut with_vesting_schedule(9)),
+                (Ok(()), Ok(()))
+            );
+        }
+    }
+}
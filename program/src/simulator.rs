@@ -0,0 +1,698 @@
+//! Host-side simulation of stake accounts over many epochs. The simulator holds
+//! a set of accounts and, like the bank at every epoch boundary, sums the
+//! activation status of every delegation into the cluster stake history entry
+//! of the ending epoch. Tests step through epochs and apply the functions of
+//! [`crate::transition`] at any point of realistic activation curves, without
+//! hand-writing stake history entries. Instructions moving lamports between
+//! accounts, such as `Split`, `Merge` or `Withdraw`, have their own methods
+//! updating the balances of every account they touch.
+
+use std::{collections::BTreeMap, vec::Vec};
+
+use pinocchio::{
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvars::{
+        clock::{Clock, Epoch},
+        rent::Rent,
+    },
+};
+
+use crate::{
+    state::{Delegation, StakeHistoryEntry, StakeStateV2, MAX_ENTRIES},
+    transition, PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH,
+};
+
+/// Slots per epoch of mainnet-beta
+pub const DEFAULT_SLOTS_PER_EPOCH: u64 = 432_000;
+
+/// Target slot duration, used to advance the clock timestamp
+pub const DEFAULT_MS_PER_SLOT: u64 = 400;
+
+/// Rent of mainnet-beta, used for the reserve of split destinations
+pub const DEFAULT_RENT: Rent = Rent {
+    lamports_per_byte_year: 3_480,
+    exemption_threshold: 2.0,
+    burn_percent: 50,
+};
+
+/// A stake account held by the simulator
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulatedAccount {
+    pub lamports: u64,
+    pub state: StakeStateV2,
+    /// extension area, the account data after the state
+    pub extensions: Vec<u8>,
+}
+
+/// Stake accounts and the cluster state they evolve in. Accounts left without
+/// lamports are removed, as the runtime does at the end of a transaction.
+pub struct Simulator {
+    clock: Clock,
+    rent: Rent,
+    slots_per_epoch: u64,
+    new_rate_activation_epoch: Option<Epoch>,
+    accounts: BTreeMap<Pubkey, SimulatedAccount>,
+    /// delegations not held by any simulated account, e.g. the bootstrap stake
+    cluster_delegations: Vec<Delegation>,
+    /// newest epoch first, like the sysvar
+    stake_history: Vec<(Epoch, StakeHistoryEntry)>,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new(PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH)
+    }
+}
+
+impl Simulator {
+    /// Starts at epoch 0 with an empty cluster, using the 9% warmup and
+    /// cooldown rate from `new_rate_activation_epoch` on
+    pub fn new(new_rate_activation_epoch: Option<Epoch>) -> Self {
+        Self {
+            clock: Clock {
+                slot: 0,
+                epoch_start_timestamp: 0,
+                epoch: 0,
+                leader_schedule_epoch: 1,
+                unix_timestamp: 0,
+            },
+            rent: DEFAULT_RENT,
+            slots_per_epoch: DEFAULT_SLOTS_PER_EPOCH,
+            new_rate_activation_epoch,
+            accounts: BTreeMap::new(),
+            cluster_delegations: Vec::new(),
+            stake_history: Vec::new(),
+        }
+    }
+
+    /// Adds fully active stake delegated to `voter_pubkey` since genesis, which
+    /// lets new delegations warm up
+    pub fn with_bootstrap_stake(mut self, voter_pubkey: &Pubkey, lamports: u64) -> Self {
+        self.add_cluster_delegation(Delegation::new(
+            voter_pubkey,
+            lamports.into(),
+            u64::MAX.into(),
+        ));
+        self
+    }
+
+    /// Adds a delegation of the cluster that is not backed by a simulated
+    /// account
+    pub fn add_cluster_delegation(&mut self, delegation: Delegation) {
+        self.cluster_delegations.push(delegation);
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    pub fn epoch(&self) -> Epoch {
+        self.clock.epoch
+    }
+
+    pub fn rent(&self) -> &Rent {
+        &self.rent
+    }
+
+    /// Entries of past epochs, newest first, as read by the program
+    pub fn stake_history(&self) -> &[(Epoch, StakeHistoryEntry)] {
+        &self.stake_history
+    }
+
+    pub fn set_account(&mut self, pubkey: Pubkey, lamports: u64, state: StakeStateV2) {
+        self.accounts.insert(
+            pubkey,
+            SimulatedAccount {
+                lamports,
+                state,
+                extensions: Vec::new(),
+            },
+        );
+    }
+
+    pub fn account(&self, pubkey: &Pubkey) -> Option<&SimulatedAccount> {
+        self.accounts.get(pubkey)
+    }
+
+    pub fn account_mut(&mut self, pubkey: &Pubkey) -> Option<&mut SimulatedAccount> {
+        self.accounts.get_mut(pubkey)
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = (&Pubkey, &SimulatedAccount)> {
+        self.accounts.iter()
+    }
+
+    /// Updates the state and the extensions of an account with `transition`,
+    /// called with a copy of them, the balance, the clock and the stake
+    /// history. The account is left untouched on error. Only for instructions
+    /// that keep the balance, the others have their own methods.
+    pub fn apply(
+        &mut self,
+        pubkey: &Pubkey,
+        transition: impl FnOnce(
+            &mut StakeStateV2,
+            &mut [u8],
+            u64,
+            &Clock,
+            &[(Epoch, StakeHistoryEntry)],
+        ) -> Result<(), ProgramError>,
+    ) -> Result<(), ProgramError> {
+        let account = self
+            .accounts
+            .get_mut(pubkey)
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let mut state = account.state.clone();
+        let mut extensions = account.extensions.clone();
+        transition(
+            &mut state,
+            &mut extensions,
+            account.lamports,
+            &self.clock,
+            &self.stake_history,
+        )?;
+        account.state = state;
+        account.extensions = extensions;
+        Ok(())
+    }
+
+    /// `Split`: moves `lamports` from `source` to `destination`, an
+    /// uninitialized account or a new one without extensions
+    pub fn split(
+        &mut self,
+        source_pubkey: &Pubkey,
+        destination_pubkey: &Pubkey,
+        lamports: u64,
+        is_signer: impl Fn(&Pubkey) -> bool,
+    ) -> Result<(), ProgramError> {
+        let (mut source, mut destination) = self.account_pair(source_pubkey, destination_pubkey)?;
+        transition::split(
+            &mut source.state,
+            &mut source.extensions,
+            source_pubkey,
+            source.lamports,
+            &mut destination.state,
+            &mut destination.extensions,
+            destination.lamports,
+            lamports,
+            is_signer,
+            &self.clock,
+            self.stake_history.as_slice(),
+            &self.rent,
+        )?;
+        self.transfer_and_commit(
+            source_pubkey,
+            source,
+            destination_pubkey,
+            destination,
+            lamports,
+        )
+    }
+
+    /// `Merge`: merges `source` into `destination` and moves all its lamports
+    pub fn merge(
+        &mut self,
+        destination_pubkey: &Pubkey,
+        source_pubkey: &Pubkey,
+        is_signer: impl Fn(&Pubkey) -> bool,
+    ) -> Result<(), ProgramError> {
+        let (mut source, mut destination) = self.account_pair(source_pubkey, destination_pubkey)?;
+        transition::merge(
+            &mut destination.state,
+            &mut destination.extensions,
+            destination.lamports,
+            &mut source.state,
+            &mut source.extensions,
+            source.lamports,
+            is_signer,
+            &self.clock,
+            self.stake_history.as_slice(),
+        )?;
+        let lamports = source.lamports;
+        self.transfer_and_commit(
+            source_pubkey,
+            source,
+            destination_pubkey,
+            destination,
+            lamports,
+        )
+    }
+
+    /// `MoveStake`: moves `lamports` of active stake and the lamports backing it
+    pub fn move_stake(
+        &mut self,
+        source_pubkey: &Pubkey,
+        destination_pubkey: &Pubkey,
+        lamports: u64,
+        authority: &Pubkey,
+    ) -> Result<(), ProgramError> {
+        let (mut source, mut destination) = self.account_pair(source_pubkey, destination_pubkey)?;
+        transition::move_stake(
+            &mut source.state,
+            &mut source.extensions,
+            source.lamports,
+            &mut destination.state,
+            &mut destination.extensions,
+            destination.lamports,
+            lamports,
+            authority,
+            &self.clock,
+            self.stake_history.as_slice(),
+        )?;
+        self.transfer_and_commit(
+            source_pubkey,
+            source,
+            destination_pubkey,
+            destination,
+            lamports,
+        )
+    }
+
+    /// `MoveLamports`: moves `lamports` not backing any stake
+    pub fn move_lamports(
+        &mut self,
+        source_pubkey: &Pubkey,
+        destination_pubkey: &Pubkey,
+        lamports: u64,
+        authority: &Pubkey,
+    ) -> Result<(), ProgramError> {
+        let (mut source, mut destination) = self.account_pair(source_pubkey, destination_pubkey)?;
+        transition::move_lamports(
+            &source.state,
+            &mut source.extensions,
+            source.lamports,
+            &destination.state,
+            &mut destination.extensions,
+            destination.lamports,
+            lamports,
+            authority,
+            &self.clock,
+            self.stake_history.as_slice(),
+        )?;
+        self.transfer_and_commit(
+            source_pubkey,
+            source,
+            destination_pubkey,
+            destination,
+            lamports,
+        )
+    }
+
+    /// `Withdraw`: withdraws `lamports` to `recipient`, which is credited if it
+    /// is a simulated account. Returns the lamports withdrawn.
+    pub fn withdraw(
+        &mut self,
+        stake_pubkey: &Pubkey,
+        recipient: &Pubkey,
+        lamports: u64,
+        withdraw_authority: &Pubkey,
+        custodian: Option<&Pubkey>,
+    ) -> Result<u64, ProgramError> {
+        if stake_pubkey == recipient {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let mut account = self
+            .accounts
+            .get(stake_pubkey)
+            .cloned()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let withdrawn = transition::withdraw(
+            &mut account.state,
+            &mut account.extensions,
+            stake_pubkey,
+            account.lamports,
+            lamports,
+            recipient,
+            withdraw_authority,
+            custodian,
+            &self.clock,
+            self.stake_history.as_slice(),
+        )?;
+
+        account.lamports -= withdrawn;
+        if let Some(recipient_account) = self.accounts.get_mut(recipient) {
+            recipient_account.lamports = recipient_account
+                .lamports
+                .checked_add(withdrawn)
+                .ok_or(ProgramError::ArithmeticOverflow)?;
+        }
+        self.commit(stake_pubkey, account);
+        Ok(withdrawn)
+    }
+
+    /// Effective, activating and deactivating stake of an account in the
+    /// current epoch, `None` if it is not delegated
+    pub fn status(&self, pubkey: &Pubkey) -> Option<StakeHistoryEntry> {
+        match &self.accounts.get(pubkey)?.state {
+            StakeStateV2::Stake(_, stake, _) => Some(self.delegation_status(&stake.delegation)),
+            _ => None,
+        }
+    }
+
+    /// Sum of the status of every delegation in the current epoch, the entry
+    /// the epoch will be recorded with
+    pub fn cluster_status(&self) -> StakeHistoryEntry {
+        let account_delegations = self.accounts.values().filter_map(|account| {
+            if let StakeStateV2::Stake(_, stake, _) = &account.state {
+                Some(&stake.delegation)
+            } else {
+                None
+            }
+        });
+
+        account_delegations
+            .chain(self.cluster_delegations.iter())
+            .map(|delegation| self.delegation_status(delegation))
+            .fold(StakeHistoryEntry::default(), |total, status| {
+                StakeHistoryEntry {
+                    effective: total.effective.saturating_add(status.effective),
+                    activating: total.activating.saturating_add(status.activating),
+                    deactivating: total.deactivating.saturating_add(status.deactivating),
+                }
+            })
+    }
+
+    /// Records the entry of the ending epoch and moves the clock to the first
+    /// slot of the next one
+    pub fn advance_epoch(&mut self) {
+        let entry = self.cluster_status();
+        self.stake_history.insert(0, (self.clock.epoch, entry));
+        self.stake_history.truncate(MAX_ENTRIES);
+
+        let slot = self
+            .clock
+            .epoch
+            .saturating_add(1)
+            .saturating_mul(self.slots_per_epoch);
+        let elapsed_ms = slot
+            .saturating_sub(self.clock.slot)
+            .saturating_mul(DEFAULT_MS_PER_SLOT);
+
+        self.clock.slot = slot;
+        self.clock.epoch = self.clock.epoch.saturating_add(1);
+        self.clock.leader_schedule_epoch = self.clock.epoch.saturating_add(1);
+        self.clock.unix_timestamp = self
+            .clock
+            .unix_timestamp
+            .saturating_add((elapsed_ms / 1_000) as i64);
+        self.clock.epoch_start_timestamp = self.clock.unix_timestamp;
+    }
+
+    pub fn advance_epochs(&mut self, epochs: u64) {
+        for _ in 0..epochs {
+            self.advance_epoch();
+        }
+    }
+
+    /// Copies of two distinct accounts, the destination being a new empty
+    /// account if it does not exist
+    fn account_pair(
+        &self,
+        source_pubkey: &Pubkey,
+        destination_pubkey: &Pubkey,
+    ) -> Result<(SimulatedAccount, SimulatedAccount), ProgramError> {
+        if source_pubkey == destination_pubkey {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let source = self
+            .accounts
+            .get(source_pubkey)
+            .cloned()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let destination =
+            self.accounts
+                .get(destination_pubkey)
+                .cloned()
+                .unwrap_or(SimulatedAccount {
+                    lamports: 0,
+                    state: StakeStateV2::Uninitialized,
+                    extensions: Vec::new(),
+                });
+        Ok((source, destination))
+    }
+
+    /// Moves `lamports` between the updated copies of two accounts and stores
+    /// them
+    fn transfer_and_commit(
+        &mut self,
+        source_pubkey: &Pubkey,
+        mut source: SimulatedAccount,
+        destination_pubkey: &Pubkey,
+        mut destination: SimulatedAccount,
+        lamports: u64,
+    ) -> Result<(), ProgramError> {
+        source.lamports = source
+            .lamports
+            .checked_sub(lamports)
+            .ok_or(ProgramError::InsufficientFunds)?;
+        destination.lamports = destination
+            .lamports
+            .checked_add(lamports)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        self.commit(source_pubkey, source);
+        self.commit(destination_pubkey, destination);
+        Ok(())
+    }
+
+    fn commit(&mut self, pubkey: &Pubkey, account: SimulatedAccount) {
+        if account.lamports == 0 {
+            self.accounts.remove(pubkey);
+        } else {
+            self.accounts.insert(*pubkey, account);
+        }
+    }
+
+    fn delegation_status(&self, delegation: &Delegation) -> StakeHistoryEntry {
+        delegation.stake_activating_and_deactivating(
+            self.clock.epoch,
+            self.stake_history.as_slice(),
+            self.new_rate_activation_epoch,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        state::{Authorized, Lockup, Meta},
+        transition, LAMPORTS_PER_SOL,
+    };
+
+    use super::*;
+
+    const RESERVE: u64 = 2_282_880;
+    const STAKER: Pubkey = [1; 32];
+    const WITHDRAWER: Pubkey = [2; 32];
+    const VOTER: Pubkey = [3; 32];
+    const STAKE_ACCOUNT: Pubkey = [4; 32];
+    const OTHER_ACCOUNT: Pubkey = [5; 32];
+    const RECIPIENT: Pubkey = [6; 32];
+
+    fn initialized() -> StakeStateV2 {
+        StakeStateV2::Initialized(Meta {
+            rent_exempt_reserve: RESERVE.into(),
+            authorized: Authorized {
+                staker: STAKER,
+                withdrawer: WITHDRAWER,
+            },
+            lockup: Lockup::default(),
+        })
+    }
+
+    fn signed_by_staker(pubkey: &Pubkey) -> bool {
+        *pubkey == STAKER
+    }
+
+    fn delegated_simulator(stake: u64) -> Simulator {
+        let mut simulator =
+            Simulator::default().with_bootstrap_stake(&VOTER, 1_000 * LAMPORTS_PER_SOL);
+        simulator.set_account(STAKE_ACCOUNT, RESERVE + stake, initialized());
+        simulator
+            .apply(
+                &STAKE_ACCOUNT,
+                |state, extensions, lamports, clock, history| {
+                    transition::delegate(
+                        state,
+                        extensions,
+                        lamports,
+                        &VOTER,
+                        0,
+                        signed_by_staker,
+                        clock,
+                        history,
+                    )
+                },
+            )
+            .unwrap();
+        simulator
+    }
+
+    #[test]
+    fn test_warmup_curve() {
+        let stake = 500 * LAMPORTS_PER_SOL;
+        let mut simulator = delegated_simulator(stake);
+
+        assert_eq!(
+            simulator.status(&STAKE_ACCOUNT),
+            Some(StakeHistoryEntry::with_effective_and_activating(0, stake))
+        );
+
+        let mut effective = 0;
+        while effective < stake {
+            let cluster_effective = simulator.cluster_status().effective;
+            simulator.advance_epoch();
+            assert!(simulator.epoch() < 32, "stake never became fully active");
+
+            let status = simulator.status(&STAKE_ACCOUNT).unwrap();
+            assert_eq!(status.effective + status.activating, stake);
+
+            // warmup is bounded by 9% of the cluster effective stake
+            let warmed_up = status.effective - effective;
+            assert!(warmed_up > 0);
+            assert!(warmed_up <= cluster_effective * 9 / 100 + 1);
+            effective = status.effective;
+        }
+
+        // every past epoch is recorded, the first one with all stake activating
+        let history = simulator.stake_history();
+        assert_eq!(history.len() as u64, simulator.epoch());
+        assert_eq!(
+            history.last(),
+            Some(&(
+                0,
+                StakeHistoryEntry::with_effective_and_activating(1_000 * LAMPORTS_PER_SOL, stake)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_deactivate_mid_warmup_then_withdraw() {
+        let stake = 500 * LAMPORTS_PER_SOL;
+        let mut simulator = delegated_simulator(stake);
+        simulator.advance_epochs(2);
+
+        let partially_active = simulator.status(&STAKE_ACCOUNT).unwrap();
+        assert!(partially_active.effective > 0 && partially_active.activating > 0);
+
+        simulator
+            .apply(&STAKE_ACCOUNT, |state, extensions, _, clock, _| {
+                transition::deactivate(state, extensions, signed_by_staker, clock)
+            })
+            .unwrap();
+
+        // cooling down stake cannot be withdrawn
+        let lamports = RESERVE + stake;
+        let mut epochs = 0;
+        while simulator.status(&STAKE_ACCOUNT).unwrap().effective > 0 {
+            assert_eq!(
+                simulator.withdraw(&STAKE_ACCOUNT, &RECIPIENT, lamports, &WITHDRAWER, None),
+                Err(ProgramError::InsufficientFunds)
+            );
+            simulator.advance_epoch();
+            epochs += 1;
+            assert!(epochs < 32, "stake never became inactive");
+        }
+
+        assert_eq!(
+            simulator.withdraw(&STAKE_ACCOUNT, &RECIPIENT, lamports, &WITHDRAWER, None),
+            Ok(lamports)
+        );
+        assert_eq!(simulator.account(&STAKE_ACCOUNT), None);
+    }
+
+    #[test]
+    fn test_split_merge_and_moves_keep_lamports() {
+        let stake = 500 * LAMPORTS_PER_SOL;
+        let mut simulator = delegated_simulator(stake);
+        simulator.advance_epochs(32);
+        let total_lamports = |simulator: &Simulator| {
+            simulator
+                .accounts()
+                .map(|(_, account)| account.lamports)
+                .sum::<u64>()
+        };
+        let delegated_stake = |simulator: &Simulator, pubkey: &Pubkey| match &simulator
+            .account(pubkey)
+            .unwrap()
+            .state
+        {
+            StakeStateV2::Stake(_, stake, _) => u64::from(stake.delegation.stake),
+            _ => 0,
+        };
+
+        // the split destination, funded with its reserve, takes the lamports and
+        // the stake they back
+        let split_lamports = 100 * LAMPORTS_PER_SOL;
+        simulator.set_account(OTHER_ACCOUNT, RESERVE, StakeStateV2::Uninitialized);
+        simulator
+            .split(
+                &STAKE_ACCOUNT,
+                &OTHER_ACCOUNT,
+                split_lamports,
+                signed_by_staker,
+            )
+            .unwrap();
+        assert_eq!(total_lamports(&simulator), 2 * RESERVE + stake);
+        assert_eq!(
+            simulator.account(&OTHER_ACCOUNT).unwrap().lamports,
+            RESERVE + split_lamports
+        );
+        assert_eq!(
+            delegated_stake(&simulator, &OTHER_ACCOUNT),
+            100 * LAMPORTS_PER_SOL
+        );
+        assert_eq!(
+            simulator.split(&STAKE_ACCOUNT, &[9; 32], stake, signed_by_staker),
+            Err(ProgramError::InsufficientFunds)
+        );
+        assert_eq!(simulator.account(&[9; 32]), None);
+
+        // moves change both balances
+        simulator
+            .move_stake(&OTHER_ACCOUNT, &STAKE_ACCOUNT, LAMPORTS_PER_SOL, &STAKER)
+            .unwrap();
+        assert_eq!(
+            delegated_stake(&simulator, &STAKE_ACCOUNT),
+            401 * LAMPORTS_PER_SOL
+        );
+        assert_eq!(
+            simulator.account(&STAKE_ACCOUNT).unwrap().lamports,
+            RESERVE + 401 * LAMPORTS_PER_SOL
+        );
+        simulator.account_mut(&OTHER_ACCOUNT).unwrap().lamports += 1_000;
+        simulator
+            .move_lamports(&OTHER_ACCOUNT, &STAKE_ACCOUNT, 1_000, &STAKER)
+            .unwrap();
+        assert_eq!(
+            simulator.account(&STAKE_ACCOUNT).unwrap().lamports,
+            RESERVE + 401 * LAMPORTS_PER_SOL + 1_000
+        );
+        assert_eq!(
+            simulator.move_lamports(&OTHER_ACCOUNT, &STAKE_ACCOUNT, 1, &STAKER),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        // the merged source is drained and goes away
+        simulator
+            .merge(&STAKE_ACCOUNT, &OTHER_ACCOUNT, signed_by_staker)
+            .unwrap();
+        assert_eq!(simulator.account(&OTHER_ACCOUNT), None);
+        assert_eq!(
+            simulator.account(&STAKE_ACCOUNT).unwrap().lamports,
+            2 * RESERVE + stake + 1_000
+        );
+        assert_eq!(delegated_stake(&simulator, &STAKE_ACCOUNT), stake);
+
+        // withdrawals credit a simulated recipient
+        simulator.set_account(RECIPIENT, RESERVE, initialized());
+        assert_eq!(
+            simulator.withdraw(&STAKE_ACCOUNT, &RECIPIENT, 1_000, &WITHDRAWER, None),
+            Ok(1_000)
+        );
+        assert_eq!(
+            simulator.account(&RECIPIENT).unwrap().lamports,
+            RESERVE + 1_000
+        );
+        assert_eq!(total_lamports(&simulator), 3 * RESERVE + stake + 1_000);
+    }
+}
@@ -0,0 +1,19 @@
+use super::{Epoch, Extension, ExtensionType};
+
+/// Epoch at which the stake of an account is to be deactivated. Once it is
+/// reached, anyone may deactivate the stake with `CrankScheduledDeactivation`.
+#[repr(C)]
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct DeactivationSchedule {
+    pub epoch: Epoch,
+}
+
+impl Extension for DeactivationSchedule {
+    const TYPE: ExtensionType = ExtensionType::DeactivationSchedule;
+}
+
+impl DeactivationSchedule {
+    pub fn is_due(&self, epoch: u64) -> bool {
+        epoch >= u64::from(self.epoch)
+    }
+}
@@ -0,0 +1,149 @@
+use pinocchio::{program_error::ProgramError, pubkey::Pubkey, ProgramResult};
+
+use super::{
+    extension_space, get_extension, get_extension_mut, init_extension, Epoch, Extension,
+    ExtensionType, PodU64,
+};
+
+/// Opts a stake account into two-phase withdrawer changes: a new withdrawer is
+/// first proposed by the current one and can only accept after `delay_epochs`.
+/// Until then, the current withdrawer may cancel the proposal.
+#[repr(C)]
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct DelayedWithdrawerTransfer {
+    /// epochs between a proposal and the earliest acceptance
+    pub delay_epochs: PodU64,
+    /// proposed withdrawer, only meaningful while a transfer is pending
+    pub pending_withdrawer: Pubkey,
+    /// epoch from which the pending withdrawer may accept, `u64::MAX` if there
+    /// is no pending transfer
+    pub effective_epoch: Epoch,
+}
+
+impl Extension for DelayedWithdrawerTransfer {
+    const TYPE: ExtensionType = ExtensionType::DelayedWithdrawerTransfer;
+}
+
+impl DelayedWithdrawerTransfer {
+    pub fn new(delay_epochs: u64) -> Self {
+        Self {
+            delay_epochs: delay_epochs.into(),
+            pending_withdrawer: Pubkey::default(),
+            effective_epoch: u64::MAX.into(),
+        }
+    }
+
+    /// Returns the pending withdrawer and the epoch from which it may accept
+    pub fn pending(&self) -> Option<(&Pubkey, u64)> {
+        let effective_epoch = u64::from(self.effective_epoch);
+        if effective_epoch == u64::MAX {
+            None
+        } else {
+            Some((&self.pending_withdrawer, effective_epoch))
+        }
+    }
+
+    pub fn propose(&mut self, new_withdrawer: &Pubkey, epoch: u64) {
+        self.pending_withdrawer = *new_withdrawer;
+        self.effective_epoch = epoch
+            .saturating_add(self.delay_epochs.into())
+            .min(u64::MAX - 1)
+            .into();
+    }
+
+    pub fn cancel(&mut self) {
+        self.pending_withdrawer = Pubkey::default();
+        self.effective_epoch = u64::MAX.into();
+    }
+}
+
+/// Returns whether a stake account has opted into delayed withdrawer transfers
+pub fn has_delayed_withdrawer_transfer(extensions: &[u8]) -> Result<bool, ProgramError> {
+    Ok(get_extension::<DelayedWithdrawerTransfer>(extensions)?.is_some())
+}
+
+/// Carries the opt-in of the source over to the split destination, without the
+/// pending transfer. Fails if the destination has no room for it.
+pub fn split_delayed_withdrawer_transfer(
+    source_extensions: &[u8],
+    destination_extensions: &mut [u8],
+) -> ProgramResult {
+    let Some(source_transfer) = get_extension::<DelayedWithdrawerTransfer>(source_extensions)?
+    else {
+        return Ok(());
+    };
+
+    *init_extension::<DelayedWithdrawerTransfer>(destination_extensions)? =
+        DelayedWithdrawerTransfer::new(source_transfer.delay_epochs.into());
+
+    Ok(())
+}
+
+/// Returns the room the merge destination needs to opt in like the source
+pub fn merge_delayed_withdrawer_transfer_space(
+    destination_extensions: &[u8],
+    source_extensions: &[u8],
+) -> Result<usize, ProgramError> {
+    if get_extension::<DelayedWithdrawerTransfer>(source_extensions)?.is_some()
+        && get_extension::<DelayedWithdrawerTransfer>(destination_extensions)?.is_none()
+    {
+        Ok(extension_space::<DelayedWithdrawerTransfer>())
+    } else {
+        Ok(0)
+    }
+}
+
+/// Makes the merge destination at least as protected as the source. Fails if the
+/// destination has not opted in and has no room to.
+pub fn merge_delayed_withdrawer_transfers(
+    destination_extensions: &mut [u8],
+    source_extensions: &[u8],
+) -> ProgramResult {
+    let Some(source_transfer) = get_extension::<DelayedWithdrawerTransfer>(source_extensions)?
+    else {
+        return Ok(());
+    };
+
+    match get_extension_mut::<DelayedWithdrawerTransfer>(destination_extensions)? {
+        Some(destination_transfer) => {
+            if u64::from(destination_transfer.delay_epochs)
+                < u64::from(source_transfer.delay_epochs)
+            {
+                destination_transfer.delay_epochs = source_transfer.delay_epochs;
+            }
+        }
+        None => {
+            *init_extension::<DelayedWithdrawerTransfer>(destination_extensions)? =
+                DelayedWithdrawerTransfer::new(source_transfer.delay_epochs.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Stake and lamports of an opted-in account can only be moved to an account
+/// that is at least as protected
+pub fn check_move_delayed_withdrawer_transfer(
+    source_extensions: &[u8],
+    destination_extensions: &[u8],
+) -> ProgramResult {
+    let Some(source_transfer) = get_extension::<DelayedWithdrawerTransfer>(source_extensions)?
+    else {
+        return Ok(());
+    };
+
+    match get_extension::<DelayedWithdrawerTransfer>(destination_extensions)? {
+        Some(destination_transfer)
+            if u64::from(destination_transfer.delay_epochs)
+                >= u64::from(source_transfer.delay_epochs) =>
+        {
+            Ok(())
+        }
+        _ => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Destination does not delay withdrawer transfers as long as source");
+            // StakeError::MergeMismatch
+            Err(ProgramError::Custom(6))
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use pinocchio::{pubkey::Pubkey, sysvars::clock};
 
-use super::{Epoch, PodF64, PodU64, StakeHistoryEntry, StakeHistorySysvar};
+use super::{Epoch, PodF64, PodU64, StakeHistoryEntry, StakeHistoryGetEntry};
 
 #[repr(C)]
 #[derive(Debug, PartialEq, Clone)]
@@ -47,10 +47,10 @@ impl Delegation {
         }
     }
 
-    pub fn stake_activating_and_deactivating(
+    pub fn stake_activating_and_deactivating<T: StakeHistoryGetEntry + ?Sized>(
         &self,
         target_epoch: clock::Epoch,
-        history: &StakeHistorySysvar,
+        history: &T,
         new_rate_activation_epoch: Option<clock::Epoch>,
     ) -> StakeHistoryEntry {
         // first, calculate an effective and activating stake
@@ -133,10 +133,10 @@ impl Delegation {
     }
 
     // returned tuple is (effective, activating) stake
-    fn stake_and_activating(
+    fn stake_and_activating<T: StakeHistoryGetEntry + ?Sized>(
         &self,
         target_epoch: clock::Epoch,
-        history: &StakeHistorySysvar,
+        history: &T,
         new_rate_activation_epoch: Option<clock::Epoch>,
     ) -> (u64, u64) {
         let delegated_stake = self.stake.into();
@@ -218,10 +218,10 @@ impl Delegation {
     }
 
     // Explanation: this is an optimized version of stake_activating_and_deactivating when only effective stake is needed
-    pub fn get_effective_stake(
+    pub fn get_effective_stake<T: StakeHistoryGetEntry + ?Sized>(
         &self,
         target_epoch: clock::Epoch,
-        history: &StakeHistorySysvar,
+        history: &T,
         new_rate_activation_epoch: Option<clock::Epoch>,
     ) -> u64 {
         let effective_stake =
@@ -291,10 +291,10 @@ impl Delegation {
         }
     }
 
-    fn get_effective_stake_inner(
+    fn get_effective_stake_inner<T: StakeHistoryGetEntry + ?Sized>(
         &self,
         target_epoch: clock::Epoch,
-        history: &StakeHistorySysvar,
+        history: &T,
         new_rate_activation_epoch: Option<clock::Epoch>,
     ) -> u64 {
         let delegated_stake = self.stake.into();
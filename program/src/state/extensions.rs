@@ -0,0 +1,520 @@
+use pinocchio::{program_error::ProgramError, sysvars::clock::Clock, ProgramResult};
+
+use super::StakeStateV2;
+
+/// Offset of the extension area, which starts right after the stake state in
+/// accounts larger than `StakeStateV2::size_of()`.
+///
+/// The extension area is a list of type-length-value entries: a `u16` extension
+/// type, a `u16` length and the extension data. The list ends at the first entry
+/// of type `Uninitialized` or at the end of the account data. The functions of
+/// this module take the extension area, which is empty for accounts without
+/// room for extensions.
+pub const EXTENSIONS_OFFSET: usize = StakeStateV2::size_of();
+
+const TYPE_SIZE: usize = 2;
+const LENGTH_SIZE: usize = 2;
+const HEADER_SIZE: usize = TYPE_SIZE + LENGTH_SIZE;
+
+#[repr(u16)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ExtensionType {
+    Uninitialized = 0,
+    VestingSchedule = 1,
+    Metadata = 2,
+    DeactivationSchedule = 3,
+    DelayedWithdrawerTransfer = 4,
+    WithdrawAllowlist = 5,
+    RoleAuthorities = 6,
+    RecoveryAuthority = 7,
+}
+
+impl TryFrom<u16> for ExtensionType {
+    type Error = ProgramError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ExtensionType::Uninitialized),
+            1 => Ok(ExtensionType::VestingSchedule),
+            2 => Ok(ExtensionType::Metadata),
+            3 => Ok(ExtensionType::DeactivationSchedule),
+            4 => Ok(ExtensionType::DelayedWithdrawerTransfer),
+            5 => Ok(ExtensionType::WithdrawAllowlist),
+            6 => Ok(ExtensionType::RoleAuthorities),
+            7 => Ok(ExtensionType::RecoveryAuthority),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+}
+
+/// Extension data stored in the extension area. Implementors must be `repr(C)`
+/// with an alignment of 1, i.e. made of byte arrays and pod types only.
+pub trait Extension {
+    const TYPE: ExtensionType;
+}
+
+/// Number of bytes an extension of type `T` takes in the extension area, to be
+/// added to `StakeStateV2::size_of()` when creating an account
+pub const fn extension_space<T: Extension>() -> usize {
+    HEADER_SIZE + core::mem::size_of::<T>()
+}
+
+/// Iterator over the entries of the extension area, yielding the raw extension
+/// type and the extension data
+pub struct ExtensionIter<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for ExtensionIter<'a> {
+    type Item = Result<(u16, &'a [u8]), ProgramError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset;
+        if offset + HEADER_SIZE > self.data.len() {
+            return None;
+        }
+
+        let entry_type = u16::from_le_bytes([self.data[offset], self.data[offset + 1]]);
+        if entry_type == ExtensionType::Uninitialized as u16 {
+            return None;
+        }
+        let entry_length =
+            u16::from_le_bytes([self.data[offset + 2], self.data[offset + 3]]) as usize;
+
+        let value_start = offset + HEADER_SIZE;
+        let value_end = value_start + entry_length;
+        if value_end > self.data.len() {
+            // stop at a malformed entry
+            self.offset = self.data.len();
+            return Some(Err(ProgramError::InvalidAccountData));
+        }
+
+        self.offset = value_end;
+        Some(Ok((entry_type, &self.data[value_start..value_end])))
+    }
+}
+
+/// Returns an iterator over the extensions in the extension area of a stake
+/// account
+pub fn get_extensions(extensions: &[u8]) -> ExtensionIter<'_> {
+    ExtensionIter {
+        data: extensions,
+        offset: 0,
+    }
+}
+
+/// Returns the range of the value of the first `extension_type` entry, or the
+/// offset of the end of the list if there is no such entry
+fn find_extension(
+    data: &[u8],
+    extension_type: ExtensionType,
+) -> Result<Result<(usize, usize), usize>, ProgramError> {
+    let mut offset = 0;
+    while offset + HEADER_SIZE <= data.len() {
+        let entry_type = u16::from_le_bytes([data[offset], data[offset + 1]]);
+        if entry_type == ExtensionType::Uninitialized as u16 {
+            break;
+        }
+        let entry_length = u16::from_le_bytes([data[offset + 2], data[offset + 3]]) as usize;
+
+        let value_start = offset + HEADER_SIZE;
+        let value_end = value_start + entry_length;
+        if value_end > data.len() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if entry_type == extension_type as u16 {
+            return Ok(Ok((value_start, value_end)));
+        }
+        offset = value_end;
+    }
+    Ok(Err(offset))
+}
+
+/// Returns the extension of type `T` from the extension area of a stake account,
+/// if any
+pub fn get_extension<T: Extension>(data: &[u8]) -> Result<Option<&T>, ProgramError> {
+    match find_extension(data, T::TYPE)? {
+        Ok((value_start, value_end)) => {
+            if value_end - value_start != core::mem::size_of::<T>() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            Ok(Some(unsafe {
+                &*(data.as_ptr().add(value_start) as *const T)
+            }))
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// Returns the extension of type `T` from the extension area of a stake account,
+/// if any
+pub fn get_extension_mut<T: Extension>(data: &mut [u8]) -> Result<Option<&mut T>, ProgramError> {
+    match find_extension(data, T::TYPE)? {
+        Ok((value_start, value_end)) => {
+            if value_end - value_start != core::mem::size_of::<T>() {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            Ok(Some(unsafe {
+                &mut *(data.as_mut_ptr().add(value_start) as *mut T)
+            }))
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// Appends a zeroed extension of type `T` to the extension area of a stake
+/// account. The account must have been created large enough to hold it.
+pub fn init_extension<T: Extension>(data: &mut [u8]) -> Result<&mut T, ProgramError> {
+    let offset = match find_extension(data, T::TYPE)? {
+        Ok(_) => return Err(ProgramError::AccountAlreadyInitialized),
+        Err(offset) => offset,
+    };
+
+    let value_start = offset + HEADER_SIZE;
+    let value_end = value_start + core::mem::size_of::<T>();
+    if value_end > data.len() {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+
+    data[offset..offset + TYPE_SIZE].copy_from_slice(&(T::TYPE as u16).to_le_bytes());
+    data[offset + TYPE_SIZE..value_start]
+        .copy_from_slice(&(core::mem::size_of::<T>() as u16).to_le_bytes());
+    data[value_start..value_end].fill(0);
+
+    Ok(unsafe { &mut *(data.as_mut_ptr().add(value_start) as *mut T) })
+}
+
+/// Removes the extension of type `T` from the extension area of a stake account,
+/// moving the following entries up. Returns whether there was such an extension.
+pub fn remove_extension<T: Extension>(data: &mut [u8]) -> Result<bool, ProgramError> {
+    let Ok((value_start, value_end)) = find_extension(data, T::TYPE)? else {
+        return Ok(false);
+    };
+
+    let entry_start = value_start - HEADER_SIZE;
+    let entry_length = value_end - entry_start;
+    data.copy_within(value_end.., entry_start);
+    let data_len = data.len();
+    data[data_len - entry_length..].fill(0);
+
+    Ok(true)
+}
+
+/// Returns the number of bytes the extensions take in the extension area
+fn extensions_len(data: &[u8]) -> Result<usize, ProgramError> {
+    // the list ends before any entry of type `Uninitialized`
+    let (Ok((_, end)) | Err(end)) = find_extension(data, ExtensionType::Uninitialized)?;
+    Ok(end)
+}
+
+/// Removes all extensions from the extension area of a stake account
+pub fn clear_extensions(extensions: &mut [u8]) {
+    extensions.fill(0);
+}
+
+/// Extension rules for `Split`: the destination starts without extensions and
+/// receives its share of the source extensions. `split_lamports` are taken out
+/// of `source_lamports`. Fails if the destination is too small to hold them.
+pub fn split_extensions(
+    source_extensions: &mut [u8],
+    destination_extensions: &mut [u8],
+    _split_lamports: u64,
+    _source_lamports: u64,
+    _clock: &Clock,
+) -> ProgramResult {
+    // the destination must not inherit the extensions of a previous life
+    clear_extensions(destination_extensions);
+
+    if source_extensions.is_empty() {
+        return Ok(());
+    }
+
+    #[cfg(feature = "vesting")]
+    super::split_vesting_schedule(
+        source_extensions,
+        destination_extensions,
+        _split_lamports,
+        _source_lamports,
+        _clock,
+    )?;
+    #[cfg(feature = "delayed-withdrawer-transfer")]
+    super::split_delayed_withdrawer_transfer(source_extensions, destination_extensions)?;
+    #[cfg(feature = "withdraw-allowlist")]
+    super::split_withdraw_allowlist(source_extensions, destination_extensions)?;
+
+    // optional extensions last, so they cannot take the room of mandatory ones
+    #[cfg(feature = "deactivation-schedule")]
+    copy_optional_extension::<super::DeactivationSchedule>(
+        source_extensions,
+        destination_extensions,
+    )?;
+    #[cfg(feature = "recovery-authority")]
+    copy_optional_extension::<super::RecoveryAuthority>(source_extensions, destination_extensions)?;
+    #[cfg(feature = "role-authorities")]
+    copy_optional_extension::<super::RoleAuthorities>(source_extensions, destination_extensions)?;
+    #[cfg(feature = "metadata")]
+    copy_optional_extension::<super::Metadata>(source_extensions, destination_extensions)?;
+
+    Ok(())
+}
+
+/// Copies the extension of type `T` to the split destination, if the source has
+/// one and the destination has room for it
+#[cfg(any(
+    feature = "deactivation-schedule",
+    feature = "metadata",
+    feature = "recovery-authority",
+    feature = "role-authorities"
+))]
+fn copy_optional_extension<T: Extension + Clone>(
+    source_extensions: &[u8],
+    destination_extensions: &mut [u8],
+) -> ProgramResult {
+    let Some(extension) = get_extension::<T>(source_extensions)? else {
+        return Ok(());
+    };
+
+    match init_extension::<T>(destination_extensions) {
+        Ok(destination_extension) => *destination_extension = extension.clone(),
+        Err(ProgramError::AccountDataTooSmall) => {}
+        Err(error) => return Err(error),
+    }
+
+    Ok(())
+}
+
+/// Checks that the extensions of `source` can be merged into `destination`
+/// without changing either, failing like `merge_extensions` would
+pub fn check_merge_extensions(
+    destination_extensions: &[u8],
+    source_extensions: &[u8],
+    _clock: &Clock,
+) -> ProgramResult {
+    if source_extensions.is_empty() {
+        return Ok(());
+    }
+
+    #[allow(unused_mut)]
+    let mut space = 0;
+    #[cfg(feature = "withdraw-allowlist")]
+    super::check_same_withdraw_allowlist(source_extensions, destination_extensions)?;
+    #[cfg(feature = "vesting")]
+    {
+        space += super::check_merge_vesting_schedules(
+            destination_extensions,
+            source_extensions,
+            _clock,
+        )?;
+    }
+    #[cfg(feature = "delayed-withdrawer-transfer")]
+    {
+        space += super::merge_delayed_withdrawer_transfer_space(
+            destination_extensions,
+            source_extensions,
+        )?;
+    }
+
+    if space > destination_extensions.len() - extensions_len(destination_extensions)? {
+        #[cfg(feature = "logging")]
+        pinocchio::msg!("Destination has no room for the extensions of source");
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+
+    Ok(())
+}
+
+/// Extension rules for `Merge`: the destination keeps its own extensions and
+/// takes over the obligations of the source ones, which are removed with the
+/// source state.
+pub fn merge_extensions(
+    destination_extensions: &mut [u8],
+    source_extensions: &mut [u8],
+    clock: &Clock,
+) -> ProgramResult {
+    if source_extensions.is_empty() {
+        return Ok(());
+    }
+
+    check_merge_extensions(destination_extensions, source_extensions, clock)?;
+
+    #[cfg(feature = "vesting")]
+    super::merge_vesting_schedules(destination_extensions, source_extensions, clock)?;
+    #[cfg(feature = "delayed-withdrawer-transfer")]
+    super::merge_delayed_withdrawer_transfers(destination_extensions, source_extensions)?;
+
+    clear_extensions(source_extensions);
+
+    Ok(())
+}
+
+/// Extension rules for `MoveStake` and `MoveLamports`: the extensions of both
+/// accounts stay in place, so lamports must not escape the safeguards of the source.
+pub fn check_move_extensions(
+    _source_extensions: &[u8],
+    _destination_extensions: &[u8],
+) -> ProgramResult {
+    #[cfg(feature = "delayed-withdrawer-transfer")]
+    super::check_move_delayed_withdrawer_transfer(_source_extensions, _destination_extensions)?;
+    #[cfg(feature = "withdraw-allowlist")]
+    super::check_same_withdraw_allowlist(_source_extensions, _destination_extensions)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    #[derive(Debug, PartialEq, Clone)]
+    struct First([u8; 4]);
+
+    impl Extension for First {
+        const TYPE: ExtensionType = ExtensionType::VestingSchedule;
+    }
+
+    #[repr(C)]
+    #[derive(Debug, PartialEq, Clone)]
+    struct Second([u8; 2]);
+
+    impl Extension for Second {
+        const TYPE: ExtensionType = ExtensionType::Metadata;
+    }
+
+    const FIRST_SPACE: usize = extension_space::<First>();
+    const SECOND_SPACE: usize = extension_space::<Second>();
+
+    #[test]
+    fn test_init_get_remove() {
+        let mut data = [0; FIRST_SPACE + SECOND_SPACE];
+        assert_eq!(get_extension::<First>(&data), Ok(None));
+        assert_eq!(find_extension(&data, ExtensionType::Metadata), Ok(Err(0)));
+
+        *init_extension::<First>(&mut data).unwrap() = First([1, 2, 3, 4]);
+        *init_extension::<Second>(&mut data).unwrap() = Second([5, 6]);
+        assert_eq!(data, [1, 0, 4, 0, 1, 2, 3, 4, 2, 0, 2, 0, 5, 6]);
+        assert_eq!(
+            find_extension(&data, ExtensionType::Metadata),
+            Ok(Ok((12, 14)))
+        );
+        assert_eq!(
+            find_extension(&data, ExtensionType::RoleAuthorities),
+            Ok(Err(14))
+        );
+        assert_eq!(
+            get_extension::<First>(&data),
+            Ok(Some(&First([1, 2, 3, 4])))
+        );
+        get_extension_mut::<Second>(&mut data).unwrap().unwrap().0 = [7, 8];
+        assert_eq!(get_extension::<Second>(&data), Ok(Some(&Second([7, 8]))));
+        assert_eq!(
+            init_extension::<First>(&mut data),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+
+        // removing moves the following entries up and clears the end
+        assert_eq!(remove_extension::<First>(&mut data), Ok(true));
+        assert_eq!(data, [2, 0, 2, 0, 7, 8, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(get_extension::<Second>(&data), Ok(Some(&Second([7, 8]))));
+        assert_eq!(remove_extension::<First>(&mut data), Ok(false));
+        assert_eq!(
+            get_extensions(&data).collect::<Vec<_>>(),
+            [Ok((ExtensionType::Metadata as u16, &[7, 8][..]))]
+        );
+    }
+
+    #[test]
+    fn test_init_without_room() {
+        assert_eq!(
+            init_extension::<First>(&mut []),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+        let mut data = [0; FIRST_SPACE + SECOND_SPACE - 1];
+        init_extension::<First>(&mut data).unwrap();
+        assert_eq!(
+            init_extension::<Second>(&mut data),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+    }
+
+    #[test]
+    fn test_malformed_extensions() {
+        // an entry running past the end of the area
+        let mut truncated = [1, 0, 4, 0, 1, 2, 3];
+        assert_eq!(
+            get_extension::<First>(&truncated),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            get_extension::<Second>(&truncated),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            init_extension::<Second>(&mut truncated),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            remove_extension::<First>(&mut truncated),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            get_extensions(&truncated).collect::<Vec<_>>(),
+            [Err(ProgramError::InvalidAccountData)]
+        );
+
+        // an entry of the wrong length for its type
+        let mut wrong_length = [1, 0, 2, 0, 1, 2];
+        assert_eq!(
+            get_extension::<First>(&wrong_length),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            get_extension_mut::<First>(&mut wrong_length),
+            Err(ProgramError::InvalidAccountData)
+        );
+
+        // trailing bytes too short for a header end the list
+        let data = [2, 0, 2, 0, 7, 8, 1, 0, 4];
+        assert_eq!(get_extension::<First>(&data), Ok(None));
+        assert_eq!(get_extensions(&data).count(), 1);
+    }
+
+    #[test]
+    fn test_clear_extensions() {
+        let mut data = [0; FIRST_SPACE];
+        init_extension::<First>(&mut data).unwrap();
+        clear_extensions(&mut data);
+        assert_eq!(data, [0; FIRST_SPACE]);
+    }
+
+    #[cfg(feature = "metadata")]
+    #[test]
+    fn test_split_and_merge_extensions() {
+        use crate::state::Metadata;
+
+        let clock = Clock::default();
+        let space = extension_space::<Metadata>();
+        let metadata = Metadata::new(b"name", &[]).unwrap();
+        let mut source = vec![0; space];
+        *init_extension::<Metadata>(&mut source).unwrap() = metadata.clone();
+
+        // the destination loses its previous extensions and gets a copy
+        let mut destination = vec![0xff; space];
+        split_extensions(&mut source, &mut destination, 1, 2, &clock).unwrap();
+        assert_eq!(get_extension::<Metadata>(&destination), Ok(Some(&metadata)));
+        assert_eq!(get_extension::<Metadata>(&source), Ok(Some(&metadata)));
+
+        // optional extensions are left out when there is no room
+        let mut small_destination = vec![0xff; space - 1];
+        split_extensions(&mut source, &mut small_destination, 1, 2, &clock).unwrap();
+        assert_eq!(small_destination, vec![0; space - 1]);
+
+        // the merged source is left without extensions
+        let mut destination = vec![0; space];
+        merge_extensions(&mut destination, &mut source, &clock).unwrap();
+        assert_eq!(source, vec![0; space]);
+        assert_eq!(get_extension::<Metadata>(&destination), Ok(None));
+    }
+}
@@ -6,7 +6,7 @@ use pinocchio::{
 
 use crate::PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH;
 
-use super::{Delegation, Meta, Stake, StakeFlags, StakeHistorySysvar, StakeStateV2};
+use super::{Delegation, Meta, Stake, StakeFlags, StakeHistoryGetEntry, StakeStateV2};
 
 #[derive(Debug, PartialEq)]
 // TODO: probaby can be optimized using references and/or change to struct
@@ -33,11 +33,11 @@ impl MergeKind {
         }
     }
 
-    pub(crate) fn get_if_mergeable(
+    pub(crate) fn get_if_mergeable<T: StakeHistoryGetEntry + ?Sized>(
         stake_state: &StakeStateV2,
         stake_lamports: u64,
         clock: &Clock,
-        stake_history: &StakeHistorySysvar,
+        stake_history: &T,
     ) -> Result<Self, ProgramError> {
         match stake_state {
             StakeStateV2::Stake(meta, stake, stake_flags) => {
@@ -84,6 +84,13 @@ impl MergeKind {
         source: Self,
         clock: &Clock,
     ) -> Result<Option<StakeStateV2>, ProgramError> {
+        Ok(self.merge_kind(source, clock)?.into_merged_state())
+    }
+
+    /// Merges `source` into `self` and returns the resulting destination kind,
+    /// so that further sources can be merged in without recomputing it from
+    /// the stake history
+    pub(crate) fn merge_kind(self, source: Self, clock: &Clock) -> Result<Self, ProgramError> {
         Self::metas_can_merge(self.meta(), source.meta(), clock)?;
         self.active_stake()
             .zip(source.active_stake())
@@ -91,9 +98,9 @@ impl MergeKind {
                 Self::active_delegations_can_merge(&stake.delegation, &source.delegation)
             })
             .unwrap_or(Ok(()))?;
-        let merged_state = match (self, source) {
-            (Self::Inactive(_, _, _), Self::Inactive(_, _, _)) => None,
-            (Self::Inactive(_, _, _), Self::ActivationEpoch(_, _, _)) => None,
+        let merged_kind = match (self, source) {
+            (destination @ Self::Inactive(_, _, _), Self::Inactive(_, _, _)) => destination,
+            (destination @ Self::Inactive(_, _, _), Self::ActivationEpoch(_, _, _)) => destination,
             (
                 Self::ActivationEpoch(meta, mut stake, stake_flags),
                 Self::Inactive(_, source_lamports, source_stake_flags),
@@ -102,11 +109,7 @@ impl MergeKind {
                     .checked_add(source_lamports)
                     .ok_or(ProgramError::InsufficientFunds)?
                     .into();
-                Some(StakeStateV2::Stake(
-                    meta,
-                    stake,
-                    stake_flags.union(source_stake_flags),
-                ))
+                Self::ActivationEpoch(meta, stake, stake_flags.union(source_stake_flags))
             }
             (
                 Self::ActivationEpoch(meta, mut stake, stake_flags),
@@ -124,11 +127,7 @@ impl MergeKind {
                     source_lamports,
                     source_stake.credits_observed.into(),
                 )?;
-                Some(StakeStateV2::Stake(
-                    meta,
-                    stake,
-                    stake_flags.union(source_stake_flags),
-                ))
+                Self::ActivationEpoch(meta, stake, stake_flags.union(source_stake_flags))
             }
             (Self::FullyActive(meta, mut stake), Self::FullyActive(_, source_stake)) => {
                 // Don't stake the source account's `rent_exempt_reserve` to
@@ -140,12 +139,26 @@ impl MergeKind {
                     source_stake.delegation.stake.into(),
                     source_stake.credits_observed.into(),
                 )?;
-                Some(StakeStateV2::Stake(meta, stake, StakeFlags::empty()))
+                Self::FullyActive(meta, stake)
             }
             // _ => return Err(StakeError::MergeMismatch.into()),
             _ => return Err(ProgramError::Custom(6)),
         };
-        Ok(merged_state)
+        Ok(merged_kind)
+    }
+
+    /// Stake state to be written to the destination after a merge, `None` if the
+    /// destination is inactive and its state is left untouched
+    pub(crate) fn into_merged_state(self) -> Option<StakeStateV2> {
+        match self {
+            Self::Inactive(_, _, _) => None,
+            Self::ActivationEpoch(meta, stake, stake_flags) => {
+                Some(StakeStateV2::Stake(meta, stake, stake_flags))
+            }
+            Self::FullyActive(meta, stake) => {
+                Some(StakeStateV2::Stake(meta, stake, StakeFlags::empty()))
+            }
+        }
     }
 
     pub(crate) fn metas_can_merge(stake: &Meta, source: &Meta, clock: &Clock) -> ProgramResult {
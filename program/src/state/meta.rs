@@ -1,4 +1,4 @@
-use pinocchio::{program_error::ProgramError, sysvars::clock::Clock};
+use pinocchio::program_error::ProgramError;
 
 use crate::instruction::LockupArgs;
 
@@ -18,16 +18,18 @@ pub struct SetLockupSignerArgs {
 }
 
 impl Meta {
+    /// Updates the lockup, `is_in_force` telling whether the lockup, or anything
+    /// else only the custodian may release, still holds the account
     pub fn set_lockup(
         &mut self,
         lockup: &LockupArgs,
         signer_args: SetLockupSignerArgs,
-        clock: &Clock,
+        is_in_force: bool,
     ) -> Result<(), ProgramError> {
         // post-stake_program_v4 behavior:
         // * custodian can update the lockup while in force
         // * withdraw authority can set a new lockup
-        if self.lockup.is_in_force(clock, None) {
+        if is_in_force {
             if !signer_args.has_custodian_signer {
                 return Err(ProgramError::MissingRequiredSignature);
             }
@@ -0,0 +1,127 @@
+use pinocchio::program_error::ProgramError;
+
+use super::{get_extension, Extension, ExtensionType, PodU16};
+
+/// Maximum length of the name of a stake account
+pub const MAX_METADATA_NAME_LEN: usize = 32;
+
+/// Maximum length of the encoded key/value pairs of a stake account
+pub const MAX_METADATA_ENTRIES_LEN: usize = 256;
+
+/// Name and free-form key/value pairs describing a stake account. They have no
+/// effect on the program.
+///
+/// Entries are encoded one after the other as a `u8` key length, the key, a `u8`
+/// value length and the value.
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone)]
+pub struct Metadata {
+    name_len: u8,
+    name: [u8; MAX_METADATA_NAME_LEN],
+    entries_len: PodU16,
+    entries: [u8; MAX_METADATA_ENTRIES_LEN],
+}
+
+impl Extension for Metadata {
+    const TYPE: ExtensionType = ExtensionType::Metadata;
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Self {
+            name_len: 0,
+            name: [0; MAX_METADATA_NAME_LEN],
+            entries_len: PodU16::default(),
+            entries: [0; MAX_METADATA_ENTRIES_LEN],
+        }
+    }
+}
+
+impl Metadata {
+    /// Builds metadata from a name and encoded entries, failing if either is too
+    /// long or the entries are malformed
+    pub fn new(name: &[u8], entries: &[u8]) -> Result<Self, ProgramError> {
+        if name.len() > MAX_METADATA_NAME_LEN || entries.len() > MAX_METADATA_ENTRIES_LEN {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        let mut metadata_name = [0; MAX_METADATA_NAME_LEN];
+        metadata_name[..name.len()].copy_from_slice(name);
+        let mut metadata_entries = [0; MAX_METADATA_ENTRIES_LEN];
+        metadata_entries[..entries.len()].copy_from_slice(entries);
+
+        let metadata = Self {
+            name_len: name.len() as u8,
+            name: metadata_name,
+            entries_len: (entries.len() as u16).into(),
+            entries: metadata_entries,
+        };
+
+        if metadata.entries().any(|entry| entry.is_err()) {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        Ok(metadata)
+    }
+
+    pub fn name(&self) -> &[u8] {
+        &self.name[..(self.name_len as usize).min(MAX_METADATA_NAME_LEN)]
+    }
+
+    /// Returns an iterator over the key/value pairs
+    pub fn entries(&self) -> MetadataEntryIter<'_> {
+        let entries_len = (u16::from(self.entries_len) as usize).min(MAX_METADATA_ENTRIES_LEN);
+        MetadataEntryIter {
+            entries: &self.entries[..entries_len],
+        }
+    }
+
+    /// Returns the value of the first entry with `key`, if any
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.entries()
+            .filter_map(Result::ok)
+            .find(|(entry_key, _)| *entry_key == key)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Iterator over the key/value pairs of [`Metadata`]
+pub struct MetadataEntryIter<'a> {
+    entries: &'a [u8],
+}
+
+impl<'a> MetadataEntryIter<'a> {
+    fn take(&mut self) -> Option<&'a [u8]> {
+        let (len, rest) = self.entries.split_first()?;
+        if rest.len() < *len as usize {
+            return None;
+        }
+        let (value, rest) = rest.split_at(*len as usize);
+        self.entries = rest;
+        Some(value)
+    }
+}
+
+impl<'a> Iterator for MetadataEntryIter<'a> {
+    type Item = Result<(&'a [u8], &'a [u8]), ProgramError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        match (self.take(), self.take()) {
+            (Some(key), Some(value)) => Some(Ok((key, value))),
+            _ => {
+                // stop at a malformed entry
+                self.entries = &[];
+                Some(Err(ProgramError::InvalidAccountData))
+            }
+        }
+    }
+}
+
+/// Returns the metadata of a stake account, if any
+pub fn get_metadata(extensions: &[u8]) -> Result<Option<&Metadata>, ProgramError> {
+    get_extension::<Metadata>(extensions)
+}
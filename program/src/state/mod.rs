@@ -1,28 +1,62 @@
 pub mod authorized;
+#[cfg(feature = "deactivation-schedule")]
+pub mod deactivation_schedule;
+#[cfg(feature = "delayed-withdrawer-transfer")]
+pub mod delayed_withdrawer_transfer;
 pub mod delegation;
+#[cfg(feature = "extensions")]
+pub mod extensions;
 pub mod lockup;
 pub mod merge_kind;
 pub mod meta;
+#[cfg(feature = "metadata")]
+pub mod metadata;
 pub mod pod;
+#[cfg(feature = "recovery-authority")]
+pub mod recovery_authority;
+#[cfg(feature = "role-authorities")]
+pub mod role_authorities;
 pub mod stake;
 pub mod stake_flags;
+pub mod stake_history;
 pub mod stake_history_entry;
 pub mod stake_history_sysvar;
 pub mod stake_state_v2;
+#[cfg(feature = "vesting")]
+pub mod vesting_schedule;
 pub mod vote_state;
+#[cfg(feature = "withdraw-allowlist")]
+pub mod withdraw_allowlist;
 
 pub use authorized::*;
+#[cfg(feature = "deactivation-schedule")]
+pub use deactivation_schedule::*;
+#[cfg(feature = "delayed-withdrawer-transfer")]
+pub use delayed_withdrawer_transfer::*;
 pub use delegation::*;
+#[cfg(feature = "extensions")]
+pub use extensions::*;
 pub use lockup::*;
 pub use merge_kind::*;
 pub use meta::*;
+#[cfg(feature = "metadata")]
+pub use metadata::*;
 pub use pod::*;
+#[cfg(feature = "recovery-authority")]
+pub use recovery_authority::*;
+#[cfg(feature = "role-authorities")]
+pub use role_authorities::*;
 pub use stake::*;
 pub use stake_flags::*;
+pub use stake_history::*;
 pub use stake_history_entry::*;
 pub use stake_history_sysvar::*;
 pub use stake_state_v2::*;
+#[cfg(feature = "vesting")]
+pub use vesting_schedule::*;
 pub use vote_state::*;
+#[cfg(feature = "withdraw-allowlist")]
+pub use withdraw_allowlist::*;
 
 use pinocchio::{
     account_info::{AccountInfo, Ref, RefMut},
@@ -65,3 +99,15 @@ pub fn get_stake_state_mut(
 
     StakeStateV2::from_account_info_mut(stake_account_info)
 }
+
+/// Borrows the data of a stake account, to be split into the state and the
+/// extension area with [`StakeStateV2::split_data_mut`]
+pub fn get_stake_account_data_mut(
+    stake_account_info: &AccountInfo,
+) -> Result<RefMut<'_, [u8]>, ProgramError> {
+    if !stake_account_info.is_owned_by(&crate::ID) {
+        return Err(ProgramError::InvalidAccountOwner);
+    }
+
+    stake_account_info.try_borrow_mut_data()
+}
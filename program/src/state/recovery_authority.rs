@@ -0,0 +1,66 @@
+use pinocchio::{program_error::ProgramError, pubkey::Pubkey, ProgramResult};
+
+use super::{
+    get_extension, get_extension_mut, Epoch, Extension, ExtensionType, PodU64, StakeStateV2,
+};
+
+/// Authority that may replace the withdrawer of a stake account once no
+/// instruction signed by the staker or the withdrawer has touched it for
+/// `inactivity_epochs`
+#[repr(C)]
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct RecoveryAuthority {
+    pub authority: Pubkey,
+    pub inactivity_epochs: PodU64,
+    /// epoch of the last instruction signed by the staker or the withdrawer
+    pub last_activity_epoch: Epoch,
+}
+
+impl Extension for RecoveryAuthority {
+    const TYPE: ExtensionType = ExtensionType::RecoveryAuthority;
+}
+
+impl RecoveryAuthority {
+    pub fn can_recover(&self, epoch: u64) -> bool {
+        epoch >= u64::from(self.last_activity_epoch).saturating_add(self.inactivity_epochs.into())
+    }
+}
+
+/// Records that an instruction signed by the staker or the withdrawer touched a
+/// stake account. A no-op for accounts without a recovery authority.
+pub fn record_activity(extensions: &mut [u8], epoch: u64) -> ProgramResult {
+    if let Some(recovery_authority) = get_extension_mut::<RecoveryAuthority>(extensions)? {
+        recovery_authority.last_activity_epoch = epoch.into();
+    }
+
+    Ok(())
+}
+
+/// Records the activity of an instruction if the staker or the withdrawer of
+/// the stake account signed it
+pub fn record_signed_activity(
+    stake_state: &StakeStateV2,
+    extensions: &mut [u8],
+    is_signer: impl Fn(&Pubkey) -> bool,
+    epoch: u64,
+) -> ProgramResult {
+    let is_signed = match stake_state {
+        StakeStateV2::Initialized(meta) | StakeStateV2::Stake(meta, _, _) => {
+            is_signer(&meta.authorized.staker) || is_signer(&meta.authorized.withdrawer)
+        }
+        _ => false,
+    };
+
+    if is_signed {
+        record_activity(extensions, epoch)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the recovery authority of a stake account, if any
+pub fn get_recovery_authority(
+    extensions: &[u8],
+) -> Result<Option<&RecoveryAuthority>, ProgramError> {
+    get_extension::<RecoveryAuthority>(extensions)
+}
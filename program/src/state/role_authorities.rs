@@ -0,0 +1,65 @@
+use pinocchio::{program_error::ProgramError, pubkey::Pubkey};
+
+use super::{get_extension, Extension, ExtensionType};
+
+/// Narrow roles that can be granted on top of the staker and the withdrawer
+#[repr(u8)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StakeRole {
+    /// may `Deactivate`
+    Deactivator = 0,
+    /// may `MoveStake` and `MoveLamports` out of the account
+    Rebalancer = 1,
+}
+
+/// Authorities of the optional roles of a stake account, the default pubkey
+/// standing for none
+#[repr(C)]
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct RoleAuthorities {
+    pub deactivator: Pubkey,
+    pub rebalancer: Pubkey,
+}
+
+impl Extension for RoleAuthorities {
+    const TYPE: ExtensionType = ExtensionType::RoleAuthorities;
+}
+
+impl RoleAuthorities {
+    pub fn get(&self, role: StakeRole) -> Option<&Pubkey> {
+        let authority = match role {
+            StakeRole::Deactivator => &self.deactivator,
+            StakeRole::Rebalancer => &self.rebalancer,
+        };
+        (*authority != Pubkey::default()).then_some(authority)
+    }
+
+    pub fn set(&mut self, role: StakeRole, authority: &Pubkey) {
+        match role {
+            StakeRole::Deactivator => self.deactivator = *authority,
+            StakeRole::Rebalancer => self.rebalancer = *authority,
+        }
+    }
+}
+
+/// Returns whether `authority` holds `role` on a stake account
+pub fn is_role_authority(
+    extensions: &[u8],
+    authority: &Pubkey,
+    role: StakeRole,
+) -> Result<bool, ProgramError> {
+    Ok(get_extension::<RoleAuthorities>(extensions)?
+        .and_then(|role_authorities| role_authorities.get(role))
+        == Some(authority))
+}
+
+/// Returns whether a signer holds `role` on a stake account
+pub fn has_role_signer(
+    extensions: &[u8],
+    is_signer: impl Fn(&Pubkey) -> bool,
+    role: StakeRole,
+) -> Result<bool, ProgramError> {
+    Ok(get_extension::<RoleAuthorities>(extensions)?
+        .and_then(|role_authorities| role_authorities.get(role))
+        .is_some_and(is_signer))
+}
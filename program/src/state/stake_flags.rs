@@ -9,6 +9,11 @@ impl StakeFlags {
         Self { bits: 0 }
     }
 
+    /// Raw flag bits, as serialized after the stake
+    pub const fn bits(&self) -> u8 {
+        self.bits
+    }
+
     pub const fn contains(&self, other: Self) -> bool {
         (self.bits & other.bits) == other.bits
     }
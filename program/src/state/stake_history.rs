@@ -0,0 +1,19 @@
+use pinocchio::sysvars::clock::Epoch;
+
+use super::StakeHistoryEntry;
+
+/// Source of stake history entries used by the activation and deactivation math.
+/// On chain this is the stake history sysvar, off chain it can be any list of
+/// entries.
+pub trait StakeHistoryGetEntry {
+    fn get_entry(&self, target_epoch: Epoch) -> Option<StakeHistoryEntry>;
+}
+
+/// Entries in the same order as the stake history sysvar, newest epoch first
+impl StakeHistoryGetEntry for [(Epoch, StakeHistoryEntry)] {
+    fn get_entry(&self, target_epoch: Epoch) -> Option<StakeHistoryEntry> {
+        self.binary_search_by(|(epoch, _)| target_epoch.cmp(epoch))
+            .ok()
+            .map(|index| self[index].1.clone())
+    }
+}
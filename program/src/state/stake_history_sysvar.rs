@@ -3,7 +3,7 @@ use pinocchio_pubkey::pubkey;
 
 use crate::pinocchio_add::sysvar::get_sysvar_unchecked;
 
-use super::StakeHistoryEntry;
+use super::{StakeHistoryEntry, StakeHistoryGetEntry};
 
 pub const SYSVAR_STAKE_HISTORY_ID: Pubkey = pubkey!("SysvarStakeHistory1111111111111111111111111");
 
@@ -15,8 +15,8 @@ pub const MAX_ENTRIES: usize = 512; // it should never take as many as 512 epoch
 // precompute so we can statically allocate buffer
 const EPOCH_AND_ENTRY_SERIALIZED_SIZE: u64 = 32;
 
-impl StakeHistorySysvar {
-    pub fn get_entry(&self, target_epoch: Epoch) -> Option<StakeHistoryEntry> {
+impl StakeHistoryGetEntry for StakeHistorySysvar {
+    fn get_entry(&self, target_epoch: Epoch) -> Option<StakeHistoryEntry> {
         let current_epoch = self.0;
 
         // if current epoch is zero this returns None because there is no history yet
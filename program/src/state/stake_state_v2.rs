@@ -6,7 +6,7 @@ use pinocchio::{
 use super::{Meta, Stake, StakeFlags};
 
 #[repr(u32)]
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Default, PartialEq, Clone)]
 pub enum StakeStateV2 {
     #[default]
     Uninitialized = 0,
@@ -94,6 +94,53 @@ impl StakeStateV2 {
         Ok(Self::from_bytes_mut(data))
     }
 
+    /// Splits the data of a stake account into its state and the extension area
+    /// after it
+    #[inline]
+    pub fn split_data(data: &[u8]) -> Result<(&Self, &[u8]), ProgramError> {
+        if data.len() < Self::size_of() || data[0] > 3 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let (state, extensions) = data.split_at(Self::size_of());
+        Ok((unsafe { Self::from_bytes(state) }, extensions))
+    }
+
+    /// Splits the data of a stake account into its state and the extension area
+    /// after it, to be updated together
+    #[inline]
+    pub fn split_data_mut(data: &mut [u8]) -> Result<(&mut Self, &mut [u8]), ProgramError> {
+        if data.len() < Self::size_of() || data[0] > 3 {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let (state, extensions) = data.split_at_mut(Self::size_of());
+        Ok((unsafe { Self::from_bytes_mut(state) }, extensions))
+    }
+
+    /// Decodes a copy of the state at the start of `data`, which may be unaligned,
+    /// e.g. account data read off chain
+    pub fn try_from_slice(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::size_of() || data[0] > 3 || data[1..4] != [0; 3] {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // aligned like the discriminant
+        let mut buffer = [0u32; Self::size_of() / 4];
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                buffer.as_mut_ptr() as *mut u8,
+                Self::size_of(),
+            );
+            Ok(Self::from_bytes(core::slice::from_raw_parts(
+                buffer.as_ptr() as *const u8,
+                Self::size_of(),
+            ))
+            .clone())
+        }
+    }
+
     /// # Safety
     ///
     /// The caller must ensure that `bytes` contains a valid representation of `StakeStateV2`.
@@ -0,0 +1,186 @@
+use pinocchio::{program_error::ProgramError, sysvars::clock::Clock, ProgramResult};
+
+use super::{
+    extension_space, get_extension, get_extension_mut, init_extension, Extension, ExtensionType,
+    PodI64, PodU64, UnixTimestamp,
+};
+
+/// Gradually releases lamports of a stake account, on top of its lockup.
+/// Lamports that are not yet vested may be staked, but cannot leave the account
+/// unless the lockup custodian signs.
+#[repr(C)]
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct VestingSchedule {
+    /// lamports subject to vesting
+    pub amount: PodU64,
+    /// UnixTimestamp at which vesting starts, nothing is vested before
+    pub start_timestamp: UnixTimestamp,
+    /// UnixTimestamp at which the whole amount is vested
+    pub end_timestamp: UnixTimestamp,
+    /// seconds between vesting steps, zero for linear vesting
+    pub period: PodI64,
+}
+
+impl Extension for VestingSchedule {
+    const TYPE: ExtensionType = ExtensionType::VestingSchedule;
+}
+
+impl VestingSchedule {
+    pub fn is_valid(&self) -> bool {
+        i64::from(self.end_timestamp) > i64::from(self.start_timestamp)
+            && i64::from(self.period) >= 0
+    }
+
+    /// Lamports that are still locked at `clock`
+    pub fn unvested_amount(&self, clock: &Clock) -> u64 {
+        let amount = u64::from(self.amount);
+        let start_timestamp = i64::from(self.start_timestamp);
+        let end_timestamp = i64::from(self.end_timestamp);
+
+        if clock.unix_timestamp >= end_timestamp {
+            return 0;
+        }
+        if clock.unix_timestamp <= start_timestamp {
+            return amount;
+        }
+
+        let mut elapsed = clock.unix_timestamp.abs_diff(start_timestamp);
+        let period = i64::from(self.period).unsigned_abs();
+        if period != 0 {
+            // stepwise vesting only releases whole periods
+            elapsed -= elapsed % period;
+        }
+        let duration = end_timestamp.abs_diff(start_timestamp);
+
+        let vested = u128::from(amount) * u128::from(elapsed) / u128::from(duration);
+        amount - vested as u64
+    }
+
+    /// Takes the share of the schedule proportional to `split_lamports` out of
+    /// `source_lamports` and returns it as a new schedule with the same timing
+    pub fn split(&mut self, split_lamports: u64, source_lamports: u64) -> Self {
+        let amount = u64::from(self.amount);
+        let split_amount = if source_lamports == 0 {
+            0
+        } else {
+            (u128::from(amount) * u128::from(split_lamports.min(source_lamports))
+                / u128::from(source_lamports)) as u64
+        };
+        // rounding leaves the remainder with the source
+        self.amount = (amount - split_amount).into();
+
+        Self {
+            amount: split_amount.into(),
+            ..self.clone()
+        }
+    }
+
+    fn has_same_timing(&self, other: &Self) -> bool {
+        self.start_timestamp == other.start_timestamp
+            && self.end_timestamp == other.end_timestamp
+            && self.period == other.period
+    }
+}
+
+/// Lamports of a stake account that are still locked by its vesting schedule
+pub fn get_unvested_lamports(extensions: &[u8], clock: &Clock) -> Result<u64, ProgramError> {
+    Ok(get_extension::<VestingSchedule>(extensions)?
+        .map(|vesting_schedule| vesting_schedule.unvested_amount(clock))
+        .unwrap_or(0))
+}
+
+/// Carries the share of the source vesting schedule proportional to
+/// `split_lamports` over to the split destination. Fails if the destination
+/// cannot hold a schedule with unvested lamports.
+pub fn split_vesting_schedule(
+    source_extensions: &mut [u8],
+    destination_extensions: &mut [u8],
+    split_lamports: u64,
+    source_lamports: u64,
+    clock: &Clock,
+) -> ProgramResult {
+    let Some(source_vesting_schedule) = get_extension_mut::<VestingSchedule>(source_extensions)?
+    else {
+        return Ok(());
+    };
+
+    let destination_vesting_schedule =
+        source_vesting_schedule.split(split_lamports, source_lamports);
+    if destination_vesting_schedule.unvested_amount(clock) == 0 {
+        return Ok(());
+    }
+
+    *init_extension::<VestingSchedule>(destination_extensions)? = destination_vesting_schedule;
+
+    Ok(())
+}
+
+/// Checks that the source vesting schedule can be merged into the destination
+/// and returns the room the destination needs for it
+pub fn check_merge_vesting_schedules(
+    destination_extensions: &[u8],
+    source_extensions: &[u8],
+    clock: &Clock,
+) -> Result<usize, ProgramError> {
+    let Some(source_vesting_schedule) = get_extension::<VestingSchedule>(source_extensions)? else {
+        return Ok(0);
+    };
+    if source_vesting_schedule.unvested_amount(clock) == 0 {
+        return Ok(0);
+    }
+
+    match get_extension::<VestingSchedule>(destination_extensions)? {
+        Some(destination_vesting_schedule)
+            if destination_vesting_schedule.unvested_amount(clock) != 0
+                && !destination_vesting_schedule.has_same_timing(source_vesting_schedule) =>
+        {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Unable to merge due to vesting schedule mismatch");
+            // StakeError::MergeMismatch
+            Err(ProgramError::Custom(6))
+        }
+        Some(_) => Ok(0),
+        None => Ok(extension_space::<VestingSchedule>()),
+    }
+}
+
+/// Adds the unvested part of the source vesting schedule to the merge
+/// destination. Schedules can only be merged if their timing matches.
+pub fn merge_vesting_schedules(
+    destination_extensions: &mut [u8],
+    source_extensions: &[u8],
+    clock: &Clock,
+) -> ProgramResult {
+    let Some(source_vesting_schedule) = get_extension::<VestingSchedule>(source_extensions)? else {
+        return Ok(());
+    };
+    if source_vesting_schedule.unvested_amount(clock) == 0 {
+        return Ok(());
+    }
+
+    match get_extension_mut::<VestingSchedule>(destination_extensions)? {
+        Some(destination_vesting_schedule)
+            if destination_vesting_schedule.unvested_amount(clock) == 0 =>
+        {
+            *destination_vesting_schedule = source_vesting_schedule.clone();
+        }
+        Some(destination_vesting_schedule) => {
+            if !destination_vesting_schedule.has_same_timing(source_vesting_schedule) {
+                #[cfg(feature = "logging")]
+                pinocchio::msg!("Unable to merge due to vesting schedule mismatch");
+                // StakeError::MergeMismatch
+                return Err(ProgramError::Custom(6));
+            }
+            destination_vesting_schedule.amount = u64::from(destination_vesting_schedule.amount)
+                .checked_add(source_vesting_schedule.amount.into())
+                .ok_or(ProgramError::ArithmeticOverflow)?
+                .into();
+        }
+        None => {
+            *init_extension::<VestingSchedule>(destination_extensions)? =
+                source_vesting_schedule.clone();
+        }
+    }
+
+    Ok(())
+}
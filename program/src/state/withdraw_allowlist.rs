@@ -0,0 +1,162 @@
+use pinocchio::{program_error::ProgramError, pubkey::Pubkey, ProgramResult};
+
+use super::{get_extension, init_extension, Epoch, Extension, ExtensionType, PodU64};
+
+/// Maximum number of allowed withdraw destinations
+pub const MAX_ALLOWED_DESTINATIONS: usize = 4;
+
+/// Error returned by `Withdraw` to a destination that is not allowed. Custom
+/// errors of this program start at 100 to stay clear of `StakeError`.
+pub const WITHDRAW_DESTINATION_NOT_ALLOWED: u32 = 100;
+
+/// Error returned by `Merge`, `MoveStake` and `MoveLamports` between accounts
+/// with different allowlists
+pub const WITHDRAW_ALLOWLIST_MISMATCH: u32 = 101;
+
+/// Restricts the destinations of withdrawals from a stake account. Changes only
+/// take effect after a timelock, so that a compromised withdrawer cannot
+/// withdraw elsewhere before the owner can react.
+#[repr(C)]
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct WithdrawAllowlist {
+    /// epochs between a change and its effect
+    pub timelock_epochs: PodU64,
+    pub destinations_len: u8,
+    pub destinations: [Pubkey; MAX_ALLOWED_DESTINATIONS],
+    /// timelock taking effect with the pending destinations
+    pub pending_timelock_epochs: PodU64,
+    pub pending_destinations_len: u8,
+    pub pending_destinations: [Pubkey; MAX_ALLOWED_DESTINATIONS],
+    /// epoch from which the pending destinations apply, `u64::MAX` if there is
+    /// no pending change
+    pub pending_effective_epoch: Epoch,
+}
+
+impl Extension for WithdrawAllowlist {
+    const TYPE: ExtensionType = ExtensionType::WithdrawAllowlist;
+}
+
+impl WithdrawAllowlist {
+    pub fn new(timelock_epochs: u64, destinations: &[Pubkey]) -> Result<Self, ProgramError> {
+        if destinations.len() > MAX_ALLOWED_DESTINATIONS {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        let mut allowlist = Self {
+            timelock_epochs: timelock_epochs.into(),
+            destinations_len: destinations.len() as u8,
+            pending_effective_epoch: u64::MAX.into(),
+            ..Self::default()
+        };
+        allowlist.destinations[..destinations.len()].copy_from_slice(destinations);
+
+        Ok(allowlist)
+    }
+
+    /// Destinations allowed at `epoch`. An empty list allows any destination.
+    pub fn destinations(&self, epoch: u64) -> &[Pubkey] {
+        if epoch >= u64::from(self.pending_effective_epoch) {
+            &self.pending_destinations
+                [..(self.pending_destinations_len as usize).min(MAX_ALLOWED_DESTINATIONS)]
+        } else {
+            &self.destinations[..(self.destinations_len as usize).min(MAX_ALLOWED_DESTINATIONS)]
+        }
+    }
+
+    pub fn timelock_epochs(&self, epoch: u64) -> u64 {
+        if epoch >= u64::from(self.pending_effective_epoch) {
+            self.pending_timelock_epochs.into()
+        } else {
+            self.timelock_epochs.into()
+        }
+    }
+
+    pub fn is_allowed(&self, destination: &Pubkey, epoch: u64) -> bool {
+        let destinations = self.destinations(epoch);
+        destinations.is_empty() || destinations.contains(destination)
+    }
+
+    /// Stages new destinations and timelock, to apply once the current timelock
+    /// has passed. Replaces any change that has not applied yet.
+    pub fn stage(
+        &mut self,
+        timelock_epochs: u64,
+        destinations: &[Pubkey],
+        epoch: u64,
+    ) -> ProgramResult {
+        if destinations.len() > MAX_ALLOWED_DESTINATIONS {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        // a change that already applies becomes the current state
+        if epoch >= u64::from(self.pending_effective_epoch) {
+            self.timelock_epochs = self.pending_timelock_epochs;
+            self.destinations_len = self.pending_destinations_len;
+            self.destinations = self.pending_destinations;
+        }
+
+        self.pending_timelock_epochs = timelock_epochs.into();
+        self.pending_destinations_len = destinations.len() as u8;
+        self.pending_destinations = [Pubkey::default(); MAX_ALLOWED_DESTINATIONS];
+        self.pending_destinations[..destinations.len()].copy_from_slice(destinations);
+        self.pending_effective_epoch = epoch
+            .saturating_add(self.timelock_epochs.into())
+            .min(u64::MAX - 1)
+            .into();
+
+        Ok(())
+    }
+}
+
+/// Fails with `WITHDRAW_DESTINATION_NOT_ALLOWED` if the allowlist of a stake
+/// account does not allow withdrawing to `destination`
+pub fn check_withdraw_destination(
+    extensions: &[u8],
+    destination: &Pubkey,
+    epoch: u64,
+) -> ProgramResult {
+    match get_extension::<WithdrawAllowlist>(extensions)? {
+        Some(allowlist) if !allowlist.is_allowed(destination, epoch) => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Withdraw destination is not allowed");
+            Err(ProgramError::Custom(WITHDRAW_DESTINATION_NOT_ALLOWED))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Copies the allowlist of the source to the split destination. Fails if the
+/// destination has no room for it.
+pub fn split_withdraw_allowlist(
+    source_extensions: &[u8],
+    destination_extensions: &mut [u8],
+) -> ProgramResult {
+    let Some(source_allowlist) = get_extension::<WithdrawAllowlist>(source_extensions)? else {
+        return Ok(());
+    };
+
+    *init_extension::<WithdrawAllowlist>(destination_extensions)? = source_allowlist.clone();
+
+    Ok(())
+}
+
+/// Lamports of an account with an allowlist can only be merged or moved into an
+/// account with the same allowlist, fails with `WITHDRAW_ALLOWLIST_MISMATCH`
+/// otherwise
+pub fn check_same_withdraw_allowlist(
+    source_extensions: &[u8],
+    destination_extensions: &[u8],
+) -> ProgramResult {
+    let Some(source_allowlist) = get_extension::<WithdrawAllowlist>(source_extensions)? else {
+        return Ok(());
+    };
+
+    match get_extension::<WithdrawAllowlist>(destination_extensions)? {
+        Some(destination_allowlist) if destination_allowlist == source_allowlist => Ok(()),
+        _ => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Destination does not have the withdraw allowlist of source");
+            Err(ProgramError::Custom(WITHDRAW_ALLOWLIST_MISMATCH))
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Fixtures shared by the unit tests of the transitions and the instructions
+//! built on them
+
+use pinocchio::{
+    pubkey::Pubkey,
+    sysvars::{clock::Clock, rent::Rent},
+};
+
+#[cfg(feature = "extensions")]
+use crate::state::{extension_space, init_extension, Extension};
+#[cfg(feature = "recovery-authority")]
+use crate::state::{get_recovery_authority, RecoveryAuthority};
+use crate::{
+    state::{Authorized, Lockup, Meta, StakeHistoryEntry, StakeStateV2},
+    transition::delegate,
+};
+
+pub const RESERVE: u64 = 2_282_880;
+pub const STAKER: Pubkey = [1; 32];
+pub const WITHDRAWER: Pubkey = [2; 32];
+pub const VOTER: Pubkey = [3; 32];
+
+pub fn clock(epoch: u64) -> Clock {
+    Clock {
+        slot: 0,
+        epoch_start_timestamp: 0,
+        epoch,
+        leader_schedule_epoch: epoch,
+        unix_timestamp: 0,
+    }
+}
+
+/// Rent charging for the account size, unlike the default one
+pub fn rent() -> Rent {
+    Rent {
+        lamports_per_byte_year: 3_480,
+        exemption_threshold: 2.0,
+        burn_percent: 50,
+    }
+}
+
+pub fn initialized() -> StakeStateV2 {
+    StakeStateV2::Initialized(Meta {
+        rent_exempt_reserve: RESERVE.into(),
+        authorized: Authorized {
+            staker: STAKER,
+            withdrawer: WITHDRAWER,
+        },
+        lockup: Lockup::default(),
+    })
+}
+
+pub fn signed_by(signer: Pubkey) -> impl Fn(&Pubkey) -> bool {
+    move |pubkey| *pubkey == signer
+}
+
+pub fn delegated(lamports: u64, epoch: u64) -> StakeStateV2 {
+    let history: &[(u64, StakeHistoryEntry)] = &[];
+    let mut stake_state = initialized();
+    delegate(
+        &mut stake_state,
+        &mut [],
+        lamports,
+        &VOTER,
+        0,
+        signed_by(STAKER),
+        &clock(epoch),
+        history,
+    )
+    .unwrap();
+    stake_state
+}
+
+/// Extension area holding `extension` and nothing else
+#[cfg(feature = "extensions")]
+pub fn with_extension<T: Extension>(extension: T) -> Vec<u8> {
+    let mut extensions = vec![0; extension_space::<T>()];
+    *init_extension::<T>(&mut extensions).unwrap() = extension;
+    extensions
+}
+
+#[cfg(feature = "recovery-authority")]
+pub const RECOVERER: Pubkey = [4; 32];
+
+/// Extension area with room to spare, holding a recovery authority whose
+/// last activity was in epoch 0
+#[cfg(feature = "recovery-authority")]
+pub fn with_recovery_authority() -> Vec<u8> {
+    let mut extensions = vec![0; 1_024];
+    *init_extension::<RecoveryAuthority>(&mut extensions).unwrap() = RecoveryAuthority {
+        authority: RECOVERER,
+        inactivity_epochs: 10.into(),
+        last_activity_epoch: 0.into(),
+    };
+    extensions
+}
+
+#[cfg(feature = "recovery-authority")]
+pub fn last_activity_epoch(extensions: &[u8]) -> u64 {
+    get_recovery_authority(extensions)
+        .unwrap()
+        .unwrap()
+        .last_activity_epoch
+        .into()
+}
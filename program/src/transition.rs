@@ -0,0 +1,2063 @@
+//! Pure state transitions of the stake instructions. Each function takes the
+//! decoded states and balances of the accounts an instruction works on, their
+//! extension areas, the signers and the sysvars, and updates the states and the
+//! extensions in place, like the processors do with the account data, so the
+//! rules can be reused off chain by simulators, wallets and tests. On error the
+//! accounts may be partly updated and must be discarded, as the runtime does
+//! for a failed instruction.
+//!
+//! The extension area of an account is its data after the stake state, empty
+//! for accounts of `StakeStateV2::size_of()` bytes.
+//!
+//! The processors in [`crate::instruction`] are thin adapters around these
+//! functions: they read the accounts, check account-level properties such as
+//! sizes, writability and derived addresses, and move the lamports.
+
+use pinocchio::{
+    account_info::AccountInfo,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvars::{
+        clock::{Clock, Epoch},
+        rent::Rent,
+    },
+};
+
+#[cfg(feature = "deactivation-schedule")]
+use crate::instruction::NO_DEACTIVATION_SCHEDULE;
+#[cfg(feature = "withdraw-max")]
+use crate::instruction::WITHDRAW_MAX_LAMPORTS;
+#[cfg(feature = "withdraw-allowlist")]
+use crate::state::check_withdraw_destination;
+#[cfg(feature = "vesting")]
+use crate::state::get_unvested_lamports;
+#[cfg(feature = "delayed-withdrawer-transfer")]
+use crate::state::has_delayed_withdrawer_transfer;
+#[cfg(any(
+    feature = "metadata",
+    feature = "deactivation-schedule",
+    feature = "recovery-authority"
+))]
+use crate::state::remove_extension;
+#[cfg(feature = "delayed-withdrawer-transfer")]
+use crate::state::DelayedWithdrawerTransfer;
+#[cfg(feature = "metadata")]
+use crate::state::Metadata;
+#[cfg(feature = "role-authorities")]
+use crate::state::RoleAuthorities;
+#[cfg(feature = "vesting")]
+use crate::state::VestingSchedule;
+#[cfg(feature = "withdraw-allowlist")]
+use crate::state::WithdrawAllowlist;
+#[cfg(feature = "extensions")]
+use crate::state::{check_move_extensions, merge_extensions, split_extensions};
+#[cfg(feature = "deactivation-schedule")]
+use crate::state::{get_extension, DeactivationSchedule};
+#[cfg(any(
+    feature = "vesting",
+    feature = "metadata",
+    feature = "deactivation-schedule",
+    feature = "withdraw-allowlist",
+    feature = "role-authorities",
+    feature = "delayed-withdrawer-transfer",
+    feature = "recovery-authority"
+))]
+use crate::state::{get_extension_mut, init_extension};
+#[cfg(feature = "recovery-authority")]
+use crate::state::{get_recovery_authority, RecoveryAuthority};
+#[cfg(feature = "role-authorities")]
+use crate::state::{has_role_signer, is_role_authority, StakeRole};
+#[cfg(feature = "recovery-authority")]
+use crate::state::{record_activity, record_signed_activity};
+use crate::{
+    instruction::LockupArgs,
+    state::{
+        merge_delegation_stake_and_credits_observed, AuthorizeSignerArgs, Authorized, Delegation,
+        Lockup, MergeKind, Meta, SetLockupSignerArgs, Stake, StakeAuthorize, StakeFlags,
+        StakeHistoryGetEntry, StakeStateV2, MINIMUM_DELINQUENT_EPOCHS_FOR_DEACTIVATION,
+    },
+    PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH,
+};
+
+/// Returns whether a pubkey signed, as one of `accounts`
+#[inline(always)]
+pub fn account_signers(accounts: &[AccountInfo]) -> impl Fn(&Pubkey) -> bool + '_ {
+    move |pubkey| {
+        accounts
+            .iter()
+            .any(|account| account.is_signer() && account.key() == pubkey)
+    }
+}
+
+#[inline(always)]
+fn check_staker_signer(
+    meta: &Meta,
+    is_signer: &impl Fn(&Pubkey) -> bool,
+) -> Result<(), ProgramError> {
+    if !is_signer(&meta.authorized.staker) {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    Ok(())
+}
+
+/// `Initialize`: an uninitialized account holding at least the rent exempt
+/// reserve of its size becomes initialized
+pub fn initialize(
+    stake_state: &mut StakeStateV2,
+    extensions: &mut [u8],
+    lamports: u64,
+    authorized: Authorized,
+    lockup: Lockup,
+    rent: &Rent,
+) -> Result<(), ProgramError> {
+    match stake_state {
+        StakeStateV2::Uninitialized => {
+            let rent_exempt_reserve =
+                rent.minimum_balance(StakeStateV2::size_of() + extensions.len());
+            if lamports >= rent_exempt_reserve {
+                // a closed account may still hold the extensions of its previous life
+                extensions.fill(0);
+
+                *stake_state = StakeStateV2::Initialized(Meta {
+                    rent_exempt_reserve: rent_exempt_reserve.into(),
+                    authorized,
+                    lockup,
+                });
+                Ok(())
+            } else {
+                Err(ProgramError::InsufficientFunds)
+            }
+        }
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+/// `InitializeAndDelegate`: `Initialize` followed by `DelegateStake`, signed by
+/// the new staker
+#[cfg(feature = "initialize-and-delegate")]
+#[allow(clippy::too_many_arguments)]
+pub fn initialize_and_delegate<T: StakeHistoryGetEntry + ?Sized>(
+    stake_state: &mut StakeStateV2,
+    extensions: &mut [u8],
+    lamports: u64,
+    authorized: Authorized,
+    lockup: Lockup,
+    vote_pubkey: &Pubkey,
+    credits: u64,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    clock: &Clock,
+    stake_history: &T,
+    rent: &Rent,
+) -> Result<(), ProgramError> {
+    // the new staker must sign, as it would have to sign a separate DelegateStake
+    if !is_signer(&authorized.staker) {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    initialize(stake_state, extensions, lamports, authorized, lockup, rent)?;
+    delegate(
+        stake_state,
+        extensions,
+        lamports,
+        vote_pubkey,
+        credits,
+        is_signer,
+        clock,
+        stake_history,
+    )
+}
+
+/// `CreateAccount`: returns the state of a new account of
+/// `StakeStateV2::size_of()` bytes funded with `lamports` and initialized
+#[cfg(feature = "create-account")]
+pub fn create_account(
+    lamports: u64,
+    authorized: Authorized,
+    lockup: Lockup,
+    rent: &Rent,
+) -> Result<StakeStateV2, ProgramError> {
+    let mut stake_state = StakeStateV2::Uninitialized;
+    initialize(
+        &mut stake_state,
+        &mut [],
+        lamports,
+        authorized,
+        lockup,
+        rent,
+    )?;
+    Ok(stake_state)
+}
+
+/// `Authorize` and its variants: replaces the staker or the withdrawer
+#[cfg_attr(
+    not(any(
+        feature = "delayed-withdrawer-transfer",
+        feature = "recovery-authority"
+    )),
+    allow(unused_variables)
+)]
+pub fn authorize(
+    stake_state: &mut StakeStateV2,
+    extensions: &mut [u8],
+    new_authority: &Pubkey,
+    authority_type: StakeAuthorize,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    custodian: Option<&Pubkey>,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    #[cfg(feature = "delayed-withdrawer-transfer")]
+    if authority_type == StakeAuthorize::Withdrawer && has_delayed_withdrawer_transfer(extensions)?
+    {
+        #[cfg(feature = "logging")]
+        pinocchio::msg!("Withdrawer must be proposed and accepted after a delay");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    match stake_state {
+        StakeStateV2::Initialized(meta) | StakeStateV2::Stake(meta, _, _) => {
+            let signer_args = AuthorizeSignerArgs {
+                has_staker_signer: is_signer(&meta.authorized.staker),
+                has_withdrawer_signer: is_signer(&meta.authorized.withdrawer),
+            };
+            meta.authorized.authorize(
+                signer_args,
+                new_authority,
+                authority_type,
+                (&meta.lockup, clock, custodian),
+            )?;
+        }
+        _ => return Err(ProgramError::InvalidAccountData),
+    }
+
+    // authorizing requires the staker or the withdrawer, possibly through a seed
+    #[cfg(feature = "recovery-authority")]
+    record_activity(extensions, clock.epoch)?;
+
+    Ok(())
+}
+
+/// `AuthorizeWithProgramAddress`: `Authorize` signed by `program_address`, the
+/// program derived address checked against the seeds of the instruction. Other
+/// signers, such as the account the address is derived from, do not count.
+#[cfg(feature = "authorize-with-program-address")]
+pub fn authorize_with_program_address(
+    stake_state: &mut StakeStateV2,
+    extensions: &mut [u8],
+    new_authority: &Pubkey,
+    authority_type: StakeAuthorize,
+    program_address: Option<&Pubkey>,
+    custodian: Option<&Pubkey>,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    authorize(
+        stake_state,
+        extensions,
+        new_authority,
+        authority_type,
+        |pubkey| program_address == Some(pubkey) || custodian == Some(pubkey),
+        custodian,
+        clock,
+    )
+}
+
+/// Returns the amount delegated from an account holding `lamports`, which must
+/// meet the minimum delegation once the rent exempt reserve is set aside
+#[inline(always)]
+pub fn delegated_amount(lamports: u64, meta: &Meta) -> Result<u64, ProgramError> {
+    let stake_amount = lamports.saturating_sub(meta.rent_exempt_reserve.into()); // can't stake the rent
+
+    // Stake accounts may be initialized with a stake amount below the minimum
+    // delegation so check that the minimum is met before delegation.
+    if stake_amount < crate::get_minimum_delegation() {
+        // StakeError::InsufficientDelegation
+        return Err(ProgramError::Custom(12));
+    }
+    Ok(stake_amount)
+}
+
+/// `DelegateStake`: delegates an initialized account, or redelegates a
+/// delegated one, to `vote_pubkey`
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(not(feature = "recovery-authority"), allow(unused_variables))]
+pub fn delegate<T: StakeHistoryGetEntry + ?Sized>(
+    stake_state: &mut StakeStateV2,
+    extensions: &mut [u8],
+    lamports: u64,
+    vote_pubkey: &Pubkey,
+    credits: u64,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    clock: &Clock,
+    stake_history: &T,
+) -> Result<(), ProgramError> {
+    match stake_state {
+        StakeStateV2::Initialized(meta) => {
+            check_staker_signer(meta, &is_signer)?;
+
+            let stake_amount = delegated_amount(lamports, meta)?;
+
+            let stake = Stake {
+                delegation: Delegation::new(vote_pubkey, stake_amount.into(), clock.epoch.into()),
+                credits_observed: credits.into(),
+            };
+
+            *stake_state = StakeStateV2::Stake(meta.clone(), stake, StakeFlags::empty());
+        }
+        StakeStateV2::Stake(meta, stake, _stake_flags) => {
+            check_staker_signer(meta, &is_signer)?;
+
+            let stake_amount = delegated_amount(lamports, meta)?;
+
+            redelegate_stake(
+                stake,
+                stake_amount,
+                vote_pubkey,
+                credits,
+                clock.epoch,
+                stake_history,
+            )?;
+        }
+        _ => return Err(ProgramError::InvalidAccountData),
+    }
+
+    #[cfg(feature = "recovery-authority")]
+    record_signed_activity(stake_state, extensions, is_signer, clock.epoch)?;
+
+    Ok(())
+}
+
+fn redelegate_stake<T: StakeHistoryGetEntry + ?Sized>(
+    stake: &mut Stake,
+    stake_lamports: u64,
+    voter_pubkey: &Pubkey,
+    credits: u64,
+    epoch: Epoch,
+    stake_history: &T,
+) -> Result<(), ProgramError> {
+    // If stake is currently active:
+    if stake.delegation.get_effective_stake(
+        epoch,
+        stake_history,
+        PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH,
+    ) != 0
+    {
+        // If pubkey of new voter is the same as current,
+        // and we are scheduled to start deactivating this epoch,
+        // we rescind deactivation
+        if stake.delegation.voter_pubkey == *voter_pubkey
+            && epoch == u64::from(stake.delegation.deactivation_epoch)
+        {
+            stake.delegation.deactivation_epoch = u64::MAX.into();
+            return Ok(());
+        } else {
+            // can't redelegate to another pubkey if stake is active.
+            // return Err(StakeError::TooSoonToRedelegate.into());
+            return Err(ProgramError::Custom(3));
+        }
+    }
+    // Either the stake is freshly activated, is active but has been
+    // deactivated this epoch, or has fully de-activated.
+    // Redelegation implies either re-activation or un-deactivation
+
+    stake.delegation.stake = stake_lamports.into();
+    stake.delegation.activation_epoch = epoch.into();
+    stake.delegation.deactivation_epoch = u64::MAX.into();
+    stake.delegation.voter_pubkey = *voter_pubkey;
+    stake.credits_observed = credits.into();
+    Ok(())
+}
+
+/// `Split`: moves `split_lamports` and the matching share of the stake from
+/// `source` into the uninitialized `destination`, whose rent exempt reserve
+/// follows from its size, together with the share of the source extensions
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(not(feature = "extensions"), allow(unused_variables))]
+pub fn split<T: StakeHistoryGetEntry + ?Sized>(
+    source: &mut StakeStateV2,
+    source_extensions: &mut [u8],
+    source_pubkey: &Pubkey,
+    source_lamports: u64,
+    destination: &mut StakeStateV2,
+    destination_extensions: &mut [u8],
+    destination_lamports: u64,
+    split_lamports: u64,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    clock: &Clock,
+    stake_history: &T,
+    rent: &Rent,
+) -> Result<(), ProgramError> {
+    if !matches!(destination, StakeStateV2::Uninitialized) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // TODO: this check is done in check_split_amount and probably could be removed
+    if split_lamports > source_lamports {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    let destination_rent_exempt_reserve =
+        rent.minimum_balance(StakeStateV2::size_of() + destination_extensions.len());
+
+    match source {
+        StakeStateV2::Stake(source_meta, source_stake, stake_flags) => {
+            check_staker_signer(source_meta, &is_signer)?;
+
+            let minimum_delegation = crate::get_minimum_delegation();
+
+            let effective_stake = source_stake.delegation.get_effective_stake(
+                clock.epoch,
+                stake_history,
+                PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH,
+            );
+
+            let is_active = effective_stake > 0;
+
+            let validated_split_info = check_split_amount(
+                source_lamports,
+                destination_lamports,
+                split_lamports,
+                source_meta.rent_exempt_reserve.into(),
+                destination_rent_exempt_reserve,
+                minimum_delegation,
+                is_active,
+            )?;
+
+            let (remaining_stake_delta, split_stake_amount) = get_split_stake_amounts(
+                source_stake.delegation.stake.into(),
+                source_meta.rent_exempt_reserve.into(),
+                split_lamports,
+                destination_lamports,
+                &validated_split_info,
+                minimum_delegation,
+            )?;
+
+            let destination_stake =
+                source_stake.split(remaining_stake_delta, split_stake_amount)?;
+
+            let mut destination_meta = source_meta.clone();
+            destination_meta.rent_exempt_reserve =
+                validated_split_info.destination_rent_exempt_reserve.into();
+
+            *destination =
+                StakeStateV2::Stake(destination_meta, destination_stake, stake_flags.clone());
+        }
+        StakeStateV2::Initialized(source_meta) => {
+            check_staker_signer(source_meta, &is_signer)?;
+
+            let validated_split_info = check_split_amount(
+                source_lamports,
+                destination_lamports,
+                split_lamports,
+                source_meta.rent_exempt_reserve.into(),
+                destination_rent_exempt_reserve,
+                0,     // additional_required_lamports
+                false, // is_active
+            )?;
+
+            let mut destination_meta = source_meta.clone();
+            destination_meta.rent_exempt_reserve =
+                validated_split_info.destination_rent_exempt_reserve.into();
+
+            *destination = StakeStateV2::Initialized(destination_meta);
+        }
+        StakeStateV2::Uninitialized => {
+            if !is_signer(source_pubkey) {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+        }
+        _ => return Err(ProgramError::InvalidAccountData),
+    }
+
+    // Deinitialize state upon zero balance
+    if split_lamports == source_lamports {
+        *source = StakeStateV2::Uninitialized;
+    }
+
+    #[cfg(feature = "extensions")]
+    split_extensions(
+        source_extensions,
+        destination_extensions,
+        split_lamports,
+        source_lamports,
+        clock,
+    )?;
+    // the split counts as activity of both accounts, which have the same
+    // authorities
+    #[cfg(feature = "recovery-authority")]
+    {
+        record_signed_activity(source, source_extensions, &is_signer, clock.epoch)?;
+        record_signed_activity(destination, destination_extensions, is_signer, clock.epoch)?;
+    }
+
+    Ok(())
+}
+
+pub(crate) struct ValidatedSplitInfo {
+    pub source_remaining_balance: u64,
+    pub destination_rent_exempt_reserve: u64,
+}
+
+/// Checks a split of `split_lamports` against the balances and reserves of both
+/// accounts
+pub(crate) fn check_split_amount(
+    source_lamports: u64,
+    destination_lamports: u64,
+    split_lamports: u64,
+    source_rent_exempt_reserve: u64,
+    destination_rent_exempt_reserve: u64,
+    additional_required_lamports: u64,
+    source_is_active: bool,
+) -> Result<ValidatedSplitInfo, ProgramError> {
+    // Split amount has to be something
+    if split_lamports == 0 {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    // Obviously cannot split more than what the source account has
+    if split_lamports > source_lamports {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    // Verify that the source account still has enough lamports left after
+    // splitting: EITHER at least the minimum balance, OR zero (in this case the
+    // source account is transferring all lamports to new destination account,
+    // and the source account will be closed)
+    let source_minimum_balance =
+        source_rent_exempt_reserve.saturating_add(additional_required_lamports);
+    let source_remaining_balance = source_lamports.saturating_sub(split_lamports);
+    if source_remaining_balance == 0 {
+        // full amount is a withdrawal
+        // nothing to do here
+    } else if source_remaining_balance < source_minimum_balance {
+        // the remaining balance is too low to do the split
+        return Err(ProgramError::InsufficientFunds);
+    } else {
+        // all clear!
+        // nothing to do here
+    }
+
+    // If the source is active stake, one of these criteria must be met:
+    // 1. the destination account must be prefunded with at least the rent-exempt
+    //    reserve, or
+    // 2. the split must consume 100% of the source
+    if source_is_active
+        && source_remaining_balance != 0
+        && destination_lamports < destination_rent_exempt_reserve
+    {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    // Verify the destination account meets the minimum balance requirements
+    // This must handle:
+    // 1. The destination account having a different rent exempt reserve due to data
+    //    size changes
+    // 2. The destination account being prefunded, which would lower the minimum
+    //    split amount
+    let destination_minimum_balance =
+        destination_rent_exempt_reserve.saturating_add(additional_required_lamports);
+    let destination_balance_deficit =
+        destination_minimum_balance.saturating_sub(destination_lamports);
+    if split_lamports < destination_balance_deficit {
+        return Err(ProgramError::InsufficientFunds);
+    }
+
+    Ok(ValidatedSplitInfo {
+        source_remaining_balance,
+        destination_rent_exempt_reserve,
+    })
+}
+
+/// Returns the stake delta of the source and the stake of the destination of a
+/// split of active or activating stake
+pub(crate) fn get_split_stake_amounts(
+    source_stake: u64,
+    source_rent_exempt_reserve: u64,
+    split_lamports: u64,
+    destination_lamports: u64,
+    validated_split_info: &ValidatedSplitInfo,
+    minimum_delegation: u64,
+) -> Result<(u64, u64), ProgramError> {
+    // split the stake, subtract rent_exempt_balance unless
+    // the destination account already has those lamports
+    // in place.
+    // this means that the new stake account will have a stake equivalent to
+    // lamports minus rent_exempt_reserve if it starts out with a zero balance
+    let (remaining_stake_delta, split_stake_amount) =
+        if validated_split_info.source_remaining_balance == 0 {
+            // If split amount equals the full source stake (as implied by 0
+            // source_remaining_balance), the new split stake must equal the same
+            // amount, regardless of any current lamport balance in the split account.
+            // Since split accounts retain the state of their source account, this
+            // prevents any magic activation of stake by prefunding the split account.
+            //
+            // The new split stake also needs to ignore any positive delta between the
+            // original rent_exempt_reserve and the split_rent_exempt_reserve, in order
+            // to prevent magic activation of stake by splitting between accounts of
+            // different sizes.
+            let remaining_stake_delta = split_lamports.saturating_sub(source_rent_exempt_reserve);
+            (remaining_stake_delta, remaining_stake_delta)
+        } else {
+            // Otherwise, the new split stake should reflect the entire split
+            // requested, less any lamports needed to cover the
+            // split_rent_exempt_reserve.
+            if source_stake.saturating_sub(split_lamports) < minimum_delegation {
+                // StakeError::InsufficientDelegation
+                return Err(ProgramError::Custom(12));
+            }
+
+            (
+                split_lamports,
+                split_lamports.saturating_sub(
+                    validated_split_info
+                        .destination_rent_exempt_reserve
+                        .saturating_sub(destination_lamports),
+                ),
+            )
+        };
+
+    if split_stake_amount < minimum_delegation {
+        // StakeError::InsufficientDelegation
+        return Err(ProgramError::Custom(12));
+    }
+
+    Ok((remaining_stake_delta, split_stake_amount))
+}
+
+/// `Withdraw`: withdraws `withdraw_lamports` to `destination`, signed by
+/// `withdraw_authority`. Unvested lamports must stay in the account unless the
+/// lockup custodian signs, and the withdraw allowlist must allow `destination`.
+/// Returns the lamports to move to the destination, which differ from the
+/// requested amount for a withdraw-max request.
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(not(feature = "withdraw-allowlist"), allow(unused_variables))]
+pub fn withdraw<T: StakeHistoryGetEntry + ?Sized>(
+    stake_state: &mut StakeStateV2,
+    extensions: &mut [u8],
+    stake_pubkey: &Pubkey,
+    lamports: u64,
+    withdraw_lamports: u64,
+    destination: &Pubkey,
+    withdraw_authority: &Pubkey,
+    custodian: Option<&Pubkey>,
+    clock: &Clock,
+    stake_history: &T,
+) -> Result<u64, ProgramError> {
+    #[cfg(feature = "withdraw-allowlist")]
+    check_withdraw_destination(extensions, destination, clock.epoch)?;
+
+    #[cfg(feature = "vesting")]
+    let unvested_lamports = get_unvested_lamports(extensions, clock)?;
+    #[cfg(not(feature = "vesting"))]
+    let unvested_lamports = 0;
+
+    let (reserve, is_staked) = match &*stake_state {
+        StakeStateV2::Stake(meta, stake, _stake_flag) => {
+            if let Some(custodian) = custodian {
+                if meta.authorized.withdrawer != *custodian
+                    && meta.authorized.withdrawer != *withdraw_authority
+                {
+                    return Err(ProgramError::MissingRequiredSignature);
+                }
+            } else if meta.authorized.withdrawer != *withdraw_authority {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+
+            let (staked_and_reserve, is_staked) =
+                get_staked_and_reserve(meta, stake, clock, stake_history)?;
+
+            // verify that lockup has expired or that the withdrawal is signed by the
+            // custodian both epoch and unix_timestamp must have passed
+            if meta.lockup.is_in_force(clock, custodian) {
+                // StakeError::LockupInForce
+                return Err(ProgramError::Custom(1));
+            }
+
+            (staked_and_reserve, is_staked)
+        }
+        StakeStateV2::Initialized(meta) => {
+            if let Some(custodian) = custodian {
+                if meta.authorized.withdrawer != *custodian
+                    && meta.authorized.withdrawer != *withdraw_authority
+                {
+                    return Err(ProgramError::MissingRequiredSignature);
+                }
+            } else if meta.authorized.withdrawer != *withdraw_authority {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            // verify that lockup has expired or that the withdrawal is signed by the
+            // custodian both epoch and unix_timestamp must have passed
+            if meta.lockup.is_in_force(clock, custodian) {
+                // StakeError::LockupInForce
+                return Err(ProgramError::Custom(1));
+            }
+
+            // stake accounts must have a balance >= rent_exempt_reserve
+            (meta.rent_exempt_reserve.into(), false)
+        }
+        StakeStateV2::Uninitialized => {
+            if let Some(custodian) = custodian {
+                if *stake_pubkey != *custodian && *stake_pubkey != *withdraw_authority {
+                    return Err(ProgramError::MissingRequiredSignature);
+                }
+            } else if *stake_pubkey != *withdraw_authority {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+
+            (0, false)
+        }
+        _ => return Err(ProgramError::InvalidAccountData),
+    };
+
+    let (reserve, is_staked) = match &*stake_state {
+        StakeStateV2::Initialized(meta) | StakeStateV2::Stake(meta, _, _)
+            if unvested_lamports != 0 && custodian != Some(&meta.lockup.custodian) =>
+        {
+            // unvested lamports may be staked, but must not leave the account, which
+            // therefore cannot be closed either
+            let vesting_reserve =
+                u64::from(meta.rent_exempt_reserve).saturating_add(unvested_lamports);
+            (reserve.max(vesting_reserve), true)
+        }
+        _ => (reserve, is_staked),
+    };
+
+    #[cfg(feature = "withdraw-max")]
+    let withdraw_lamports = if withdraw_lamports == WITHDRAW_MAX_LAMPORTS {
+        // withdraw everything if the account can be closed, otherwise everything
+        // above the reserve and the staked amount
+        if is_staked {
+            lamports.saturating_sub(reserve)
+        } else {
+            lamports
+        }
+    } else {
+        withdraw_lamports
+    };
+
+    if withdraw_lamports == lamports {
+        // if the stake is active, we mustn't allow the account to go away
+        if is_staked {
+            return Err(ProgramError::InsufficientFunds);
+        }
+
+        // Deinitialize state upon zero balance
+        *stake_state = StakeStateV2::Uninitialized;
+
+        // a closed account leaves no extensions behind, without extensions any
+        // bytes after the stake state are left alone like upstream
+        #[cfg(feature = "extensions")]
+        extensions.fill(0);
+    } else {
+        // a partial withdrawal must not deplete the reserve
+        let withdraw_lamports_and_reserve = withdraw_lamports
+            .checked_add(reserve)
+            .ok_or(ProgramError::InsufficientFunds)?;
+        if withdraw_lamports_and_reserve > lamports {
+            return Err(ProgramError::InsufficientFunds);
+        }
+
+        // a partial withdrawal is activity of the withdrawer
+        #[cfg(feature = "recovery-authority")]
+        record_signed_activity(
+            stake_state,
+            extensions,
+            |pubkey| pubkey == withdraw_authority || custodian == Some(pubkey),
+            clock.epoch,
+        )?;
+    }
+
+    Ok(withdraw_lamports)
+}
+
+/// Returns the lamports a delegated stake account must retain, i.e. the staked
+/// amount plus the rent exempt reserve, and whether any of them are staked
+#[inline(always)]
+pub(crate) fn get_staked_and_reserve<T: StakeHistoryGetEntry + ?Sized>(
+    meta: &Meta,
+    stake: &Stake,
+    clock: &Clock,
+    stake_history: &T,
+) -> Result<(u64, bool), ProgramError> {
+    // if we have a deactivation epoch and we're in cooldown
+    let staked = if clock.epoch >= u64::from(stake.delegation.deactivation_epoch) {
+        stake.delegation.get_effective_stake(
+            clock.epoch,
+            stake_history,
+            PERPETUAL_NEW_WARMUP_COOLDOWN_RATE_EPOCH,
+        )
+    } else {
+        // Assume full stake if the stake account hasn't been
+        //  de-activated, because in the future the exposed stake
+        //  might be higher than stake.stake() due to warmup
+        stake.delegation.stake.into()
+    };
+    let staked_and_reserve = staked
+        .checked_add(meta.rent_exempt_reserve.into())
+        .ok_or(ProgramError::InsufficientFunds)?;
+
+    Ok((staked_and_reserve, staked != 0))
+}
+
+/// `Deactivate`: starts the cooldown of a delegated account, signed by the
+/// staker or the deactivator
+#[cfg_attr(
+    not(any(feature = "role-authorities", feature = "recovery-authority")),
+    allow(unused_variables)
+)]
+pub fn deactivate(
+    stake_state: &mut StakeStateV2,
+    extensions: &mut [u8],
+    is_signer: impl Fn(&Pubkey) -> bool,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    // the deactivator may act as the staker, whose signature is the only one
+    // deactivating requires
+    #[cfg(feature = "role-authorities")]
+    let has_deactivator_signer = has_role_signer(extensions, &is_signer, StakeRole::Deactivator)?;
+    #[cfg(feature = "role-authorities")]
+    let is_staker_signer = |pubkey: &Pubkey| has_deactivator_signer || is_signer(pubkey);
+    #[cfg(not(feature = "role-authorities"))]
+    let is_staker_signer = &is_signer;
+
+    match stake_state {
+        StakeStateV2::Stake(meta, stake, _stake_flags) => {
+            check_staker_signer(meta, &is_staker_signer)?;
+
+            stake.deactivate(clock.epoch.into())?;
+        }
+        _ => return Err(ProgramError::InvalidAccountData),
+    }
+
+    #[cfg(feature = "recovery-authority")]
+    record_signed_activity(stake_state, extensions, is_signer, clock.epoch)?;
+
+    Ok(())
+}
+
+/// `SetLockup` and its variants: updates the lockup. While lamports are
+/// unvested the lockup counts as in force, so only the custodian, who alone may
+/// release them, can change it.
+#[cfg_attr(
+    not(any(feature = "vesting", feature = "recovery-authority")),
+    allow(unused_variables)
+)]
+pub fn set_lockup(
+    stake_state: &mut StakeStateV2,
+    extensions: &mut [u8],
+    lockup: &LockupArgs,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    // otherwise the withdrawer could make itself custodian once the lockup
+    // expires and release the unvested lamports
+    #[cfg(feature = "vesting")]
+    let has_unvested_lamports = get_unvested_lamports(extensions, clock)? != 0;
+    #[cfg(not(feature = "vesting"))]
+    let has_unvested_lamports = false;
+
+    match stake_state {
+        StakeStateV2::Initialized(meta) | StakeStateV2::Stake(meta, _, _) => {
+            let signer_args = SetLockupSignerArgs {
+                has_custodian_signer: is_signer(&meta.lockup.custodian),
+                has_withdrawer_signer: is_signer(&meta.authorized.withdrawer),
+            };
+            let is_in_force = has_unvested_lamports || meta.lockup.is_in_force(clock, None);
+            meta.set_lockup(lockup, signer_args, is_in_force)?;
+        }
+        _ => return Err(ProgramError::InvalidAccountData),
+    }
+
+    #[cfg(feature = "recovery-authority")]
+    record_signed_activity(stake_state, extensions, is_signer, clock.epoch)?;
+
+    Ok(())
+}
+
+/// `Merge`: merges `source` into `destination`, draining the source, whose
+/// lamports are to be moved to the destination
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(not(feature = "extensions"), allow(unused_variables))]
+pub fn merge<T: StakeHistoryGetEntry + ?Sized>(
+    destination: &mut StakeStateV2,
+    destination_extensions: &mut [u8],
+    destination_lamports: u64,
+    source: &mut StakeStateV2,
+    source_extensions: &mut [u8],
+    source_lamports: u64,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    clock: &Clock,
+    stake_history: &T,
+) -> Result<(), ProgramError> {
+    #[cfg(feature = "logging")]
+    pinocchio::msg!("Checking if destination stake is mergeable");
+    let destination_merge_kind =
+        MergeKind::get_if_mergeable(destination, destination_lamports, clock, stake_history)?;
+
+    // Authorized staker is allowed to split/merge accounts
+    check_staker_signer(destination_merge_kind.meta(), &is_signer)?;
+
+    #[cfg(feature = "logging")]
+    pinocchio::msg!("Checking if source stake is mergeable");
+    let source_merge_kind =
+        MergeKind::get_if_mergeable(source, source_lamports, clock, stake_history)?;
+
+    #[cfg(feature = "logging")]
+    pinocchio::msg!("Merging stake accounts");
+    if let Some(merged_state) = destination_merge_kind.merge(source_merge_kind, clock)? {
+        *destination = merged_state;
+    }
+
+    // Source is about to be drained, deinitialize its state
+    *source = StakeStateV2::Uninitialized;
+
+    #[cfg(feature = "extensions")]
+    merge_extensions(destination_extensions, source_extensions, clock)?;
+    #[cfg(feature = "recovery-authority")]
+    record_signed_activity(destination, destination_extensions, is_signer, clock.epoch)?;
+
+    Ok(())
+}
+
+/// `MergeMany`: merges the sources one after the other into a destination, as
+/// `Merge` would, without recomputing the destination from the stake history
+/// for every source. The destination state is only written by
+/// [`MergeMany::finish`].
+#[cfg(feature = "merge-many")]
+pub struct MergeMany {
+    destination: MergeKind,
+}
+
+#[cfg(feature = "merge-many")]
+impl MergeMany {
+    /// Checks that `destination` can be merged into and that its staker signed
+    pub fn new<T: StakeHistoryGetEntry + ?Sized>(
+        destination: &StakeStateV2,
+        destination_lamports: u64,
+        is_signer: impl Fn(&Pubkey) -> bool,
+        clock: &Clock,
+        stake_history: &T,
+    ) -> Result<Self, ProgramError> {
+        #[cfg(feature = "logging")]
+        pinocchio::msg!("Checking if destination stake is mergeable");
+        let destination =
+            MergeKind::get_if_mergeable(destination, destination_lamports, clock, stake_history)?;
+
+        // Authorized staker is allowed to split/merge accounts
+        check_staker_signer(destination.meta(), &is_signer)?;
+
+        Ok(Self { destination })
+    }
+
+    /// Merges `source` and drains it, its lamports are to be moved to the
+    /// destination
+    #[cfg_attr(not(feature = "extensions"), allow(unused_variables))]
+    pub fn merge_source<T: StakeHistoryGetEntry + ?Sized>(
+        self,
+        destination_extensions: &mut [u8],
+        source: &mut StakeStateV2,
+        source_extensions: &mut [u8],
+        source_lamports: u64,
+        clock: &Clock,
+        stake_history: &T,
+    ) -> Result<Self, ProgramError> {
+        #[cfg(feature = "logging")]
+        pinocchio::msg!("Checking if source stake is mergeable");
+        let source_merge_kind =
+            MergeKind::get_if_mergeable(source, source_lamports, clock, stake_history)?;
+
+        #[cfg(feature = "logging")]
+        pinocchio::msg!("Merging stake accounts");
+        let destination = self.destination.merge_kind(source_merge_kind, clock)?;
+
+        // Source is about to be drained, deinitialize its state
+        *source = StakeStateV2::Uninitialized;
+
+        #[cfg(feature = "extensions")]
+        merge_extensions(destination_extensions, source_extensions, clock)?;
+
+        Ok(Self { destination })
+    }
+
+    /// Writes the destination state once every source is merged
+    #[cfg_attr(not(feature = "recovery-authority"), allow(unused_variables))]
+    pub fn finish(
+        self,
+        destination: &mut StakeStateV2,
+        destination_extensions: &mut [u8],
+        is_signer: impl Fn(&Pubkey) -> bool,
+        clock: &Clock,
+    ) -> Result<(), ProgramError> {
+        if let Some(merged_state) = self.destination.into_merged_state() {
+            *destination = merged_state;
+        }
+
+        #[cfg(feature = "recovery-authority")]
+        record_signed_activity(destination, destination_extensions, is_signer, clock.epoch)?;
+
+        Ok(())
+    }
+}
+
+/// `DeactivateDelinquent`: deactivates stake delegated to a vote account that
+/// last voted in `delinquent_last_epoch`, given whether the reference vote
+/// account voted in every recent epoch
+pub fn deactivate_delinquent(
+    stake_state: &mut StakeStateV2,
+    delinquent_vote_pubkey: &Pubkey,
+    delinquent_last_epoch: Option<Epoch>,
+    has_acceptable_reference_votes: bool,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    if !has_acceptable_reference_votes {
+        // return Err(StakeError::InsufficientReferenceVotes.into());
+        return Err(ProgramError::Custom(9));
+    }
+
+    let StakeStateV2::Stake(_meta, stake, _stake_flags) = stake_state else {
+        return Err(ProgramError::InvalidAccountData);
+    };
+
+    if stake.delegation.voter_pubkey != *delinquent_vote_pubkey {
+        // return Err(StakeError::VoteAddressMismatch.into());
+        return Err(ProgramError::Custom(10));
+    }
+
+    // Deactivate the stake account if its delegated vote account has never voted or
+    // has not voted in the last
+    // `MINIMUM_DELINQUENT_EPOCHS_FOR_DEACTIVATION`
+    if eligible_for_deactivate_delinquent(&delinquent_last_epoch, clock.epoch) {
+        stake.deactivate(clock.epoch.into())
+    } else {
+        // Err(StakeError::MinimumDelinquentEpochsForDeactivationNotMet.into())
+        Err(ProgramError::Custom(11))
+    }
+}
+
+fn eligible_for_deactivate_delinquent(last_epoch: &Option<Epoch>, current_epoch: Epoch) -> bool {
+    match last_epoch {
+        None => true,
+        Some(epoch) => {
+            if let Some(minimum_epoch) =
+                current_epoch.checked_sub(MINIMUM_DELINQUENT_EPOCHS_FOR_DEACTIVATION)
+            {
+                *epoch <= minimum_epoch
+            } else {
+                false
+            }
+        }
+    }
+}
+
+fn move_stake_or_lamports_shared_checks<T: StakeHistoryGetEntry + ?Sized>(
+    source: &StakeStateV2,
+    source_lamports: u64,
+    destination: &StakeStateV2,
+    destination_lamports: u64,
+    authority: &Pubkey,
+    clock: &Clock,
+    stake_history: &T,
+) -> Result<(MergeKind, MergeKind), ProgramError> {
+    // get_if_mergeable ensures accounts are not partly activated or in any form of deactivating
+    // we still need to exclude activating state ourselves
+    let source_merge_kind =
+        MergeKind::get_if_mergeable(source, source_lamports, clock, stake_history)?;
+
+    // Authorized staker is allowed to move stake
+    if source_merge_kind.meta().authorized.staker != *authority {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    // same transient assurance as with source
+    let destination_merge_kind =
+        MergeKind::get_if_mergeable(destination, destination_lamports, clock, stake_history)?;
+
+    // ensure all authorities match and lockups match if lockup is in force
+    MergeKind::metas_can_merge(
+        source_merge_kind.meta(),
+        destination_merge_kind.meta(),
+        clock,
+    )?;
+
+    Ok((source_merge_kind, destination_merge_kind))
+}
+
+/// Returns the authority a move is checked against: the signer, or the staker
+/// it acts for if the signer is the rebalancer, which may move between
+/// accounts with the same authorities
+#[cfg_attr(not(feature = "role-authorities"), allow(unused_variables))]
+fn move_authority(
+    source: &StakeStateV2,
+    source_extensions: &[u8],
+    authority: &Pubkey,
+) -> Result<Pubkey, ProgramError> {
+    #[cfg(feature = "role-authorities")]
+    if let StakeStateV2::Initialized(meta) | StakeStateV2::Stake(meta, _, _) = source {
+        if meta.authorized.staker != *authority
+            && is_role_authority(source_extensions, authority, StakeRole::Rebalancer)?
+        {
+            return Ok(meta.authorized.staker);
+        }
+    }
+    Ok(*authority)
+}
+
+/// Returns the lamports that must stay in the source of a move, staked or not
+#[cfg_attr(not(feature = "vesting"), allow(unused_variables))]
+#[inline(always)]
+fn move_source_unvested_lamports(
+    source_extensions: &[u8],
+    clock: &Clock,
+) -> Result<u64, ProgramError> {
+    #[cfg(feature = "vesting")]
+    return get_unvested_lamports(source_extensions, clock);
+    #[cfg(not(feature = "vesting"))]
+    Ok(0)
+}
+
+/// `MoveStake`: moves `lamports` of active stake between two accounts with the
+/// same authorities and extensions, signed by `authority`. The lamports are to
+/// be moved along with the stake.
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(not(feature = "extensions"), allow(unused_variables))]
+pub fn move_stake<T: StakeHistoryGetEntry + ?Sized>(
+    source: &mut StakeStateV2,
+    source_extensions: &mut [u8],
+    source_lamports: u64,
+    destination: &mut StakeStateV2,
+    destination_extensions: &mut [u8],
+    destination_lamports: u64,
+    lamports: u64,
+    authority: &Pubkey,
+    clock: &Clock,
+    stake_history: &T,
+) -> Result<(), ProgramError> {
+    // must move something
+    if lamports == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // extensions stay with their accounts
+    #[cfg(feature = "extensions")]
+    check_move_extensions(source_extensions, destination_extensions)?;
+
+    let source_unvested_lamports = move_source_unvested_lamports(source_extensions, clock)?;
+
+    let (source_merge_kind, destination_merge_kind) = move_stake_or_lamports_shared_checks(
+        source,
+        source_lamports,
+        destination,
+        destination_lamports,
+        &move_authority(source, source_extensions, authority)?,
+        clock,
+        stake_history,
+    )?;
+
+    // source must be fully active
+    let MergeKind::FullyActive(source_meta, mut source_stake) = source_merge_kind else {
+        return Err(ProgramError::InvalidAccountData);
+    };
+
+    let minimum_delegation = crate::get_minimum_delegation();
+    let source_effective_stake: u64 = source_stake.delegation.stake.into();
+
+    // source cannot move more stake than it has, regardless of how many lamports it
+    // has
+    let source_final_stake = source_effective_stake
+        .checked_sub(lamports)
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    // unless all stake is being moved, source must retain at least the minimum
+    // delegation
+    if source_final_stake != 0 && source_final_stake < minimum_delegation {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // destination must be fully active or fully inactive
+    let (destination_state, destination_rent_exempt_reserve) = match destination_merge_kind {
+        MergeKind::FullyActive(destination_meta, mut destination_stake) => {
+            // if active, destination must be delegated to the same vote account as source
+            if source_stake.delegation.voter_pubkey != destination_stake.delegation.voter_pubkey {
+                // return Err(StakeError::VoteAddressMismatch.into());
+                return Err(ProgramError::Custom(10));
+            }
+
+            let destination_effective_stake: u64 = destination_stake.delegation.stake.into();
+            let destination_final_stake = destination_effective_stake
+                .checked_add(lamports)
+                .ok_or(ProgramError::ArithmeticOverflow)?;
+
+            // ensure destination meets miniumum delegation
+            // since it is already active, this only really applies if the minimum is raised
+            if destination_final_stake < minimum_delegation {
+                return Err(ProgramError::InvalidArgument);
+            }
+
+            merge_delegation_stake_and_credits_observed(
+                &mut destination_stake,
+                lamports,
+                source_stake.credits_observed.into(),
+            )?;
+
+            let rent_exempt_reserve = destination_meta.rent_exempt_reserve;
+
+            // StakeFlags::empty() is valid here because the only existing stake flag,
+            // MUST_FULLY_ACTIVATE_BEFORE_DEACTIVATION_IS_PERMITTED, does not apply to
+            // active stakes
+            (
+                StakeStateV2::Stake(destination_meta, destination_stake, StakeFlags::empty()),
+                rent_exempt_reserve,
+            )
+        }
+        MergeKind::Inactive(destination_meta, _, _) => {
+            // if destination is inactive, it must be given at least the minimum delegation
+            if lamports < minimum_delegation {
+                return Err(ProgramError::InvalidArgument);
+            }
+
+            let mut destination_stake = source_stake.clone();
+            destination_stake.delegation.stake = lamports.into();
+
+            let rent_exempt_reserve = destination_meta.rent_exempt_reserve;
+
+            // StakeFlags::empty() is valid here because the only existing stake flag,
+            // MUST_FULLY_ACTIVATE_BEFORE_DEACTIVATION_IS_PERMITTED, is cleared when a stake
+            // is activated
+            (
+                StakeStateV2::Stake(destination_meta, destination_stake, StakeFlags::empty()),
+                rent_exempt_reserve,
+            )
+        }
+        _ => return Err(ProgramError::InvalidAccountData),
+    };
+
+    let source_rent_exempt_reserve = source_meta.rent_exempt_reserve;
+
+    let source_state = if source_final_stake == 0 {
+        StakeStateV2::Initialized(source_meta)
+    } else {
+        source_stake.delegation.stake = source_final_stake.into();
+
+        // StakeFlags::empty() is valid here because the only existing stake flag,
+        // MUST_FULLY_ACTIVATE_BEFORE_DEACTIVATION_IS_PERMITTED, does not apply to
+        // active stakes
+        StakeStateV2::Stake(source_meta, source_stake, StakeFlags::empty())
+    };
+
+    let source_final_lamports = source_lamports
+        .checked_sub(lamports)
+        .ok_or(ProgramError::InsufficientFunds)?;
+    let destination_final_lamports = destination_lamports
+        .checked_add(lamports)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+
+    // unvested lamports must stay in the source, staked or not
+    if source_final_lamports
+        < u64::from(source_rent_exempt_reserve).saturating_add(source_unvested_lamports)
+    {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // this should be impossible, but because we do all our math with delegations,
+    // best to guard it
+    if source_final_lamports < u64::from(source_rent_exempt_reserve)
+        || destination_final_lamports < u64::from(destination_rent_exempt_reserve)
+    {
+        #[cfg(feature = "logging")]
+        pinocchio::msg!("Delegation calculations violated lamport balance assumptions");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    *source = source_state;
+    *destination = destination_state;
+
+    // moves count as activity of both accounts, which have the same authorities
+    #[cfg(feature = "recovery-authority")]
+    {
+        record_signed_activity(
+            source,
+            source_extensions,
+            |pubkey| pubkey == authority,
+            clock.epoch,
+        )?;
+        record_signed_activity(
+            destination,
+            destination_extensions,
+            |pubkey| pubkey == authority,
+            clock.epoch,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// `MoveLamports`: checks a move of `lamports` not backing any stake between
+/// two accounts with the same authorities and extensions, signed by
+/// `authority`. The states are unchanged, the lamports are to be moved.
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(not(feature = "extensions"), allow(unused_variables))]
+pub fn move_lamports<T: StakeHistoryGetEntry + ?Sized>(
+    source: &StakeStateV2,
+    source_extensions: &mut [u8],
+    source_lamports: u64,
+    destination: &StakeStateV2,
+    destination_extensions: &mut [u8],
+    destination_lamports: u64,
+    lamports: u64,
+    authority: &Pubkey,
+    clock: &Clock,
+    stake_history: &T,
+) -> Result<(), ProgramError> {
+    // must move something
+    if lamports == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // extensions stay with their accounts
+    #[cfg(feature = "extensions")]
+    check_move_extensions(source_extensions, destination_extensions)?;
+
+    let source_unvested_lamports = move_source_unvested_lamports(source_extensions, clock)?;
+
+    let (source_merge_kind, _) = move_stake_or_lamports_shared_checks(
+        source,
+        source_lamports,
+        destination,
+        destination_lamports,
+        &move_authority(source, source_extensions, authority)?,
+        clock,
+        stake_history,
+    )?;
+
+    let source_free_lamports = match &source_merge_kind {
+        MergeKind::FullyActive(source_meta, source_stake) => source_lamports
+            .saturating_sub(source_stake.delegation.stake.into())
+            .saturating_sub(source_meta.rent_exempt_reserve.into()),
+        MergeKind::Inactive(source_meta, source_lamports, _) => {
+            source_lamports.saturating_sub(source_meta.rent_exempt_reserve.into())
+        }
+        _ => return Err(ProgramError::InvalidAccountData),
+    };
+
+    // unvested lamports must stay in the source
+    let source_free_lamports = source_free_lamports.min(
+        source_lamports
+            .saturating_sub(source_merge_kind.meta().rent_exempt_reserve.into())
+            .saturating_sub(source_unvested_lamports),
+    );
+
+    if lamports > source_free_lamports {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // moves count as activity of both accounts, which have the same authorities
+    #[cfg(feature = "recovery-authority")]
+    {
+        record_signed_activity(
+            source,
+            source_extensions,
+            |pubkey| pubkey == authority,
+            clock.epoch,
+        )?;
+        record_signed_activity(
+            destination,
+            destination_extensions,
+            |pubkey| pubkey == authority,
+            clock.epoch,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Returns the meta of an initialized or delegated account whose withdrawer
+/// signed
+#[cfg(any(
+    feature = "vesting",
+    feature = "metadata",
+    feature = "withdraw-allowlist",
+    feature = "delayed-withdrawer-transfer",
+    feature = "recovery-authority"
+))]
+#[inline(always)]
+fn check_withdrawer_signer<'a>(
+    stake_state: &'a StakeStateV2,
+    is_signer: &impl Fn(&Pubkey) -> bool,
+) -> Result<&'a Meta, ProgramError> {
+    match stake_state {
+        StakeStateV2::Initialized(meta) | StakeStateV2::Stake(meta, _, _) => {
+            if !is_signer(&meta.authorized.withdrawer) {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            Ok(meta)
+        }
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+/// `SetVestingSchedule`: sets the vesting schedule, signed by the withdrawer.
+/// A schedule with unvested lamports can only be replaced with the lockup
+/// custodian.
+#[cfg(feature = "vesting")]
+pub fn set_vesting_schedule(
+    stake_state: &StakeStateV2,
+    extensions: &mut [u8],
+    vesting_schedule: &VestingSchedule,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    custodian: Option<&Pubkey>,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    if !vesting_schedule.is_valid() {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let meta = check_withdrawer_signer(stake_state, &is_signer)?;
+
+    match get_extension_mut::<VestingSchedule>(extensions)? {
+        Some(current_vesting_schedule) => {
+            // lamports already promised by the current schedule can only be released
+            // by the custodian
+            if current_vesting_schedule.unvested_amount(clock) != 0
+                && custodian != Some(&meta.lockup.custodian)
+            {
+                #[cfg(feature = "logging")]
+                pinocchio::msg!("Vesting schedule can only be replaced by the custodian");
+                // StakeError::CustodianMissing
+                return Err(ProgramError::Custom(7));
+            }
+            *current_vesting_schedule = vesting_schedule.clone();
+        }
+        None => {
+            *init_extension::<VestingSchedule>(extensions)? = vesting_schedule.clone();
+        }
+    }
+
+    #[cfg(feature = "recovery-authority")]
+    record_activity(extensions, clock.epoch)?;
+
+    Ok(())
+}
+
+/// `SetMetadata`: sets the metadata, or removes it for an empty name without
+/// entries, signed by the withdrawer
+#[cfg(feature = "metadata")]
+#[cfg_attr(not(feature = "recovery-authority"), allow(unused_variables))]
+pub fn set_metadata(
+    stake_state: &StakeStateV2,
+    extensions: &mut [u8],
+    metadata: Metadata,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    check_withdrawer_signer(stake_state, &is_signer)?;
+
+    if metadata == Metadata::default() {
+        remove_extension::<Metadata>(extensions)?;
+    } else {
+        match get_extension_mut::<Metadata>(extensions)? {
+            Some(current_metadata) => *current_metadata = metadata,
+            None => *init_extension::<Metadata>(extensions)? = metadata,
+        }
+    }
+
+    #[cfg(feature = "recovery-authority")]
+    record_activity(extensions, clock.epoch)?;
+
+    Ok(())
+}
+
+/// `SetDeactivationSchedule`: schedules the deactivation at `epoch`, or removes
+/// the schedule for [`NO_DEACTIVATION_SCHEDULE`], signed by the staker
+#[cfg(feature = "deactivation-schedule")]
+#[cfg_attr(not(feature = "recovery-authority"), allow(unused_variables))]
+pub fn set_deactivation_schedule(
+    stake_state: &StakeStateV2,
+    extensions: &mut [u8],
+    epoch: u64,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    match stake_state {
+        StakeStateV2::Initialized(meta) | StakeStateV2::Stake(meta, _, _) => {
+            check_staker_signer(meta, &is_signer)?;
+        }
+        _ => return Err(ProgramError::InvalidAccountData),
+    }
+
+    if epoch == NO_DEACTIVATION_SCHEDULE {
+        remove_extension::<DeactivationSchedule>(extensions)?;
+    } else {
+        match get_extension_mut::<DeactivationSchedule>(extensions)? {
+            Some(deactivation_schedule) => deactivation_schedule.epoch = epoch.into(),
+            None => init_extension::<DeactivationSchedule>(extensions)?.epoch = epoch.into(),
+        }
+    }
+
+    #[cfg(feature = "recovery-authority")]
+    record_activity(extensions, clock.epoch)?;
+
+    Ok(())
+}
+
+/// `CrankScheduledDeactivation`: deactivates an account whose deactivation is
+/// due, signed by anyone, and removes the schedule
+#[cfg(feature = "deactivation-schedule")]
+pub fn crank_scheduled_deactivation(
+    stake_state: &mut StakeStateV2,
+    extensions: &mut [u8],
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    match get_extension::<DeactivationSchedule>(extensions)? {
+        Some(deactivation_schedule) if deactivation_schedule.is_due(clock.epoch) => {}
+        _ => {
+            #[cfg(feature = "logging")]
+            pinocchio::msg!("Stake account is not scheduled for deactivation");
+            return Err(ProgramError::InvalidArgument);
+        }
+    }
+
+    match stake_state {
+        // stake deactivated by its staker in the meantime only loses its schedule
+        StakeStateV2::Stake(_meta, stake, _stake_flags)
+            if u64::from(stake.delegation.deactivation_epoch) == u64::MAX =>
+        {
+            stake.deactivate(clock.epoch.into())?;
+        }
+        StakeStateV2::Initialized(_) | StakeStateV2::Stake(_, _, _) => {}
+        _ => return Err(ProgramError::InvalidAccountData),
+    }
+
+    // the schedule is used up, a later delegation must not be deactivated by it
+    remove_extension::<DeactivationSchedule>(extensions)?;
+
+    Ok(())
+}
+
+/// `SetWithdrawAllowlist`: restricts withdrawals to `destinations`, signed by
+/// the withdrawer. Restricting an unrestricted account applies right away,
+/// changing an allowlist applies after its timelock.
+#[cfg(feature = "withdraw-allowlist")]
+pub fn set_withdraw_allowlist(
+    stake_state: &StakeStateV2,
+    extensions: &mut [u8],
+    timelock_epochs: u64,
+    destinations: &[Pubkey],
+    is_signer: impl Fn(&Pubkey) -> bool,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    check_withdrawer_signer(stake_state, &is_signer)?;
+
+    match get_extension_mut::<WithdrawAllowlist>(extensions)? {
+        Some(allowlist) => allowlist.stage(timelock_epochs, destinations, clock.epoch)?,
+        // restricting an unrestricted account is safe to apply right away
+        None => {
+            *init_extension::<WithdrawAllowlist>(extensions)? =
+                WithdrawAllowlist::new(timelock_epochs, destinations)?;
+        }
+    }
+
+    #[cfg(feature = "recovery-authority")]
+    record_activity(extensions, clock.epoch)?;
+
+    Ok(())
+}
+
+/// `SetRoleAuthority`: grants `role` to `new_authority`, signed by the staker
+/// or the withdrawer
+#[cfg(feature = "role-authorities")]
+#[cfg_attr(not(feature = "recovery-authority"), allow(unused_variables))]
+pub fn set_role_authority(
+    stake_state: &StakeStateV2,
+    extensions: &mut [u8],
+    new_authority: &Pubkey,
+    role: StakeRole,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    // roles are part of the powers of the staker, so the staker or the withdrawer
+    // may grant them, like they may change the staker
+    match stake_state {
+        StakeStateV2::Initialized(meta) | StakeStateV2::Stake(meta, _, _) => {
+            if !is_signer(&meta.authorized.staker) && !is_signer(&meta.authorized.withdrawer) {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+        }
+        _ => return Err(ProgramError::InvalidAccountData),
+    }
+
+    match get_extension_mut::<RoleAuthorities>(extensions)? {
+        Some(role_authorities) => role_authorities.set(role, new_authority),
+        None => init_extension::<RoleAuthorities>(extensions)?.set(role, new_authority),
+    }
+
+    #[cfg(feature = "recovery-authority")]
+    record_activity(extensions, clock.epoch)?;
+
+    Ok(())
+}
+
+/// `EnableDelayedWithdrawerTransfer`: requires withdrawer changes to be proposed
+/// `delay_epochs` ahead, signed by the withdrawer. The delay cannot be
+/// shortened.
+#[cfg(feature = "delayed-withdrawer-transfer")]
+#[cfg_attr(not(feature = "recovery-authority"), allow(unused_variables))]
+pub fn enable_delayed_withdrawer_transfer(
+    stake_state: &StakeStateV2,
+    extensions: &mut [u8],
+    delay_epochs: u64,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    check_withdrawer_signer(stake_state, &is_signer)?;
+
+    match get_extension_mut::<DelayedWithdrawerTransfer>(extensions)? {
+        Some(transfer) => {
+            // shortening the delay would let a compromised withdrawer skip it
+            if delay_epochs < u64::from(transfer.delay_epochs) {
+                return Err(ProgramError::InvalidArgument);
+            }
+            transfer.delay_epochs = delay_epochs.into();
+        }
+        None => {
+            *init_extension::<DelayedWithdrawerTransfer>(extensions)? =
+                DelayedWithdrawerTransfer::new(delay_epochs);
+        }
+    }
+
+    #[cfg(feature = "recovery-authority")]
+    record_activity(extensions, clock.epoch)?;
+
+    Ok(())
+}
+
+/// `ProposeWithdrawer`: proposes `new_withdrawer`, who may accept once the
+/// delay has passed, signed by the withdrawer
+#[cfg(feature = "delayed-withdrawer-transfer")]
+pub fn propose_withdrawer(
+    stake_state: &StakeStateV2,
+    extensions: &mut [u8],
+    new_withdrawer: &Pubkey,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    check_withdrawer_signer(stake_state, &is_signer)?;
+
+    get_extension_mut::<DelayedWithdrawerTransfer>(extensions)?
+        .ok_or(ProgramError::InvalidAccountData)?
+        .propose(new_withdrawer, clock.epoch);
+
+    #[cfg(feature = "recovery-authority")]
+    record_activity(extensions, clock.epoch)?;
+
+    Ok(())
+}
+
+/// `CancelWithdrawerProposal`: withdraws the pending proposal, signed by the
+/// withdrawer
+#[cfg(feature = "delayed-withdrawer-transfer")]
+#[cfg_attr(not(feature = "recovery-authority"), allow(unused_variables))]
+pub fn cancel_withdrawer_proposal(
+    stake_state: &StakeStateV2,
+    extensions: &mut [u8],
+    is_signer: impl Fn(&Pubkey) -> bool,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    check_withdrawer_signer(stake_state, &is_signer)?;
+
+    get_extension_mut::<DelayedWithdrawerTransfer>(extensions)?
+        .ok_or(ProgramError::InvalidAccountData)?
+        .cancel();
+
+    #[cfg(feature = "recovery-authority")]
+    record_activity(extensions, clock.epoch)?;
+
+    Ok(())
+}
+
+/// `AcceptWithdrawer`: makes the proposed withdrawer the withdrawer once the
+/// delay has passed, signed by the proposed withdrawer
+#[cfg(feature = "delayed-withdrawer-transfer")]
+pub fn accept_withdrawer(
+    stake_state: &mut StakeStateV2,
+    extensions: &mut [u8],
+    new_withdrawer: &Pubkey,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    custodian: Option<&Pubkey>,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    if !is_signer(new_withdrawer) {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let transfer = get_extension_mut::<DelayedWithdrawerTransfer>(extensions)?
+        .ok_or(ProgramError::InvalidAccountData)?;
+    match transfer.pending() {
+        Some((pending_withdrawer, effective_epoch)) if pending_withdrawer == new_withdrawer => {
+            if clock.epoch < effective_epoch {
+                #[cfg(feature = "logging")]
+                pinocchio::msg!("Withdrawer transfer delay has not passed");
+                return Err(ProgramError::InvalidArgument);
+            }
+        }
+        _ => return Err(ProgramError::InvalidArgument),
+    }
+    transfer.cancel();
+
+    // the current withdrawer signed the proposal
+    let signer_args = AuthorizeSignerArgs {
+        has_staker_signer: false,
+        has_withdrawer_signer: true,
+    };
+
+    match stake_state {
+        StakeStateV2::Initialized(meta) | StakeStateV2::Stake(meta, _, _) => {
+            meta.authorized.authorize(
+                signer_args,
+                new_withdrawer,
+                StakeAuthorize::Withdrawer,
+                (&meta.lockup, clock, custodian),
+            )?;
+        }
+        _ => return Err(ProgramError::InvalidAccountData),
+    }
+
+    // the new withdrawer gets a full inactivity period
+    #[cfg(feature = "recovery-authority")]
+    record_activity(extensions, clock.epoch)?;
+
+    Ok(())
+}
+
+/// `SetRecoveryAuthority`: lets `authority` replace the withdrawer after
+/// `inactivity_epochs` without activity, or removes the recovery authority for
+/// the default pubkey, signed by the withdrawer
+#[cfg(feature = "recovery-authority")]
+pub fn set_recovery_authority(
+    stake_state: &StakeStateV2,
+    extensions: &mut [u8],
+    authority: &Pubkey,
+    inactivity_epochs: u64,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    check_withdrawer_signer(stake_state, &is_signer)?;
+
+    if *authority == Pubkey::default() {
+        remove_extension::<RecoveryAuthority>(extensions)?;
+        return Ok(());
+    }
+
+    let recovery_authority = match get_extension_mut::<RecoveryAuthority>(extensions)? {
+        Some(recovery_authority) => recovery_authority,
+        None => init_extension::<RecoveryAuthority>(extensions)?,
+    };
+    recovery_authority.authority = *authority;
+    recovery_authority.inactivity_epochs = inactivity_epochs.into();
+    recovery_authority.last_activity_epoch = clock.epoch.into();
+
+    Ok(())
+}
+
+/// `RecoverWithdrawer`: replaces the withdrawer of an inactive account, signed
+/// by the recovery authority. For accounts that delay withdrawer transfers, the
+/// new withdrawer is only proposed, so the current withdrawer can still cancel
+/// within the delay and the new one accepts with `AcceptWithdrawer`.
+#[cfg(feature = "recovery-authority")]
+pub fn recover_withdrawer(
+    stake_state: &mut StakeStateV2,
+    extensions: &mut [u8],
+    new_withdrawer: &Pubkey,
+    is_signer: impl Fn(&Pubkey) -> bool,
+    custodian: Option<&Pubkey>,
+    clock: &Clock,
+) -> Result<(), ProgramError> {
+    let recovery_authority =
+        get_recovery_authority(extensions)?.ok_or(ProgramError::InvalidAccountData)?;
+    if !is_signer(&recovery_authority.authority) {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !recovery_authority.can_recover(clock.epoch) {
+        #[cfg(feature = "logging")]
+        pinocchio::msg!("Stake account has not been inactive long enough");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    #[cfg(feature = "delayed-withdrawer-transfer")]
+    if let Some(transfer) = get_extension_mut::<DelayedWithdrawerTransfer>(extensions)? {
+        transfer.propose(new_withdrawer, clock.epoch);
+        return Ok(());
+    }
+
+    // the recovery authority stands in for the lost withdrawer
+    let signer_args = AuthorizeSignerArgs {
+        has_staker_signer: false,
+        has_withdrawer_signer: true,
+    };
+
+    match stake_state {
+        StakeStateV2::Initialized(meta) | StakeStateV2::Stake(meta, _, _) => {
+            meta.authorized.authorize(
+                signer_args,
+                new_withdrawer,
+                StakeAuthorize::Withdrawer,
+                (&meta.lockup, clock, custodian),
+            )?;
+        }
+        _ => return Err(ProgramError::InvalidAccountData),
+    }
+
+    // the new withdrawer gets a full inactivity period
+    record_activity(extensions, clock.epoch)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{state::StakeHistoryEntry, test_utils::*};
+
+    use super::*;
+
+    #[test]
+    fn test_delegate_deactivate_withdraw() {
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let lamports = RESERVE + 1_000;
+
+        let mut stake_state = initialized();
+        assert_eq!(
+            delegate(
+                &mut stake_state,
+                &mut [],
+                lamports,
+                &VOTER,
+                0,
+                signed_by(WITHDRAWER),
+                &clock(1),
+                history
+            ),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        let mut stake_state = delegated(lamports, 1);
+        let StakeStateV2::Stake(_, stake, _) = &stake_state else {
+            panic!("not delegated");
+        };
+        assert_eq!(u64::from(stake.delegation.stake), 1_000);
+
+        // delegated stake cannot leave the account
+        assert_eq!(
+            withdraw(
+                &mut stake_state.clone(),
+                &mut [],
+                &[9; 32],
+                lamports,
+                1,
+                &[8; 32],
+                &WITHDRAWER,
+                None,
+                &clock(1),
+                history
+            ),
+            Err(ProgramError::InsufficientFunds)
+        );
+
+        // once deactivated, without history, it can in the next epoch
+        deactivate(&mut stake_state, &mut [], signed_by(STAKER), &clock(1)).unwrap();
+        assert_eq!(
+            withdraw(
+                &mut stake_state,
+                &mut [],
+                &[9; 32],
+                lamports,
+                lamports,
+                &[8; 32],
+                &WITHDRAWER,
+                None,
+                &clock(2),
+                history
+            ),
+            Ok(lamports)
+        );
+        assert_eq!(stake_state, StakeStateV2::Uninitialized);
+    }
+
+    #[cfg(not(feature = "extensions"))]
+    #[test]
+    fn test_withdraw_closing_keeps_trailing_bytes() {
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let lamports = RESERVE + 1_000;
+        let mut trailing_bytes = [7; 16];
+
+        // the bytes after the stake state of a larger account are not ours to clear
+        let mut stake_state = initialized();
+        assert_eq!(
+            withdraw(
+                &mut stake_state,
+                &mut trailing_bytes,
+                &[9; 32],
+                lamports,
+                lamports,
+                &[8; 32],
+                &WITHDRAWER,
+                None,
+                &clock(1),
+                history
+            ),
+            Ok(lamports)
+        );
+        assert_eq!(stake_state, StakeStateV2::Uninitialized);
+        assert_eq!(trailing_bytes, [7; 16]);
+    }
+
+    #[test]
+    fn test_move_lamports_and_stake() {
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let active = delegated(RESERVE + 1_000, 0);
+
+        // only lamports above the stake and the reserve are free
+        assert_eq!(
+            move_lamports(
+                &active,
+                &mut [],
+                RESERVE + 1_500,
+                &initialized(),
+                &mut [],
+                RESERVE,
+                500,
+                &STAKER,
+                &clock(1),
+                history
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            move_lamports(
+                &active,
+                &mut [],
+                RESERVE + 1_500,
+                &initialized(),
+                &mut [],
+                RESERVE,
+                501,
+                &STAKER,
+                &clock(1),
+                history
+            ),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        // moving all stake leaves the source initialized
+        let mut source = active.clone();
+        let mut destination = initialized();
+        move_stake(
+            &mut source,
+            &mut [],
+            RESERVE + 1_000,
+            &mut destination,
+            &mut [],
+            RESERVE,
+            1_000,
+            &STAKER,
+            &clock(1),
+            history,
+        )
+        .unwrap();
+        assert_eq!(source, initialized());
+        let StakeStateV2::Stake(_, stake, _) = &destination else {
+            panic!("not delegated");
+        };
+        assert_eq!(u64::from(stake.delegation.stake), 1_000);
+
+        assert_eq!(
+            move_stake(
+                &mut active.clone(),
+                &mut [],
+                RESERVE + 1_000,
+                &mut initialized(),
+                &mut [],
+                RESERVE,
+                1_000,
+                &WITHDRAWER,
+                &clock(1),
+                history
+            ),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+    }
+
+    #[test]
+    fn test_split_and_merge() {
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let rent = rent();
+
+        let mut source = initialized();
+        let mut destination = StakeStateV2::Uninitialized;
+        split(
+            &mut source,
+            &mut [],
+            &[9; 32],
+            2 * RESERVE + 2_000,
+            &mut destination,
+            &mut [],
+            0,
+            RESERVE + 1_000,
+            signed_by(STAKER),
+            &clock(1),
+            history,
+            &rent,
+        )
+        .unwrap();
+        assert_eq!(source, initialized());
+        assert_eq!(destination, initialized());
+
+        // merging drains the source into the destination
+        merge(
+            &mut destination,
+            &mut [],
+            RESERVE + 1_000,
+            &mut source,
+            &mut [],
+            RESERVE + 1_000,
+            signed_by(STAKER),
+            &clock(1),
+            history,
+        )
+        .unwrap();
+        assert_eq!(destination, initialized());
+        assert_eq!(source, StakeStateV2::Uninitialized);
+    }
+
+    #[cfg(feature = "metadata")]
+    #[test]
+    fn test_withdraw_closing_clears_extensions() {
+        let history: &[(u64, StakeHistoryEntry)] = &[];
+        let lamports = RESERVE + 1_000;
+        let mut extensions = with_extension(Metadata::new(b"name", &[]).unwrap());
+        let with_metadata = extensions.clone();
+
+        // a partial withdrawal keeps the extensions, closing removes them
+        withdraw(
+            &mut initialized(),
+            &mut extensions,
+            &[9; 32],
+            lamports,
+            1_000,
+            &[8; 32],
+            &WITHDRAWER,
+            None,
+            &clock(1),
+            history,
+        )
+        .unwrap();
+        assert_eq!(extensions, with_metadata);
+        withdraw(
+            &mut initialized(),
+            &mut extensions,
+            &[9; 32],
+            lamports,
+            lamports,
+            &[8; 32],
+            &WITHDRAWER,
+            None,
+            &clock(1),
+            history,
+        )
+        .unwrap();
+        assert_eq!(extensions, vec![0; with_metadata.len()]);
+    }
+}
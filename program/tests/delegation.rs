@@ -0,0 +1,193 @@
+#![allow(clippy::arithmetic_side_effects)]
+
+// Host-side property tests for the activation math: `Delegation::get_effective_stake`
+// is an optimized re-derivation of `Delegation::stake_activating_and_deactivating`,
+// which is itself a port of the upstream implementation. For random delegations
+// and random stake histories, all three must agree, with the old warmup rate, the
+// new one and a switch between them.
+
+use {
+    p_stake::state::{Delegation, StakeHistoryEntry},
+    proptest::{collection::btree_map, prelude::*},
+    solana_stake_interface::{
+        stake_history::{StakeHistory, StakeHistoryEntry as UpstreamStakeHistoryEntry},
+        state::Delegation as UpstreamDelegation,
+    },
+    std::collections::BTreeMap,
+};
+
+// keeps histories short enough to contain most relevant epochs, with gaps
+const MAX_EPOCH: u64 = 64;
+
+// large enough for realistic cluster stake, small enough not to overflow sums
+const MAX_LAMPORTS: u64 = 1 << 60;
+
+fn epoch() -> impl Strategy<Value = u64> {
+    0..MAX_EPOCH
+}
+
+// activation and deactivation epochs, including the bootstrap and "not
+// deactivated" sentinels
+fn delegation_epoch() -> impl Strategy<Value = u64> {
+    prop_oneof![8 => epoch(), 1 => Just(u64::MAX)]
+}
+
+fn history_entry() -> impl Strategy<Value = (u64, u64, u64)> {
+    (0..=MAX_LAMPORTS, 0..=MAX_LAMPORTS, 0..=MAX_LAMPORTS)
+}
+
+// effective, activating, deactivating per epoch, some epochs missing
+fn history() -> impl Strategy<Value = BTreeMap<u64, (u64, u64, u64)>> {
+    btree_map(epoch(), history_entry(), 0..MAX_EPOCH as usize)
+}
+
+fn check_against_upstream(
+    stake: u64,
+    activation_epoch: u64,
+    deactivation_epoch: u64,
+    history: &BTreeMap<u64, (u64, u64, u64)>,
+    target_epoch: u64,
+    new_rate_activation_epoch: Option<u64>,
+) {
+    let mut delegation = Delegation::new(&[1; 32], stake.into(), activation_epoch.into());
+    delegation.deactivation_epoch = deactivation_epoch.into();
+
+    let upstream_delegation = UpstreamDelegation {
+        voter_pubkey: [1; 32].into(),
+        stake,
+        activation_epoch,
+        deactivation_epoch,
+        ..UpstreamDelegation::default()
+    };
+
+    // slice history is ordered newest first, like the sysvar
+    let slice_history = history
+        .iter()
+        .rev()
+        .map(|(epoch, (effective, activating, deactivating))| {
+            (
+                *epoch,
+                StakeHistoryEntry {
+                    effective: *effective,
+                    activating: *activating,
+                    deactivating: *deactivating,
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut upstream_history = StakeHistory::default();
+    for (epoch, (effective, activating, deactivating)) in history {
+        upstream_history.add(
+            *epoch,
+            UpstreamStakeHistoryEntry {
+                effective: *effective,
+                activating: *activating,
+                deactivating: *deactivating,
+            },
+        );
+    }
+
+    let status = delegation.stake_activating_and_deactivating(
+        target_epoch,
+        slice_history.as_slice(),
+        new_rate_activation_epoch,
+    );
+    let upstream_status = upstream_delegation.stake_activating_and_deactivating(
+        target_epoch,
+        &upstream_history,
+        new_rate_activation_epoch,
+    );
+    assert_eq!(status.effective, upstream_status.effective);
+    assert_eq!(status.activating, upstream_status.activating);
+    assert_eq!(status.deactivating, upstream_status.deactivating);
+
+    let effective_stake = delegation.get_effective_stake(
+        target_epoch,
+        slice_history.as_slice(),
+        new_rate_activation_epoch,
+    );
+    assert_eq!(effective_stake, status.effective);
+}
+
+proptest! {
+    #[test]
+    fn delegation_matches_upstream_with_old_warmup_rate(
+        stake in 0..=MAX_LAMPORTS,
+        activation_epoch in delegation_epoch(),
+        deactivation_epoch in delegation_epoch(),
+        history in history(),
+        target_epoch in epoch(),
+    ) {
+        check_against_upstream(
+            stake,
+            activation_epoch,
+            deactivation_epoch,
+            &history,
+            target_epoch,
+            None,
+        );
+    }
+
+    #[test]
+    fn delegation_matches_upstream_with_new_warmup_rate(
+        stake in 0..=MAX_LAMPORTS,
+        activation_epoch in delegation_epoch(),
+        deactivation_epoch in delegation_epoch(),
+        history in history(),
+        target_epoch in epoch(),
+    ) {
+        check_against_upstream(
+            stake,
+            activation_epoch,
+            deactivation_epoch,
+            &history,
+            target_epoch,
+            Some(0),
+        );
+    }
+
+    #[test]
+    fn delegation_matches_upstream_across_warmup_rate_change(
+        stake in 0..=MAX_LAMPORTS,
+        activation_epoch in delegation_epoch(),
+        deactivation_epoch in delegation_epoch(),
+        history in history(),
+        target_epoch in epoch(),
+        new_rate_activation_epoch in epoch(),
+    ) {
+        check_against_upstream(
+            stake,
+            activation_epoch,
+            deactivation_epoch,
+            &history,
+            target_epoch,
+            Some(new_rate_activation_epoch),
+        );
+    }
+
+    // histories without gaps where the delegation was part of the cluster
+    // stake, which is what the bank actually produces
+    #[test]
+    fn delegation_matches_upstream_with_contiguous_history(
+        stake in 1..=MAX_LAMPORTS >> 8,
+        activation_epoch in epoch(),
+        deactivation_epoch in delegation_epoch(),
+        cluster_effective in 0..=MAX_LAMPORTS >> 8,
+        target_epoch in epoch(),
+        new_rate_activation_epoch in proptest::option::of(epoch()),
+    ) {
+        let history = (0..MAX_EPOCH)
+            .map(|epoch| (epoch, (cluster_effective + stake, stake, stake)))
+            .collect::<BTreeMap<_, _>>();
+
+        check_against_upstream(
+            stake,
+            activation_epoch,
+            deactivation_epoch,
+            &history,
+            target_epoch,
+            new_rate_activation_epoch,
+        );
+    }
+}
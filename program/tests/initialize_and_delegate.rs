@@ -0,0 +1,176 @@
+#![cfg(feature = "initialize-and-delegate")]
+#![allow(clippy::arithmetic_side_effects)]
+
+// `InitializeAndDelegate` against the built program, which must be built with
+// the feature: `cargo build-sbf --features initialize-and-delegate`.
+
+use {
+    mollusk_svm::{result::Check, Mollusk},
+    p_stake::id as p_stake_id,
+    solana_account::Account,
+    solana_sdk::{
+        instruction::{AccountMeta, Instruction},
+        program_error::ProgramError,
+        pubkey::Pubkey,
+        stake::{
+            stake_flags::StakeFlags,
+            state::{Authorized, Delegation, Lockup, Meta, Stake, StakeStateV2},
+        },
+        sysvar::{clock, rent::Rent, stake_history},
+    },
+    solana_vote_program::vote_state,
+};
+
+const STAKE_LAMPORTS: u64 = 5_000_000_000;
+
+fn id() -> Pubkey {
+    p_stake_id().into()
+}
+
+fn rent_exempt_reserve() -> u64 {
+    Rent::default().minimum_balance(StakeStateV2::size_of())
+}
+
+struct Accounts {
+    stake: Pubkey,
+    vote: Pubkey,
+    staker: Pubkey,
+    authorized: Authorized,
+    lockup: Lockup,
+}
+
+impl Accounts {
+    fn new() -> Self {
+        let staker = Pubkey::new_unique();
+        Self {
+            stake: Pubkey::new_unique(),
+            vote: Pubkey::new_unique(),
+            staker,
+            authorized: Authorized {
+                staker,
+                withdrawer: Pubkey::new_unique(),
+            },
+            lockup: Lockup {
+                unix_timestamp: 1,
+                epoch: 2,
+                custodian: Pubkey::new_unique(),
+            },
+        }
+    }
+
+    fn instruction(&self, is_staker_signer: bool) -> Instruction {
+        // discriminant, then the `Initialize` data
+        let mut data = 19u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&bincode::serialize(&(self.authorized, self.lockup)).unwrap());
+
+        Instruction {
+            program_id: id(),
+            accounts: vec![
+                AccountMeta::new(self.stake, false),
+                AccountMeta::new_readonly(self.vote, false),
+                AccountMeta::new_readonly(clock::id(), false),
+                AccountMeta::new_readonly(stake_history::id(), false),
+                AccountMeta::new_readonly(self.staker, is_staker_signer),
+            ],
+            data,
+        }
+    }
+
+    fn process(
+        &self,
+        mollusk: &Mollusk,
+        stake_state: &StakeStateV2,
+        is_staker_signer: bool,
+        check: Check,
+    ) -> Option<StakeStateV2> {
+        let vote_account = vote_state::create_account(&self.vote, &Pubkey::new_unique(), 0, 1);
+        let stake_account = Account::new_data_with_space(
+            rent_exempt_reserve() + STAKE_LAMPORTS,
+            stake_state,
+            StakeStateV2::size_of(),
+            &id(),
+        )
+        .unwrap();
+
+        let result = mollusk.process_and_validate_instruction(
+            &self.instruction(is_staker_signer),
+            &[
+                (self.stake, stake_account),
+                (self.vote, vote_account.into()),
+                mollusk.sysvars.keyed_account_for_clock_sysvar(),
+                mollusk.sysvars.keyed_account_for_stake_history_sysvar(),
+                (self.staker, Account::default()),
+            ],
+            &[check],
+        );
+
+        result
+            .get_account(&self.stake)
+            .map(|account| bincode::deserialize(&account.data).unwrap())
+    }
+}
+
+#[test]
+fn test_initialize_and_delegate() {
+    let mut mollusk = Mollusk::new(&id(), "target/deploy/p_stake");
+    mollusk.warp_to_slot(1_000_000);
+    let accounts = Accounts::new();
+
+    let stake_state = accounts.process(
+        &mollusk,
+        &StakeStateV2::Uninitialized,
+        true,
+        Check::success(),
+    );
+
+    // the whole balance above the reserve is delegated from the current epoch
+    assert_eq!(
+        stake_state,
+        Some(StakeStateV2::Stake(
+            Meta {
+                rent_exempt_reserve: rent_exempt_reserve(),
+                authorized: accounts.authorized,
+                lockup: accounts.lockup,
+            },
+            Stake {
+                delegation: Delegation::new(
+                    &accounts.vote,
+                    STAKE_LAMPORTS,
+                    mollusk.sysvars.clock.epoch,
+                ),
+                credits_observed: 0,
+            },
+            StakeFlags::empty(),
+        ))
+    );
+}
+
+#[test]
+fn test_initialize_and_delegate_requires_staker_signature() {
+    let mollusk = Mollusk::new(&id(), "target/deploy/p_stake");
+    let accounts = Accounts::new();
+
+    accounts.process(
+        &mollusk,
+        &StakeStateV2::Uninitialized,
+        false,
+        Check::err(ProgramError::MissingRequiredSignature),
+    );
+}
+
+#[test]
+fn test_initialize_and_delegate_initialized_account_fails() {
+    let mollusk = Mollusk::new(&id(), "target/deploy/p_stake");
+    let accounts = Accounts::new();
+
+    accounts.process(
+        &mollusk,
+        &StakeStateV2::Initialized(Meta {
+            rent_exempt_reserve: rent_exempt_reserve(),
+            authorized: accounts.authorized,
+            lockup: accounts.lockup,
+        }),
+        true,
+        Check::err(ProgramError::InvalidAccountData),
+    );
+}
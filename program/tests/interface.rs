@@ -4,9 +4,11 @@ use {
     arbitrary::{Arbitrary, Unstructured},
     mollusk_svm::{result::Check, Mollusk},
     p_stake::{get_minimum_delegation, id as p_stake_id},
+    serde::{Deserialize, Serialize},
     solana_account::{Account, ReadableAccount, WritableAccount},
     solana_sdk::{
-        instruction::{AccountMeta, Instruction},
+        feature_set::stake_raise_minimum_delegation_to_1_sol,
+        instruction::{AccountMeta, Instruction, InstructionError},
         native_token::LAMPORTS_PER_SOL,
         pubkey::Pubkey,
         stake::{
@@ -27,9 +29,12 @@ use {
             state::{VoteState, VoteStateVersions},
         },
     },
-    solana_sdk_ids::system_program,
+    solana_sdk_ids::{system_program, sysvar},
     std::{
         collections::{HashMap, HashSet},
+        fs,
+        path::Path,
+        str::FromStr,
         sync::LazyLock,
     },
 };
@@ -90,6 +95,9 @@ const STAKER_GRAY: Pubkey = Pubkey::from_str_const("STAKERGRAY111111111111111111
 const WITHDRAWER_GRAY: Pubkey =
     Pubkey::from_str_const("W1THDRAWERGRAY11111111111111111111111111111");
 
+// fixed so that generated instructions are reproducible
+const SEED_BASE: Pubkey = Pubkey::from_str_const("SEEDBASE11111111111111111111111111111111111");
+
 // valid custodians for any stake account
 const CUSTODIAN_LEFT: Pubkey =
     Pubkey::from_str_const("CUSTXD1ANLEFT111111111111111111111111111111");
@@ -142,9 +150,22 @@ struct Env {
 impl Env {
     // set up a test environment with valid stake history, two vote accounts, and two blank stake accounts
     fn init() -> Self {
+        Self::init_with(Mollusk::new(&id(), "target/deploy/p_stake"))
+    }
+
+    // same environment running the upstream stake program instead of p-stake,
+    // the native builtin that mollusk loads by default
+    fn init_upstream() -> Self {
+        let mut mollusk = Mollusk::default();
+        mollusk
+            .feature_set
+            .deactivate(&stake_raise_minimum_delegation_to_1_sol::id());
+        Self::init_with(mollusk)
+    }
+
+    fn init_with(mut mollusk: Mollusk) -> Self {
         // create a test environment at the execution epoch
         let mut base_accounts = HashMap::new();
-        let mut mollusk = Mollusk::new(&id(), "target/deploy/p_stake");
         mollusk.warp_to_slot(EXECUTION_EPOCH * mollusk.sysvars.epoch_schedule.slots_per_epoch + 1);
         assert_eq!(mollusk.sysvars.clock.epoch, EXECUTION_EPOCH);
 
@@ -403,10 +424,9 @@ impl StakeInterface {
                 authority_type,
                 lockup_state,
             } => {
-                let seed_base = Pubkey::new_unique();
                 let seed = "seed";
                 let seed_authority =
-                    Pubkey::create_with_seed(&seed_base, seed, &system_program::id()).unwrap();
+                    Pubkey::create_with_seed(&SEED_BASE, seed, &system_program::id()).unwrap();
 
                 let mut black_state = initialized_stake(
                     STAKE_ACCOUNT_BLACK,
@@ -440,7 +460,7 @@ impl StakeInterface {
 
                 make_instruction(
                     &STAKE_ACCOUNT_BLACK,
-                    &seed_base,
+                    &SEED_BASE,
                     seed.to_string(),
                     &system_program::id(),
                     &new_authority,
@@ -936,7 +956,6 @@ fn test_no_signer_bypass() {
     }
 }
 
-
 // the stake program cannot be used during the epoch rewards period
 // the only exception to this is GetMinimumDelegation
 #[test]
@@ -956,3 +975,234 @@ fn test_epoch_rewards_period() {
     let instruction = instruction::get_minimum_delegation();
     env.process_success(&instruction);
 }
+
+// golden test vectors: every generated case, and every case with one of its
+// signers removed, executed against the upstream stake program. the inputs and
+// resulting account states are checked in as json, so that p-stake can be
+// replayed against them without the upstream program, and so that changes in
+// upstream behavior show up as file diffs when they are regenerated:
+//
+//   cargo test --test interface -- --ignored generate_golden_fixtures
+//
+// bump the version whenever the fixture format changes
+const GOLDEN_FIXTURE_VERSION: u32 = 1;
+
+fn golden_fixtures_dir() -> String {
+    format!(
+        "{}/tests/fixtures/golden/v{}",
+        env!("CARGO_MANIFEST_DIR"),
+        GOLDEN_FIXTURE_VERSION
+    )
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct GoldenAccount {
+    pubkey: String,
+    lamports: u64,
+    owner: String,
+    executable: bool,
+    data: String,
+}
+
+impl GoldenAccount {
+    fn new(pubkey: &Pubkey, account: &Account) -> Self {
+        Self {
+            pubkey: pubkey.to_string(),
+            lamports: account.lamports,
+            owner: account.owner.to_string(),
+            executable: account.executable,
+            data: to_hex(&account.data),
+        }
+    }
+
+    fn to_account(&self) -> (Pubkey, Account) {
+        (
+            Pubkey::from_str(&self.pubkey).unwrap(),
+            Account {
+                lamports: self.lamports,
+                data: from_hex(&self.data),
+                owner: Pubkey::from_str(&self.owner).unwrap(),
+                executable: self.executable,
+                rent_epoch: u64::MAX,
+            },
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct GoldenAccountMeta {
+    pubkey: String,
+    is_signer: bool,
+    is_writable: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct GoldenFixture {
+    version: u32,
+    case: String,
+    epoch: u64,
+    program_id: String,
+    account_metas: Vec<GoldenAccountMeta>,
+    instruction_data: String,
+    accounts: Vec<GoldenAccount>,
+    result: Result<(), InstructionError>,
+    resulting_accounts: Vec<GoldenAccount>,
+}
+
+impl GoldenFixture {
+    fn to_instruction(&self) -> Instruction {
+        Instruction {
+            program_id: Pubkey::from_str(&self.program_id).unwrap(),
+            accounts: self
+                .account_metas
+                .iter()
+                .map(|meta| AccountMeta {
+                    pubkey: Pubkey::from_str(&meta.pubkey).unwrap(),
+                    is_signer: meta.is_signer,
+                    is_writable: meta.is_writable,
+                })
+                .collect(),
+            data: from_hex(&self.instruction_data),
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+// readable, stable file name for a case
+fn golden_fixture_name(case: &str) -> String {
+    let mut name = String::new();
+    for c in case.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c.to_ascii_lowercase());
+        } else if !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    format!("{}.json", name.trim_end_matches('_'))
+}
+
+// executes an instruction and records its inputs and outcome, sysvars are
+// provided by the environment and not recorded
+fn record_golden_fixture(env: &Env, case: String, instruction: &Instruction) -> GoldenFixture {
+    let accounts = env.resolve_accounts(&instruction.accounts);
+    let result = env.mollusk.process_instruction(instruction, &accounts);
+
+    let golden_accounts = |accounts: &[(Pubkey, Account)]| {
+        let mut golden_accounts: Vec<GoldenAccount> = vec![];
+        for (pubkey, account) in accounts {
+            let pubkey_string = pubkey.to_string();
+            if account.owner != sysvar::id()
+                && !golden_accounts.iter().any(|a| a.pubkey == pubkey_string)
+            {
+                golden_accounts.push(GoldenAccount::new(pubkey, account));
+            }
+        }
+        golden_accounts
+    };
+
+    GoldenFixture {
+        version: GOLDEN_FIXTURE_VERSION,
+        case,
+        epoch: env.mollusk.sysvars.clock.epoch,
+        program_id: instruction.program_id.to_string(),
+        account_metas: instruction
+            .accounts
+            .iter()
+            .map(|meta| GoldenAccountMeta {
+                pubkey: meta.pubkey.to_string(),
+                is_signer: meta.is_signer,
+                is_writable: meta.is_writable,
+            })
+            .collect(),
+        instruction_data: to_hex(&instruction.data),
+        accounts: golden_accounts(&accounts),
+        result: result.raw_result,
+        resulting_accounts: golden_accounts(&result.resulting_accounts),
+    }
+}
+
+#[test]
+#[ignore]
+fn generate_golden_fixtures() {
+    let mut env = Env::init_upstream();
+
+    // sorted so that regenerating is deterministic
+    let mut declarations = INSTRUCTION_DECLARATIONS.iter().collect::<Vec<_>>();
+    declarations.sort_by_key(|declaration| format!("{declaration:?}"));
+
+    let mut fixtures = vec![];
+    for declaration in declarations {
+        let instruction = declaration.to_instruction(&mut env);
+        fixtures.push(record_golden_fixture(
+            &env,
+            format!("{declaration:?}"),
+            &instruction,
+        ));
+
+        for i in 0..instruction.accounts.len() {
+            if !instruction.accounts[i].is_signer {
+                continue;
+            }
+
+            let mut instruction = instruction.clone();
+            instruction.accounts[i].is_signer = false;
+            fixtures.push(record_golden_fixture(
+                &env,
+                format!("{declaration:?} without signer {i}"),
+                &instruction,
+            ));
+        }
+
+        env.reset();
+    }
+
+    let dir = golden_fixtures_dir();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for fixture in fixtures {
+        let path = Path::new(&dir).join(golden_fixture_name(&fixture.case));
+        let json = serde_json::to_string_pretty(&fixture).unwrap();
+        fs::write(path, json + "\n").unwrap();
+    }
+}
+
+#[test]
+fn test_golden_fixtures() {
+    let dir = golden_fixtures_dir();
+    let mut paths = fs::read_dir(&dir)
+        .unwrap_or_else(|error| panic!("golden fixtures missing from {dir}: {error}"))
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty(), "no golden fixtures in {dir}");
+
+    let mut env = Env::init();
+    for path in paths {
+        let fixture: GoldenFixture =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(fixture.version, GOLDEN_FIXTURE_VERSION);
+        assert_eq!(fixture.epoch, env.mollusk.sysvars.clock.epoch);
+
+        env.override_accounts = fixture
+            .accounts
+            .iter()
+            .map(GoldenAccount::to_account)
+            .collect();
+
+        let instruction = fixture.to_instruction();
+        let replayed = record_golden_fixture(&env, fixture.case.clone(), &instruction);
+        assert_eq!(replayed, fixture, "{} diverges", path.display());
+
+        env.reset();
+    }
+}
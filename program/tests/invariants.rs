@@ -0,0 +1,936 @@
+#![allow(clippy::arithmetic_side_effects)]
+
+// Property-based state machine test: random sequences of every stake instruction
+// over a small pool of stake accounts, vote accounts and authorities, interleaved
+// with epoch advances. Stake history is synthesized at each epoch boundary the
+// way the bank does it, so activations and deactivations actually progress.
+//
+// Instructions are expected to fail most of the time (wrong authority, wrong
+// state, insufficient funds...). Failures are discarded, successes are applied
+// to the account store, and after every step we check global invariants:
+// * lamports are conserved
+// * every stake account decodes as a valid `StakeStateV2`
+// * effective stake never exceeds delegated stake, and stake is backed by the
+//   lamports above the rent exempt reserve
+// * a lockup in force is never bypassed without the custodian signing, and
+//   lamports under a lockup in force never move to an account whose lockup is
+//   weaker
+//
+// The same sequences run against the native stake program, which checks the
+// harness itself and runs without building the program.
+
+use {
+    mollusk_svm::Mollusk,
+    p_stake::id as p_stake_id,
+    proptest::{collection::vec, prelude::*},
+    solana_account::{Account, ReadableAccount, WritableAccount},
+    solana_sdk::{
+        feature_set::stake_raise_minimum_delegation_to_1_sol,
+        instruction::Instruction,
+        native_token::LAMPORTS_PER_SOL,
+        pubkey::Pubkey,
+        stake::{
+            instruction::{self, LockupArgs},
+            state::{Authorized, Lockup, StakeAuthorize, StakeStateV2, NEW_WARMUP_COOLDOWN_RATE},
+        },
+        stake_history::StakeHistoryEntry,
+        sysvar::{
+            clock::Clock, epoch_rewards::EpochRewards, epoch_schedule::EpochSchedule, rent::Rent,
+            stake_history::StakeHistory, SysvarId,
+        },
+        vote::{
+            program as vote_program,
+            state::{VoteState, VoteStateVersions},
+        },
+    },
+    solana_sdk_ids::{system_program, sysvar},
+    std::collections::HashMap,
+    test_case::test_case,
+};
+
+fn id() -> Pubkey {
+    p_stake_id().into()
+}
+
+fn mollusk_native() -> Mollusk {
+    let mut mollusk = Mollusk::default();
+    mollusk
+        .feature_set
+        .deactivate(&stake_raise_minimum_delegation_to_1_sol::id());
+    mollusk
+}
+
+fn mollusk_bpf() -> Mollusk {
+    let mut mollusk = Mollusk::new(&id(), "target/deploy/p_stake");
+    mollusk
+        .feature_set
+        .deactivate(&stake_raise_minimum_delegation_to_1_sol::id());
+    mollusk
+}
+
+// arbitrary, leaves room for a realistic stake history before the first step
+const START_EPOCH: u64 = 8;
+
+// stake delegated to some imaginary vote account in all epochs, so that new
+// delegations warm up and cool down over several epochs
+const PERSISTENT_ACTIVE_STAKE: u64 = 100 * LAMPORTS_PER_SOL;
+
+const MAX_INITIAL_LAMPORTS: u64 = 20 * LAMPORTS_PER_SOL;
+
+const SEED: &str = "seed";
+const SEED_BASE: Pubkey = Pubkey::from_str_const("SEEDBASE11111111111111111111111111111111111");
+
+const STAKE_ACCOUNTS: [Pubkey; 3] = [
+    Pubkey::from_str_const("STAKERED11111111111111111111111111111111111"),
+    Pubkey::from_str_const("STAKEBLUE1111111111111111111111111111111111"),
+    Pubkey::from_str_const("STAKEGREEN111111111111111111111111111111111"),
+];
+
+// the first vote account votes in every epoch and serves as the reference of
+// `DeactivateDelinquent`, the second stopped voting long before the first step
+const VOTE_ACCOUNTS: [Pubkey; 2] = [
+    Pubkey::from_str_const("VoTEBLACK1111111111111111111111111111111111"),
+    Pubkey::from_str_const("VoTEWHiTE1111111111111111111111111111111111"),
+];
+
+// last epoch the second vote account voted in, see `VOTE_ACCOUNTS`
+const DELINQUENT_LAST_EPOCH: u64 = 2;
+
+// withdrawals go here, so lamports leaving stake accounts stay in the store
+const SINK: Pubkey = Pubkey::from_str_const("S1NK111111111111111111111111111111111111111");
+
+// authorities and custodians are drawn from here, the last one can only sign
+// through `AuthorizeWithSeed`
+fn authorities() -> [Pubkey; 3] {
+    [
+        Pubkey::from_str_const("AL1CE11111111111111111111111111111111111111"),
+        Pubkey::from_str_const("BoB1111111111111111111111111111111111111111"),
+        Pubkey::create_with_seed(&SEED_BASE, SEED, &system_program::id()).unwrap(),
+    ]
+}
+
+// amounts are mostly relative to the balance of the account they apply to, so
+// that full and partial operations are both likely
+#[derive(Debug, Clone, Copy)]
+enum Amount {
+    Quarters(u64),
+    Raw(u64),
+}
+
+impl Amount {
+    fn resolve(self, balance: u64) -> u64 {
+        match self {
+            Self::Quarters(quarters) => (balance as u128 * quarters as u128 / 4) as u64,
+            Self::Raw(lamports) => lamports,
+        }
+    }
+}
+
+// one step of the state machine, indices refer to the pools above
+// NOTE Redelegate is skipped, it will never be enabled
+#[derive(Debug, Clone)]
+enum Op {
+    Initialize {
+        stake: usize,
+        staker: usize,
+        withdrawer: usize,
+        lockup: Option<(u64, usize)>,
+        checked: bool,
+    },
+    Authorize {
+        stake: usize,
+        authority: usize,
+        new_authority: usize,
+        withdrawer: bool,
+        custodian: Option<usize>,
+        checked: bool,
+    },
+    AuthorizeWithSeed {
+        stake: usize,
+        new_authority: usize,
+        withdrawer: bool,
+        custodian: Option<usize>,
+        checked: bool,
+    },
+    DelegateStake {
+        stake: usize,
+        authority: usize,
+        vote: usize,
+    },
+    Split {
+        stake: usize,
+        authority: usize,
+        destination: usize,
+        amount: Amount,
+    },
+    Withdraw {
+        stake: usize,
+        authority: usize,
+        amount: Amount,
+        custodian: Option<usize>,
+    },
+    Deactivate {
+        stake: usize,
+        authority: usize,
+    },
+    SetLockup {
+        stake: usize,
+        signer: usize,
+        epoch_offset: Option<u64>,
+        custodian: Option<usize>,
+        checked: bool,
+    },
+    Merge {
+        destination: usize,
+        source: usize,
+        authority: usize,
+    },
+    GetMinimumDelegation,
+    DeactivateDelinquent {
+        stake: usize,
+        vote: usize,
+        reference_vote: usize,
+    },
+    MoveStake {
+        source: usize,
+        destination: usize,
+        authority: usize,
+        amount: Amount,
+    },
+    MoveLamports {
+        source: usize,
+        destination: usize,
+        authority: usize,
+        amount: Amount,
+    },
+    AdvanceEpoch,
+}
+
+fn stake_index() -> impl Strategy<Value = usize> {
+    0..STAKE_ACCOUNTS.len()
+}
+
+fn vote_index() -> impl Strategy<Value = usize> {
+    0..VOTE_ACCOUNTS.len()
+}
+
+fn authority_index() -> impl Strategy<Value = usize> {
+    0..authorities().len()
+}
+
+fn amount() -> impl Strategy<Value = Amount> {
+    prop_oneof![
+        (0..=4u64).prop_map(Amount::Quarters),
+        (0..=MAX_INITIAL_LAMPORTS).prop_map(Amount::Raw),
+    ]
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (
+            stake_index(),
+            authority_index(),
+            authority_index(),
+            proptest::option::of((0..4u64, authority_index())),
+            any::<bool>(),
+        )
+            .prop_map(
+                |(stake, staker, withdrawer, lockup, checked)| Op::Initialize {
+                    stake,
+                    staker,
+                    withdrawer,
+                    lockup,
+                    checked,
+                }
+            ),
+        (
+            stake_index(),
+            authority_index(),
+            authority_index(),
+            any::<bool>(),
+            proptest::option::of(authority_index()),
+            any::<bool>(),
+        )
+            .prop_map(
+                |(stake, authority, new_authority, withdrawer, custodian, checked)| Op::Authorize {
+                    stake,
+                    authority,
+                    new_authority,
+                    withdrawer,
+                    custodian,
+                    checked,
+                }
+            ),
+        (
+            stake_index(),
+            authority_index(),
+            any::<bool>(),
+            proptest::option::of(authority_index()),
+            any::<bool>(),
+        )
+            .prop_map(|(stake, new_authority, withdrawer, custodian, checked)| {
+                Op::AuthorizeWithSeed {
+                    stake,
+                    new_authority,
+                    withdrawer,
+                    custodian,
+                    checked,
+                }
+            }),
+        (stake_index(), authority_index(), vote_index()).prop_map(|(stake, authority, vote)| {
+            Op::DelegateStake {
+                stake,
+                authority,
+                vote,
+            }
+        }),
+        (stake_index(), authority_index(), stake_index(), amount()).prop_map(
+            |(stake, authority, destination, amount)| Op::Split {
+                stake,
+                authority,
+                destination,
+                amount,
+            }
+        ),
+        (
+            stake_index(),
+            authority_index(),
+            amount(),
+            proptest::option::of(authority_index()),
+        )
+            .prop_map(|(stake, authority, amount, custodian)| Op::Withdraw {
+                stake,
+                authority,
+                amount,
+                custodian,
+            }),
+        (stake_index(), authority_index())
+            .prop_map(|(stake, authority)| Op::Deactivate { stake, authority }),
+        (
+            stake_index(),
+            authority_index(),
+            proptest::option::of(0..4u64),
+            proptest::option::of(authority_index()),
+            any::<bool>(),
+        )
+            .prop_map(|(stake, signer, epoch_offset, custodian, checked)| {
+                Op::SetLockup {
+                    stake,
+                    signer,
+                    epoch_offset,
+                    custodian,
+                    checked,
+                }
+            }),
+        (stake_index(), stake_index(), authority_index()).prop_map(
+            |(destination, source, authority)| Op::Merge {
+                destination,
+                source,
+                authority,
+            }
+        ),
+        Just(Op::GetMinimumDelegation),
+        (stake_index(), vote_index(), vote_index()).prop_map(|(stake, vote, reference_vote)| {
+            Op::DeactivateDelinquent {
+                stake,
+                vote,
+                reference_vote,
+            }
+        }),
+        (stake_index(), stake_index(), authority_index(), amount()).prop_map(
+            |(source, destination, authority, amount)| Op::MoveStake {
+                source,
+                destination,
+                authority,
+                amount,
+            }
+        ),
+        (stake_index(), stake_index(), authority_index(), amount()).prop_map(
+            |(source, destination, authority, amount)| Op::MoveLamports {
+                source,
+                destination,
+                authority,
+                amount,
+            }
+        ),
+        Just(Op::AdvanceEpoch),
+    ]
+}
+
+struct Machine {
+    mollusk: Mollusk,
+    accounts: HashMap<Pubkey, Account>,
+    total_lamports: u64,
+}
+
+impl Machine {
+    fn new(mut mollusk: Mollusk, initial_lamports: &[u64]) -> Self {
+        mollusk.warp_to_slot(
+            mollusk
+                .sysvars
+                .epoch_schedule
+                .get_first_slot_in_epoch(START_EPOCH),
+        );
+        assert_eq!(mollusk.sysvars.clock.epoch, START_EPOCH);
+
+        // backfill stake history
+        let stake_delta_amount =
+            (PERSISTENT_ACTIVE_STAKE as f64 * NEW_WARMUP_COOLDOWN_RATE).floor() as u64;
+        for epoch in 0..START_EPOCH {
+            mollusk.sysvars.stake_history.add(
+                epoch,
+                StakeHistoryEntry {
+                    effective: PERSISTENT_ACTIVE_STAKE,
+                    activating: stake_delta_amount,
+                    deactivating: stake_delta_amount,
+                },
+            );
+        }
+
+        let mut accounts = HashMap::new();
+
+        let stake_rent_exemption = Rent::default().minimum_balance(StakeStateV2::size_of());
+        for (pubkey, lamports) in STAKE_ACCOUNTS.iter().zip(initial_lamports) {
+            let stake_account = Account::create(
+                stake_rent_exemption + lamports,
+                vec![0; StakeStateV2::size_of()],
+                id(),
+                false,
+                u64::MAX,
+            );
+            accounts.insert(*pubkey, stake_account);
+        }
+
+        let vote_rent_exemption = Rent::default().minimum_balance(VoteState::size_of());
+        for (pubkey, last_epoch) in VOTE_ACCOUNTS
+            .iter()
+            .zip([START_EPOCH, DELINQUENT_LAST_EPOCH])
+        {
+            let mut vote_state = VoteState::default();
+            for epoch in 0..=last_epoch {
+                vote_state.increment_credits(epoch, 1);
+            }
+            let vote_account = Account::new_data_with_space(
+                vote_rent_exemption,
+                &VoteStateVersions::new_current(vote_state),
+                VoteState::size_of(),
+                &vote_program::id(),
+            )
+            .unwrap();
+            accounts.insert(*pubkey, vote_account);
+        }
+
+        let sink_account = Account::new_rent_epoch(0, 0, &system_program::id(), u64::MAX);
+        accounts.insert(SINK, sink_account);
+
+        let total_lamports = accounts.values().map(|account| account.lamports).sum();
+
+        Self {
+            mollusk,
+            accounts,
+            total_lamports,
+        }
+    }
+
+    fn lamports(&self, pubkey: &Pubkey) -> u64 {
+        self.accounts
+            .get(pubkey)
+            .map(|account| account.lamports)
+            .unwrap_or_default()
+    }
+
+    fn stake_state(&self, pubkey: &Pubkey) -> StakeStateV2 {
+        bincode::deserialize(self.accounts.get(pubkey).unwrap().data()).unwrap()
+    }
+
+    fn build_instruction(&self, op: &Op) -> Option<Instruction> {
+        let authorities = authorities();
+        let epoch = self.mollusk.sysvars.clock.epoch;
+        let authorize = |withdrawer: bool| {
+            if withdrawer {
+                StakeAuthorize::Withdrawer
+            } else {
+                StakeAuthorize::Staker
+            }
+        };
+
+        let instruction = match *op {
+            Op::Initialize {
+                stake,
+                staker,
+                withdrawer,
+                lockup,
+                checked,
+            } => {
+                let authorized = Authorized {
+                    staker: authorities[staker],
+                    withdrawer: authorities[withdrawer],
+                };
+                if checked {
+                    instruction::initialize_checked(&STAKE_ACCOUNTS[stake], &authorized)
+                } else {
+                    let lockup = lockup
+                        .map(|(epoch_offset, custodian)| Lockup {
+                            unix_timestamp: 0,
+                            epoch: epoch + epoch_offset,
+                            custodian: authorities[custodian],
+                        })
+                        .unwrap_or_default();
+                    instruction::initialize(&STAKE_ACCOUNTS[stake], &authorized, &lockup)
+                }
+            }
+            Op::Authorize {
+                stake,
+                authority,
+                new_authority,
+                withdrawer,
+                custodian,
+                checked,
+            } => {
+                let make_instruction = if checked {
+                    instruction::authorize_checked
+                } else {
+                    instruction::authorize
+                };
+                make_instruction(
+                    &STAKE_ACCOUNTS[stake],
+                    &authorities[authority],
+                    &authorities[new_authority],
+                    authorize(withdrawer),
+                    custodian.map(|custodian| &authorities[custodian]),
+                )
+            }
+            Op::AuthorizeWithSeed {
+                stake,
+                new_authority,
+                withdrawer,
+                custodian,
+                checked,
+            } => {
+                let make_instruction = if checked {
+                    instruction::authorize_checked_with_seed
+                } else {
+                    instruction::authorize_with_seed
+                };
+                make_instruction(
+                    &STAKE_ACCOUNTS[stake],
+                    &SEED_BASE,
+                    SEED.to_string(),
+                    &system_program::id(),
+                    &authorities[new_authority],
+                    authorize(withdrawer),
+                    custodian.map(|custodian| &authorities[custodian]),
+                )
+            }
+            Op::DelegateStake {
+                stake,
+                authority,
+                vote,
+            } => instruction::delegate_stake(
+                &STAKE_ACCOUNTS[stake],
+                &authorities[authority],
+                &VOTE_ACCOUNTS[vote],
+            ),
+            Op::Split {
+                stake,
+                authority,
+                destination,
+                amount,
+            } => instruction::split(
+                &STAKE_ACCOUNTS[stake],
+                &authorities[authority],
+                amount.resolve(self.lamports(&STAKE_ACCOUNTS[stake])),
+                &STAKE_ACCOUNTS[destination],
+            )
+            .remove(2),
+            Op::Withdraw {
+                stake,
+                authority,
+                amount,
+                custodian,
+            } => instruction::withdraw(
+                &STAKE_ACCOUNTS[stake],
+                &authorities[authority],
+                &SINK,
+                amount.resolve(self.lamports(&STAKE_ACCOUNTS[stake])),
+                custodian.map(|custodian| &authorities[custodian]),
+            ),
+            Op::Deactivate { stake, authority } => {
+                instruction::deactivate_stake(&STAKE_ACCOUNTS[stake], &authorities[authority])
+            }
+            Op::SetLockup {
+                stake,
+                signer,
+                epoch_offset,
+                custodian,
+                checked,
+            } => {
+                let lockup_args = LockupArgs {
+                    unix_timestamp: None,
+                    epoch: epoch_offset.map(|epoch_offset| epoch + epoch_offset),
+                    custodian: custodian.map(|custodian| authorities[custodian]),
+                };
+                let make_instruction = if checked {
+                    instruction::set_lockup_checked
+                } else {
+                    instruction::set_lockup
+                };
+                make_instruction(&STAKE_ACCOUNTS[stake], &lockup_args, &authorities[signer])
+            }
+            Op::Merge {
+                destination,
+                source,
+                authority,
+            } => instruction::merge(
+                &STAKE_ACCOUNTS[destination],
+                &STAKE_ACCOUNTS[source],
+                &authorities[authority],
+            )
+            .remove(0),
+            Op::GetMinimumDelegation => instruction::get_minimum_delegation(),
+            Op::DeactivateDelinquent {
+                stake,
+                vote,
+                reference_vote,
+            } => instruction::deactivate_delinquent_stake(
+                &STAKE_ACCOUNTS[stake],
+                &VOTE_ACCOUNTS[vote],
+                &VOTE_ACCOUNTS[reference_vote],
+            ),
+            Op::MoveStake {
+                source,
+                destination,
+                authority,
+                amount,
+            } => instruction::move_stake(
+                &STAKE_ACCOUNTS[source],
+                &STAKE_ACCOUNTS[destination],
+                &authorities[authority],
+                amount.resolve(self.lamports(&STAKE_ACCOUNTS[source])),
+            ),
+            Op::MoveLamports {
+                source,
+                destination,
+                authority,
+                amount,
+            } => instruction::move_lamports(
+                &STAKE_ACCOUNTS[source],
+                &STAKE_ACCOUNTS[destination],
+                &authorities[authority],
+                amount.resolve(self.lamports(&STAKE_ACCOUNTS[source])),
+            ),
+            Op::AdvanceEpoch => return None,
+        };
+
+        Some(instruction)
+    }
+
+    // custodian that must sign for `op` to succeed, if it touches a lockup in force
+    fn required_custodian(&self, op: &Op) -> Option<Pubkey> {
+        let stake = match *op {
+            Op::Withdraw { stake, .. } | Op::SetLockup { stake, .. } => stake,
+            Op::Authorize {
+                stake,
+                withdrawer: true,
+                ..
+            }
+            | Op::AuthorizeWithSeed {
+                stake,
+                withdrawer: true,
+                ..
+            } => stake,
+            _ => return None,
+        };
+
+        match self.stake_state(&STAKE_ACCOUNTS[stake]) {
+            StakeStateV2::Initialized(meta) | StakeStateV2::Stake(meta, _, _)
+                if meta.lockup.is_in_force(&self.mollusk.sysvars.clock, None) =>
+            {
+                Some(meta.lockup.custodian)
+            }
+            _ => None,
+        }
+    }
+
+    fn lockup_in_force(&self, pubkey: &Pubkey) -> Option<Lockup> {
+        match self.stake_state(pubkey) {
+            StakeStateV2::Initialized(meta) | StakeStateV2::Stake(meta, _, _)
+                if meta.lockup.is_in_force(&self.mollusk.sysvars.clock, None) =>
+            {
+                Some(meta.lockup)
+            }
+            _ => None,
+        }
+    }
+
+    // lamports leaving a stake account under a lockup in force may only land in
+    // stake accounts whose lockup is at least as strong, by `Split`, `Merge`,
+    // `MoveStake` and `MoveLamports` alike
+    fn check_lockups_not_weakened(
+        &self,
+        op: &Op,
+        lamports_before: &[u64],
+        lockups_before: &[Option<Lockup>],
+    ) {
+        for ((source, source_lamports), lockup) in STAKE_ACCOUNTS
+            .iter()
+            .zip(lamports_before)
+            .zip(lockups_before)
+        {
+            let Some(lockup) = lockup else {
+                continue;
+            };
+            if self.lamports(source) >= *source_lamports {
+                continue;
+            }
+
+            for (destination, destination_lamports) in STAKE_ACCOUNTS.iter().zip(lamports_before) {
+                if self.lamports(destination) <= *destination_lamports {
+                    continue;
+                }
+                let destination_lockup = self.lockup_in_force(destination);
+                assert!(
+                    destination_lockup.is_some_and(|destination_lockup| {
+                        destination_lockup.custodian == lockup.custodian
+                            && destination_lockup.epoch >= lockup.epoch
+                            && destination_lockup.unix_timestamp >= lockup.unix_timestamp
+                    }),
+                    "lamports of {source} left its lockup for {destination} by {op:?}",
+                );
+            }
+        }
+    }
+
+    // the accounts this instruction expects to see, sysvars come from mollusk
+    fn resolve_accounts(&self, instruction: &Instruction) -> Vec<(Pubkey, Account)> {
+        let mut accounts: Vec<(Pubkey, Account)> = vec![];
+        for account_meta in &instruction.accounts {
+            let key = account_meta.pubkey;
+            if accounts.iter().any(|(pubkey, _)| *pubkey == key) {
+                continue;
+            }
+
+            let account = if Rent::check_id(&key) {
+                self.mollusk.sysvars.keyed_account_for_rent_sysvar().1
+            } else if Clock::check_id(&key) {
+                self.mollusk.sysvars.keyed_account_for_clock_sysvar().1
+            } else if EpochSchedule::check_id(&key) {
+                self.mollusk
+                    .sysvars
+                    .keyed_account_for_epoch_schedule_sysvar()
+                    .1
+            } else if EpochRewards::check_id(&key) {
+                self.mollusk
+                    .sysvars
+                    .keyed_account_for_epoch_rewards_sysvar()
+                    .1
+            } else if StakeHistory::check_id(&key) {
+                self.mollusk
+                    .sysvars
+                    .keyed_account_for_stake_history_sysvar()
+                    .1
+            } else {
+                self.accounts.get(&key).cloned().unwrap_or_default()
+            };
+
+            accounts.push((key, account));
+        }
+
+        accounts
+    }
+
+    fn step(&mut self, op: &Op) {
+        let Some(instruction) = self.build_instruction(op) else {
+            self.advance_epoch();
+            return;
+        };
+
+        let required_custodian = self.required_custodian(op);
+        let lamports_before = STAKE_ACCOUNTS.map(|pubkey| self.lamports(&pubkey));
+        let lockups_before = STAKE_ACCOUNTS.map(|pubkey| self.lockup_in_force(&pubkey));
+        let accounts = self.resolve_accounts(&instruction);
+        let result = self.mollusk.process_instruction(&instruction, &accounts);
+        if result.program_result.is_err() {
+            return;
+        }
+
+        if let Some(custodian) = required_custodian {
+            assert!(
+                instruction
+                    .accounts
+                    .iter()
+                    .any(|meta| meta.is_signer && meta.pubkey == custodian),
+                "lockup bypassed without the custodian by {op:?}",
+            );
+        }
+
+        for (pubkey, account) in result.resulting_accounts {
+            if account.owner != sysvar::id() {
+                self.accounts.insert(pubkey, account);
+            }
+        }
+
+        self.check_lockups_not_weakened(op, &lamports_before, &lockups_before);
+    }
+
+    // record the ending epoch in stake history the way the bank does, then move
+    // to the first slot of the next epoch
+    fn advance_epoch(&mut self) {
+        let epoch = self.mollusk.sysvars.clock.epoch;
+        let stake_delta_amount =
+            (PERSISTENT_ACTIVE_STAKE as f64 * NEW_WARMUP_COOLDOWN_RATE).floor() as u64;
+        let mut entry = StakeHistoryEntry {
+            effective: PERSISTENT_ACTIVE_STAKE,
+            activating: stake_delta_amount,
+            deactivating: stake_delta_amount,
+        };
+
+        for pubkey in &STAKE_ACCOUNTS {
+            if let StakeStateV2::Stake(_, stake, _) = self.stake_state(pubkey) {
+                let status = stake.delegation.stake_activating_and_deactivating(
+                    epoch,
+                    &self.mollusk.sysvars.stake_history,
+                    Some(0),
+                );
+                entry.effective += status.effective;
+                entry.activating += status.activating;
+                entry.deactivating += status.deactivating;
+            }
+        }
+
+        self.mollusk.sysvars.stake_history.add(epoch, entry);
+        let next_slot = self
+            .mollusk
+            .sysvars
+            .epoch_schedule
+            .get_first_slot_in_epoch(epoch + 1);
+        self.mollusk.warp_to_slot(next_slot);
+
+        // the reference vote account votes in the new epoch
+        let vote_account = self.accounts.get_mut(&VOTE_ACCOUNTS[0]).unwrap();
+        let mut vote_state = vote_account
+            .deserialize_data::<VoteStateVersions>()
+            .unwrap()
+            .convert_to_current();
+        vote_state.increment_credits(epoch + 1, 1);
+        vote_account
+            .serialize_data(&VoteStateVersions::new_current(vote_state))
+            .unwrap();
+    }
+
+    fn check_invariants(&self) {
+        let total_lamports: u64 = self.accounts.values().map(|account| account.lamports).sum();
+        assert_eq!(
+            total_lamports, self.total_lamports,
+            "lamports not conserved"
+        );
+
+        let clock = &self.mollusk.sysvars.clock;
+        for pubkey in STAKE_ACCOUNTS {
+            let account = self.accounts.get(&pubkey).unwrap();
+            assert_eq!(account.owner, id());
+            assert_eq!(account.data.len(), StakeStateV2::size_of());
+
+            let stake_state: StakeStateV2 = bincode::deserialize(&account.data)
+                .unwrap_or_else(|_| panic!("invalid stake state in {pubkey}"));
+
+            match stake_state {
+                StakeStateV2::Initialized(meta) => {
+                    assert!(account.lamports >= meta.rent_exempt_reserve);
+                }
+                StakeStateV2::Stake(meta, stake, _) => {
+                    let status = stake.delegation.stake_activating_and_deactivating(
+                        clock.epoch,
+                        &self.mollusk.sysvars.stake_history,
+                        Some(0),
+                    );
+                    let backing_lamports =
+                        account.lamports.saturating_sub(meta.rent_exempt_reserve);
+                    assert!(status.effective <= stake.delegation.stake);
+                    assert!(
+                        status.effective <= backing_lamports,
+                        "effective stake of {pubkey} not backed by lamports",
+                    );
+
+                    // cooling down stake can be partially withdrawn, the rest cannot
+                    if stake.delegation.deactivation_epoch == u64::MAX {
+                        assert!(
+                            stake.delegation.stake <= backing_lamports,
+                            "delegated stake of {pubkey} not backed by lamports",
+                        );
+                    }
+                }
+                StakeStateV2::Uninitialized | StakeStateV2::RewardsPool => (),
+            }
+        }
+    }
+}
+
+fn run(mollusk: Mollusk, initial_lamports: &[u64], ops: &[Op]) {
+    let mut machine = Machine::new(mollusk, initial_lamports);
+    machine.check_invariants();
+
+    for op in ops {
+        machine.step(op);
+        machine.check_invariants();
+    }
+}
+
+// the vote accounts above let stake delegated to the second one be deactivated
+// as delinquent, so the state machine can reach `DeactivateDelinquent`
+#[test_case(mollusk_native(); "native_stake")]
+#[test_case(mollusk_bpf(); "bpf_stake")]
+fn delinquent_stake_can_be_deactivated(mollusk: Mollusk) {
+    let mut machine = Machine::new(mollusk, &[LAMPORTS_PER_SOL; STAKE_ACCOUNTS.len()]);
+    for op in [
+        Op::Initialize {
+            stake: 0,
+            staker: 0,
+            withdrawer: 0,
+            lockup: None,
+            checked: false,
+        },
+        Op::DelegateStake {
+            stake: 0,
+            authority: 0,
+            vote: 1,
+        },
+        Op::AdvanceEpoch,
+        Op::DeactivateDelinquent {
+            stake: 0,
+            vote: 1,
+            reference_vote: 0,
+        },
+    ] {
+        machine.step(&op);
+        machine.check_invariants();
+    }
+
+    let StakeStateV2::Stake(_, stake, _) = machine.stake_state(&STAKE_ACCOUNTS[0]) else {
+        panic!("stake account not delegated");
+    };
+    assert_eq!(
+        stake.delegation.deactivation_epoch,
+        machine.mollusk.sysvars.clock.epoch
+    );
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn instruction_sequences_keep_invariants(
+        initial_lamports in vec(0..=MAX_INITIAL_LAMPORTS, STAKE_ACCOUNTS.len()),
+        ops in vec(op(), 1..64),
+    ) {
+        run(mollusk_bpf(), &initial_lamports, &ops);
+    }
+
+    #[test]
+    fn native_instruction_sequences_keep_invariants(
+        initial_lamports in vec(0..=MAX_INITIAL_LAMPORTS, STAKE_ACCOUNTS.len()),
+        ops in vec(op(), 1..64),
+    ) {
+        run(mollusk_native(), &initial_lamports, &ops);
+    }
+}
@@ -0,0 +1,174 @@
+#![cfg(feature = "set-lockup-with-seed")]
+#![allow(clippy::arithmetic_side_effects)]
+
+// `SetLockupWithSeed` against the built program, which must be built with the
+// feature: `cargo build-sbf --features set-lockup-with-seed`.
+
+use {
+    mollusk_svm::{result::Check, Mollusk},
+    p_stake::id as p_stake_id,
+    solana_account::Account,
+    solana_sdk::{
+        instruction::{AccountMeta, Instruction},
+        program_error::ProgramError,
+        pubkey::Pubkey,
+        stake::{
+            instruction::LockupArgs,
+            state::{Authorized, Lockup, Meta, StakeStateV2},
+        },
+        sysvar::rent::Rent,
+    },
+};
+
+const SEED: &str = "seed";
+
+fn id() -> Pubkey {
+    p_stake_id().into()
+}
+
+fn set_lockup_with_seed(
+    stake: &Pubkey,
+    base: &Pubkey,
+    seed: &str,
+    owner: &Pubkey,
+    lockup_args: &LockupArgs,
+) -> Instruction {
+    // discriminant, seed length and seed, owner, then the `SetLockup` data
+    let mut data = 22u32.to_le_bytes().to_vec();
+    data.extend_from_slice(&(seed.len() as u64).to_le_bytes());
+    data.extend_from_slice(seed.as_bytes());
+    data.extend_from_slice(owner.as_ref());
+    data.extend_from_slice(&bincode::serialize(lockup_args).unwrap());
+
+    Instruction {
+        program_id: id(),
+        accounts: vec![
+            AccountMeta::new(*stake, false),
+            AccountMeta::new_readonly(*base, true),
+        ],
+        data,
+    }
+}
+
+/// Sets a lockup on an account with `lockup`, signed by `base` with `seed`,
+/// and returns the resulting lockup
+fn process(
+    authorized: Authorized,
+    lockup: Lockup,
+    base: &Pubkey,
+    seed: &str,
+    owner: &Pubkey,
+    check: Check,
+) -> Lockup {
+    let mollusk = Mollusk::new(&id(), "target/deploy/p_stake");
+    let stake = Pubkey::new_unique();
+    let rent_exempt_reserve = Rent::default().minimum_balance(StakeStateV2::size_of());
+    let stake_account = Account::new_data_with_space(
+        rent_exempt_reserve,
+        &StakeStateV2::Initialized(Meta {
+            rent_exempt_reserve,
+            authorized,
+            lockup,
+        }),
+        StakeStateV2::size_of(),
+        &id(),
+    )
+    .unwrap();
+    let lockup_args = LockupArgs {
+        unix_timestamp: None,
+        epoch: Some(u64::MAX),
+        custodian: None,
+    };
+
+    let result = mollusk.process_and_validate_instruction(
+        &set_lockup_with_seed(&stake, base, seed, owner, &lockup_args),
+        &[(stake, stake_account), (*base, Account::default())],
+        &[check],
+    );
+
+    match bincode::deserialize(&result.get_account(&stake).unwrap().data).unwrap() {
+        StakeStateV2::Initialized(meta) => meta.lockup,
+        stake_state => panic!("unexpected stake state {stake_state:?}"),
+    }
+}
+
+#[test]
+fn test_set_lockup_with_seed_withdrawer() {
+    let base = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let withdrawer = Pubkey::create_with_seed(&base, SEED, &owner).unwrap();
+    let authorized = Authorized {
+        staker: Pubkey::new_unique(),
+        withdrawer,
+    };
+
+    // without a lockup in force, the derived withdrawer sets the lockup
+    let lockup = process(
+        authorized,
+        Lockup::default(),
+        &base,
+        SEED,
+        &owner,
+        Check::success(),
+    );
+    assert_eq!(lockup.epoch, u64::MAX);
+}
+
+#[test]
+fn test_set_lockup_with_seed_custodian() {
+    let base = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let custodian = Pubkey::create_with_seed(&base, SEED, &owner).unwrap();
+    let lockup = Lockup {
+        unix_timestamp: 0,
+        epoch: 1_000,
+        custodian,
+    };
+
+    // with a lockup in force, the derived custodian changes it
+    let new_lockup = process(
+        Authorized::auto(&Pubkey::new_unique()),
+        lockup,
+        &base,
+        SEED,
+        &owner,
+        Check::success(),
+    );
+    assert_eq!(
+        new_lockup,
+        Lockup {
+            epoch: u64::MAX,
+            ..lockup
+        }
+    );
+}
+
+#[test]
+fn test_set_lockup_with_seed_wrong_seed() {
+    let base = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let withdrawer = Pubkey::create_with_seed(&base, SEED, &owner).unwrap();
+    let authorized = Authorized {
+        staker: Pubkey::new_unique(),
+        withdrawer,
+    };
+
+    // another seed, or another owner, derives another address
+    let lockup = process(
+        authorized,
+        Lockup::default(),
+        &base,
+        "other seed",
+        &owner,
+        Check::err(ProgramError::MissingRequiredSignature),
+    );
+    assert_eq!(lockup, Lockup::default());
+    process(
+        authorized,
+        Lockup::default(),
+        &base,
+        SEED,
+        &Pubkey::new_unique(),
+        Check::err(ProgramError::MissingRequiredSignature),
+    );
+}
@@ -0,0 +1,110 @@
+#![cfg(feature = "withdraw-max")]
+#![allow(clippy::arithmetic_side_effects)]
+
+// `Withdraw` of `u64::MAX` lamports against the built program, which must be
+// built with the feature: `cargo build-sbf --features withdraw-max`.
+
+use {
+    mollusk_svm::{result::Check, Mollusk},
+    p_stake::id as p_stake_id,
+    solana_account::Account,
+    solana_sdk::{
+        pubkey::Pubkey,
+        stake::{
+            instruction,
+            stake_flags::StakeFlags,
+            state::{Authorized, Delegation, Lockup, Meta, Stake, StakeStateV2},
+        },
+        sysvar::rent::Rent,
+    },
+};
+
+fn id() -> Pubkey {
+    p_stake_id().into()
+}
+
+fn stake_account(stake_state: &StakeStateV2, lamports: u64) -> Account {
+    Account::new_data_with_space(lamports, stake_state, StakeStateV2::size_of(), &id()).unwrap()
+}
+
+fn meta(withdrawer: &Pubkey) -> Meta {
+    Meta {
+        rent_exempt_reserve: Rent::default().minimum_balance(StakeStateV2::size_of()),
+        authorized: Authorized::auto(withdrawer),
+        lockup: Lockup::default(),
+    }
+}
+
+fn withdraw_max(
+    mollusk: &Mollusk,
+    stake_account: Account,
+    withdrawer: &Pubkey,
+    withdrawn_lamports: u64,
+) {
+    let stake_address = Pubkey::new_unique();
+    let recipient_address = Pubkey::new_unique();
+    let instruction = instruction::withdraw(
+        &stake_address,
+        withdrawer,
+        &recipient_address,
+        u64::MAX,
+        None,
+    );
+
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &[
+            (stake_address, stake_account),
+            (recipient_address, Account::default()),
+            mollusk.sysvars.keyed_account_for_clock_sysvar(),
+            mollusk.sysvars.keyed_account_for_stake_history_sysvar(),
+            (*withdrawer, Account::default()),
+        ],
+        &[
+            Check::success(),
+            Check::return_data(&withdrawn_lamports.to_le_bytes()),
+            Check::account(&recipient_address)
+                .lamports(withdrawn_lamports)
+                .build(),
+        ],
+    );
+}
+
+#[test]
+fn test_withdraw_max_closes_initialized_account() {
+    let mollusk = Mollusk::new(&id(), "target/deploy/p_stake");
+    let withdrawer = Pubkey::new_unique();
+    let meta = meta(&withdrawer);
+    let lamports = meta.rent_exempt_reserve + 1_000;
+
+    withdraw_max(
+        &mollusk,
+        stake_account(&StakeStateV2::Initialized(meta), lamports),
+        &withdrawer,
+        lamports,
+    );
+}
+
+#[test]
+fn test_withdraw_max_keeps_reserve_and_stake() {
+    let mollusk = Mollusk::new(&id(), "target/deploy/p_stake");
+    let withdrawer = Pubkey::new_unique();
+    let meta = meta(&withdrawer);
+    let staked = 5_000_000_000;
+    let stake_state = StakeStateV2::Stake(
+        meta,
+        Stake {
+            delegation: Delegation::new(&Pubkey::new_unique(), staked, 0),
+            credits_observed: 0,
+        },
+        StakeFlags::empty(),
+    );
+    let lamports = meta.rent_exempt_reserve + staked + 1_000;
+
+    withdraw_max(
+        &mollusk,
+        stake_account(&stake_state, lamports),
+        &withdrawer,
+        1_000,
+    );
+}